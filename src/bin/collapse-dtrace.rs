@@ -3,7 +3,10 @@ use std::path::PathBuf;
 
 use clap::{ArgAction, Parser};
 use env_logger::Env;
-use inferno::collapse::dtrace::{Folder, Options};
+use inferno::cli::{CollapseArgs, DemangleArgs, StreamArgs};
+use inferno::collapse::dtrace::{Folder, Options, TimeUnit};
+use inferno::collapse::rewrite;
+use inferno::collapse::stream;
 use inferno::collapse::{Collapse, DEFAULT_NTHREADS};
 use once_cell::sync::Lazy;
 
@@ -18,6 +21,11 @@ static NTHREADS: Lazy<String> = Lazy::new(|| DEFAULT_NTHREADS.to_string());
         dtrace -x ustackframes=100 -n 'profile-97 /pid == 12345 && arg1/ { @[ustack()] = count(); } tick-60s { exit(0); }'
     or including kernel time:
         dtrace -x ustackframes=100 -n 'profile-97 /pid == 12345/ { @[ustack()] = count(); } tick-60s { exit(0); }'
+[2] Off-CPU scripts sum up blocked time in nanoseconds, for example:
+        dtrace -x ustackframes=100 -n 'sched:::off-cpu /pid == 12345/ { self->ts = timestamp; }
+            sched:::on-cpu /self->ts/ { @offcpu[ustack()] = sum(timestamp - self->ts); self->ts = 0; }
+            tick-60s { printf(\"\\n@offcpu\\n\"); printa(@offcpu); exit(0); }'
+    Collapse such output with --time-unit, and pass the same unit to flamegraph --countname.
     "
 )]
 struct Opt {
@@ -28,6 +36,10 @@ struct Opt {
     #[clap(long = "includeoffset")]
     includeoffset: bool,
 
+    /// Fail on malformed or empty input, rather than skip what can't be parsed with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
     /// Silence all log output
     #[clap(short = 'q', long = "quiet")]
     quiet: bool,
@@ -48,6 +60,24 @@ struct Opt {
     )]
     nthreads: usize,
 
+    /// Treat aggregation values as durations in nanoseconds and rescale them to this unit [2]
+    #[clap(long = "time-unit", value_parser = ["ns", "us", "ms"], value_name = "UNIT")]
+    time_unit: Option<String>,
+
+    /// Only collapse the stacks of the aggregation with this name, printed on a line of its own
+    /// (like `@offcpu`) before the aggregation [2]
+    #[clap(long = "aggregation", value_parser = parse_aggregation, value_name = "NAME")]
+    aggregation: Option<String>,
+
+    #[clap(flatten)]
+    demangle: DemangleArgs,
+
+    #[clap(flatten)]
+    collapse: CollapseArgs,
+
+    #[clap(flatten)]
+    stream: StreamArgs,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -59,12 +89,29 @@ struct Opt {
 impl Opt {
     fn into_parts(self) -> (Option<PathBuf>, Options) {
         let mut options = Options::default();
+        options.strict = self.strict;
         options.includeoffset = self.includeoffset;
         options.nthreads = self.nthreads;
+        options.duration_unit = self.time_unit.map(|unit| match &*unit {
+            "us" => TimeUnit::Microseconds,
+            "ms" => TimeUnit::Milliseconds,
+            _ => TimeUnit::Nanoseconds,
+        });
+        options.aggregation = self.aggregation;
+        options.demangle = self.demangle.demangle();
         (self.infile, options)
     }
 }
 
+fn parse_aggregation(s: &str) -> Result<String, String> {
+    let name = s.strip_prefix('@').unwrap_or(s);
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Ok(name.to_string())
+    } else {
+        Err(format!("`{}` is not an aggregation name", s))
+    }
+}
+
 fn main() -> io::Result<()> {
     let opt = Opt::parse();
 
@@ -80,6 +127,23 @@ fn main() -> io::Result<()> {
         .init();
     }
 
+    let rules = opt.collapse.rewrite_rules()?;
+    let stream_options = opt.stream.options();
+    let collapse = opt.collapse.clone();
     let (infile, options) = opt.into_parts();
-    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+    match stream_options {
+        Some(mut stream_options) => {
+            stream_options.rewrite_rules = rules;
+            let mut folder = stream::Folder::new(Folder::from(options), stream_options);
+            let result = folder.collapse_file_to_stdout(infile.as_ref());
+            collapse.print_stats(folder.stats());
+            result
+        }
+        None => {
+            let mut folder = rewrite::Folder::new(Folder::from(options), rules);
+            let result = folder.collapse_file_to_stdout(infile.as_ref());
+            collapse.print_stats(folder.stats());
+            result
+        }
+    }
 }
@@ -3,7 +3,9 @@ use std::path::PathBuf;
 
 use clap::{ArgAction, ArgGroup, Parser};
 use env_logger::Env;
+use inferno::cli::CollapseArgs;
 use inferno::collapse::ghcprof::{Folder, Options, Source};
+use inferno::collapse::rewrite;
 use inferno::collapse::Collapse;
 
 #[derive(Debug, Parser)]
@@ -34,6 +36,10 @@ struct Opt {
     #[clap(long = "ticks")]
     ticks: bool,
 
+    /// Fail on malformed or empty input, rather than skip what can't be parsed with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
     /// Silence all log output
     #[clap(short = 'q', long = "quiet")]
     quiet: bool,
@@ -42,6 +48,12 @@ struct Opt {
     #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
     verbose: u8,
 
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    #[clap(flatten)]
+    collapse: CollapseArgs,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -53,6 +65,7 @@ struct Opt {
 impl Opt {
     fn into_parts(self) -> (Option<PathBuf>, Options) {
         let mut options = Options::default();
+        options.strict = self.strict;
         options.source = if self.ticks {
             Source::Ticks
         } else if self.bytes {
@@ -79,6 +92,11 @@ fn main() -> io::Result<()> {
         .init();
     }
 
+    let collapse = opt.collapse.clone();
+    let rules = opt.collapse.rewrite_rules()?;
     let (infile, options) = opt.into_parts();
-    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+    let mut folder = rewrite::Folder::new(Folder::from(options), rules);
+    let result = folder.collapse_file_to_stdout(infile.as_ref());
+    collapse.print_stats(folder.stats());
+    result
 }
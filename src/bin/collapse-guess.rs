@@ -3,7 +3,9 @@ use std::path::PathBuf;
 
 use clap::{ArgAction, Parser};
 use env_logger::Env;
+use inferno::cli::{CollapseArgs, DemangleArgs};
 use inferno::collapse::guess::{Folder, Options};
+use inferno::collapse::rewrite;
 use inferno::collapse::{Collapse, DEFAULT_NTHREADS};
 use once_cell::sync::Lazy;
 
@@ -21,6 +23,14 @@ struct Opt {
     // ************* //
     // *** FLAGS *** //
     // ************* //
+    /// Fail on input of an unknown format, or malformed or empty input, rather than skip what can't be parsed with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
+    /// Print how well the input matched each format to STDERR, from best to worst
+    #[clap(long = "explain")]
+    explain: bool,
+
     /// Silence all log output
     #[clap(short = 'q', long = "quiet")]
     quiet: bool,
@@ -41,6 +51,12 @@ struct Opt {
     )]
     nthreads: usize,
 
+    #[clap(flatten)]
+    demangle: DemangleArgs,
+
+    #[clap(flatten)]
+    collapse: CollapseArgs,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -52,7 +68,9 @@ struct Opt {
 impl Opt {
     fn into_parts(self) -> (Option<PathBuf>, Options) {
         let mut options = Options::default();
+        options.strict = self.strict;
         options.nthreads = self.nthreads;
+        options.demangle = self.demangle.demangle();
         (self.infile, options)
     }
 }
@@ -72,6 +90,20 @@ fn main() -> io::Result<()> {
         .init();
     }
 
+    let rules = opt.collapse.rewrite_rules()?;
+    let explain = opt.explain;
+    let collapse = opt.collapse.clone();
     let (infile, options) = opt.into_parts();
-    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+    let mut folder = rewrite::Folder::new(Folder::from(options), rules);
+    let result = folder.collapse_file_to_stdout(infile.as_ref());
+    collapse.print_stats(folder.stats());
+    if explain {
+        for candidate in folder.into_inner().candidates() {
+            eprintln!(
+                "{:<10} {:.2}  {}",
+                candidate.format, candidate.confidence, candidate.reason
+            );
+        }
+    }
+    result
 }
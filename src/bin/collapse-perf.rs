@@ -1,9 +1,12 @@
 use std::io;
 use std::path::PathBuf;
 
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, ArgGroup, Parser};
 use env_logger::Env;
-use inferno::collapse::perf::{Folder, Options};
+use inferno::cli::{CollapseArgs, DemangleArgs, StreamArgs};
+use inferno::collapse::perf::{Folder, InlineFrames, Options, SrcLine};
+use inferno::collapse::rewrite;
+use inferno::collapse::stream;
 use inferno::collapse::{Collapse, DEFAULT_NTHREADS};
 use once_cell::sync::Lazy;
 
@@ -20,6 +23,11 @@ static NTHREADS: Lazy<String> = Lazy::new(|| DEFAULT_NTHREADS.to_string());
         perf script -F comm,pid,tid,cpu,time,event,ip,sym,dso,trace
     If you save this output add --header on Linux >= 3.14 to include perf info."
 )]
+#[command(group(
+    ArgGroup::new("srclines")
+        .required(false)
+        .args(["srcline", "srcline_leaf"]),
+))]
 struct Opt {
     // ************* //
     // *** FLAGS *** //
@@ -48,6 +56,19 @@ struct Opt {
     #[clap(long = "tid")]
     tid: bool,
 
+    /// Annotate functions with their source location (requires perf script -F +srcline)
+    #[clap(long = "srcline")]
+    srcline: bool,
+
+    /// Add the source location of the innermost function as a leaf frame
+    /// (requires perf script -F +srcline)
+    #[clap(long = "srcline-leaf")]
+    srcline_leaf: bool,
+
+    /// Fail on malformed or empty input, rather than skip what can't be parsed with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
     /// Silence all log output
     #[clap(short = 'q', long = "quiet")]
     quiet: bool,
@@ -63,6 +84,16 @@ struct Opt {
     #[clap(long = "event-filter", value_name = "STRING")]
     event_filter: Option<String>,
 
+    /// How to handle inlined functions: keep them, annotate them with `_[i]`, drop them, or
+    /// merge them into their caller
+    #[clap(
+        long = "inline",
+        default_value = "keep",
+        value_parser = ["keep", "annotate", "drop", "merge"],
+        value_name = "STRING"
+    )]
+    inline: String,
+
     /// Number of threads to use
     #[clap(
         short = 'n',
@@ -72,6 +103,15 @@ struct Opt {
     )]
     nthreads: usize,
 
+    #[clap(flatten)]
+    demangle: DemangleArgs,
+
+    #[clap(flatten)]
+    collapse: CollapseArgs,
+
+    #[clap(flatten)]
+    stream: StreamArgs,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -89,6 +129,7 @@ struct Opt {
 impl Opt {
     fn into_parts(self) -> (Option<PathBuf>, Options) {
         let mut options = Options::default();
+        options.strict = self.strict;
         options.include_pid = self.pid;
         options.include_tid = self.tid;
         options.include_addrs = self.addrs;
@@ -97,6 +138,20 @@ impl Opt {
         options.event_filter = self.event_filter;
         options.nthreads = self.nthreads;
         options.skip_after = self.skip_after;
+        options.srcline = if self.srcline {
+            SrcLine::Annotate
+        } else if self.srcline_leaf {
+            SrcLine::Leaf
+        } else {
+            SrcLine::Ignore
+        };
+        options.inline_frames = match &*self.inline {
+            "annotate" => InlineFrames::Annotate,
+            "drop" => InlineFrames::Drop,
+            "merge" => InlineFrames::Merge,
+            _ => InlineFrames::Keep,
+        };
+        options.demangle = self.demangle.demangle();
         (self.infile, options)
     }
 }
@@ -116,6 +171,23 @@ fn main() -> io::Result<()> {
         .init();
     }
 
+    let rules = opt.collapse.rewrite_rules()?;
+    let stream_options = opt.stream.options();
+    let collapse = opt.collapse.clone();
     let (infile, options) = opt.into_parts();
-    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+    match stream_options {
+        Some(mut stream_options) => {
+            stream_options.rewrite_rules = rules;
+            let mut folder = stream::Folder::new(Folder::from(options), stream_options);
+            let result = folder.collapse_file_to_stdout(infile.as_ref());
+            collapse.print_stats(folder.stats());
+            result
+        }
+        None => {
+            let mut folder = rewrite::Folder::new(Folder::from(options), rules);
+            let result = folder.collapse_file_to_stdout(infile.as_ref());
+            collapse.print_stats(folder.stats());
+            result
+        }
+    }
 }
@@ -1,8 +1,11 @@
 use std::io;
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::cli::{CollapseArgs, DemangleArgs};
 use inferno::collapse::recursive::{Folder, Options};
+use inferno::collapse::rewrite;
 use inferno::collapse::{Collapse, DEFAULT_NTHREADS};
 use once_cell::sync::Lazy;
 
@@ -11,6 +14,19 @@ static NTHREADS: Lazy<String> = Lazy::new(|| DEFAULT_NTHREADS.to_string());
 #[derive(Debug, Parser)]
 #[clap(name = "inferno-collapse-recursive", about)]
 struct Opt {
+    /// Report the line number of a line that is not a stack followed by a count, and fail on
+    /// empty input
+    #[clap(long = "strict")]
+    strict: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
     /// Number of threads to use
     #[clap(
         short = 'n',
@@ -20,6 +36,21 @@ struct Opt {
     )]
     nthreads: usize,
 
+    /// Fold cycles of up to this many frames that repeat right after themselves,
+    /// like `parse;eval;parse;eval`
+    #[clap(long = "max-cycle-len", default_value = "1", value_name = "UINT")]
+    max_cycle_len: usize,
+
+    /// Drop every frame that already appears earlier in the stack
+    #[clap(long = "first-occurrence-only")]
+    first_occurrence_only: bool,
+
+    #[clap(flatten)]
+    demangle: DemangleArgs,
+
+    #[clap(flatten)]
+    collapse: CollapseArgs,
+
     #[clap(value_name = "PATH")]
     /// Collapse output file, or STDIN if not specified
     infile: Option<PathBuf>,
@@ -28,13 +59,35 @@ struct Opt {
 impl Opt {
     fn into_parts(self) -> (Option<PathBuf>, Options) {
         let mut options = Options::default();
+        options.strict = self.strict;
         options.nthreads = self.nthreads;
+        options.max_cycle_len = self.max_cycle_len;
+        options.first_occurrence_only = self.first_occurrence_only;
+        options.demangle = self.demangle.demangle();
         (self.infile, options)
     }
 }
 
 fn main() -> io::Result<()> {
     let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let collapse = opt.collapse.clone();
+    let rules = opt.collapse.rewrite_rules()?;
     let (infile, options) = opt.into_parts();
-    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+    let mut folder = rewrite::Folder::new(Folder::from(options), rules);
+    let result = folder.collapse_file_to_stdout(infile.as_ref());
+    collapse.print_stats(folder.stats());
+    result
 }
@@ -3,6 +3,8 @@ use std::path::PathBuf;
 
 use clap::{ArgAction, Parser};
 use env_logger::Env;
+use inferno::cli::{CollapseArgs, DemangleArgs};
+use inferno::collapse::rewrite;
 use inferno::collapse::sample::{Folder, Options};
 use inferno::collapse::Collapse;
 
@@ -22,6 +24,10 @@ struct Opt {
     #[clap(long = "no-modules")]
     no_modules: bool,
 
+    /// Fail on malformed or empty input, rather than skip what can't be parsed with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
     /// Silence all log output
     #[clap(short = 'q', long = "quiet")]
     quiet: bool,
@@ -30,6 +36,15 @@ struct Opt {
     #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
     verbose: u8,
 
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    #[clap(flatten)]
+    demangle: DemangleArgs,
+
+    #[clap(flatten)]
+    collapse: CollapseArgs,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -41,7 +56,9 @@ struct Opt {
 impl Opt {
     fn into_parts(self) -> (Option<PathBuf>, Options) {
         let mut options = Options::default();
+        options.strict = self.strict;
         options.no_modules = self.no_modules;
+        options.demangle = self.demangle.demangle();
         (self.infile, options)
     }
 }
@@ -61,6 +78,11 @@ fn main() -> io::Result<()> {
         .init();
     }
 
+    let collapse = opt.collapse.clone();
+    let rules = opt.collapse.rewrite_rules()?;
     let (infile, options) = opt.into_parts();
-    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+    let mut folder = rewrite::Folder::new(Folder::from(options), rules);
+    let result = folder.collapse_file_to_stdout(infile.as_ref());
+    collapse.print_stats(folder.stats());
+    result
 }
@@ -3,7 +3,9 @@ use std::path::PathBuf;
 
 use clap::{ArgAction, Parser};
 use env_logger::Env;
-use inferno::collapse::vsprof::Folder;
+use inferno::cli::{CollapseArgs, DemangleArgs};
+use inferno::collapse::rewrite;
+use inferno::collapse::vsprof::{Folder, Options};
 use inferno::collapse::Collapse;
 
 #[derive(Debug, Parser)]
@@ -17,6 +19,10 @@ struct Opt {
     // ************* //
     // *** FLAGS *** //
     // ************* //
+    /// Fail on malformed or empty input, rather than skip what can't be parsed with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
     /// Silence all log output
     #[clap(short = 'q', long = "quiet")]
     quiet: bool,
@@ -25,6 +31,15 @@ struct Opt {
     #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
     verbose: u8,
 
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    #[clap(flatten)]
+    demangle: DemangleArgs,
+
+    #[clap(flatten)]
+    collapse: CollapseArgs,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -48,5 +63,12 @@ fn main() -> io::Result<()> {
         .init();
     }
 
-    Folder::default().collapse_file_to_stdout(opt.infile)
+    let rules = opt.collapse.rewrite_rules()?;
+    let mut options = Options::default();
+    options.strict = opt.strict;
+    options.demangle = opt.demangle.demangle();
+    let mut folder = rewrite::Folder::new(Folder::from(options), rules);
+    let result = folder.collapse_file_to_stdout(opt.infile);
+    opt.collapse.print_stats(folder.stats());
+    result
 }
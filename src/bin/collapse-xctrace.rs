@@ -3,7 +3,9 @@ use std::path::PathBuf;
 
 use clap::{ArgAction, Parser};
 use env_logger::Env;
-use inferno::collapse::xctrace::Folder;
+use inferno::cli::{CollapseArgs, DemangleArgs};
+use inferno::collapse::rewrite;
+use inferno::collapse::xctrace::{Folder, Options};
 use inferno::collapse::Collapse;
 
 #[derive(Debug, Parser)]
@@ -20,6 +22,10 @@ use inferno::collapse::Collapse;
     "#
 )]
 struct Opt {
+    /// Fail on input without samples, rather than warn about it
+    #[clap(long = "strict")]
+    strict: bool,
+
     /// Silence all log output
     #[clap(short = 'q', long = "quiet")]
     quiet: bool,
@@ -28,6 +34,15 @@ struct Opt {
     #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
     verbose: u8,
 
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    #[clap(flatten)]
+    demangle: DemangleArgs,
+
+    #[clap(flatten)]
+    collapse: CollapseArgs,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -51,5 +66,12 @@ fn main() -> io::Result<()> {
         .init();
     }
 
-    Folder::default().collapse_file_to_stdout(opt.infile.as_ref())
+    let rules = opt.collapse.rewrite_rules()?;
+    let mut options = Options::default();
+    options.strict = opt.strict;
+    options.demangle = opt.demangle.demangle();
+    let mut folder = rewrite::Folder::new(Folder::from(options), rules);
+    let result = folder.collapse_file_to_stdout(opt.infile.as_ref());
+    opt.collapse.print_stats(folder.stats());
+    result
 }
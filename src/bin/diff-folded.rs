@@ -38,6 +38,10 @@ struct Opt {
     #[clap(short = 's', long = "strip-hex")]
     strip_hex: bool,
 
+    /// Fail on lines that can't be parsed, rather than skip them with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
     /// Silence all log output
     #[clap(short = 'q', long = "quiet")]
     quiet: bool,
@@ -66,6 +70,7 @@ impl Opt {
             Options {
                 normalize: self.normalize,
                 strip_hex: self.strip_hex,
+                strict: self.strict,
             },
         )
     }
@@ -7,7 +7,10 @@ use env_logger::Env;
 use inferno::flamegraph::color::{
     parse_hex_color, BackgroundColor, Color, PaletteMap, SearchColor, StrokeColor,
 };
-use inferno::flamegraph::{self, defaults, Direction, Options, Palette, TextTruncateDirection};
+use inferno::flamegraph::{
+    self, defaults, Direction, Format, Options, Palette, TextTruncateDirection,
+};
+use terminal_size::Width;
 
 #[cfg(feature = "nameattr")]
 use inferno::flamegraph::FuncFrameAttrsMap;
@@ -60,6 +63,10 @@ struct Opt {
     #[clap(long = "pretty-xml")]
     pretty_xml: bool,
 
+    /// Fail on lines that can't be parsed, or if there are no stack counts, rather than warn
+    #[clap(long = "strict")]
+    strict: bool,
+
     /// Silence all log output
     #[clap(short = 'q', long = "quiet")]
     quiet: bool,
@@ -109,6 +116,17 @@ struct Opt {
     )]
     factor: f64,
 
+    /// Output format; speedscope writes JSON for speedscope.app, png needs the png feature, ansi
+    /// draws the flame graph with colored text for terminals, html adds a table of functions
+    /// with their callers and callees, and json writes a d3-flame-graph tree
+    #[clap(
+        long = "format",
+        default_value = "svg",
+        value_parser = clap::builder::PossibleValuesParser::new(Format::VARIANTS).map(|s| s.parse::<Format>().unwrap()),
+        value_name = "STRING"
+    )]
+    format: Format,
+
     /// Font size
     #[clap(
         long = "fontsize",
@@ -145,6 +163,26 @@ struct Opt {
     )]
     uicolor: Color,
 
+    /// Pixels in the PNG image for every pixel of the SVG
+    #[clap(
+        long = "png-scale",
+        default_value = &**defaults::str::PNG_SCALE,
+        value_parser = |s: &str| {
+            s.parse::<f64>()
+                .ok()
+                .filter(|&scale| scale > 0.0 && scale <= flamegraph::MAX_PNG_SCALE)
+                .ok_or_else(|| {
+                    format!(
+                        "Expected a positive number no larger than {}, got: {}",
+                        flamegraph::MAX_PNG_SCALE,
+                        s
+                    )
+                })
+        },
+        value_name = "FLOAT"
+    )]
+    png_scale: f64,
+
     /// Height of each frame
     #[clap(
         long = "height",
@@ -212,6 +250,10 @@ struct Opt {
     #[clap(long = "width", value_name = "UINT")]
     width: Option<usize>,
 
+    /// Columns of the ansi output [default: the terminal's width, $COLUMNS, or 80]
+    #[clap(long = "terminal-width", value_name = "UINT")]
+    terminal_width: Option<usize>,
+
     /// Omit samples whose stacks do not contain this symbol. When this symbol is in a sample's
     /// stack, truncate the call stack so that this is the bottom-most symbol.
     /// This is particularly useful when you want to profile a specific function in a codebase that
@@ -219,6 +261,23 @@ struct Opt {
     #[clap(long = "base", value_name = "STRING")]
     base: Vec<String>,
 
+    /// Draw a sandwich graph of this function: its callers, reversed, above the function and its
+    /// callees below it. Only supported for SVG output.
+    #[clap(
+        long = "sandwich",
+        value_name = "STRING",
+        conflicts_with_all = ["base", "flame_chart", "inverted", "reverse"]
+    )]
+    sandwich: Option<String>,
+
+    /// Draw the reversed flame graph, which starts at the leaves, below the flame graph in the
+    /// same SVG. Only supported for SVG output.
+    #[clap(
+        long = "combined",
+        conflicts_with_all = ["base", "flame_chart", "inverted", "reverse", "sandwich"]
+    )]
+    combined: bool,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -264,14 +323,25 @@ impl<'a> Opt {
         options.reverse_stack_order = self.reverse;
         options.flame_chart = self.flame_chart;
         options.base = self.base;
+        options.combined = self.combined;
+        options.strict = self.strict;
+        options.format = self.format;
+        options.png_scale = self.png_scale;
 
         if self.flame_chart && self.title == defaults::TITLE {
             options.title = defaults::CHART_TITLE.to_owned();
         }
+        if let Some(function) = self.sandwich {
+            if self.title == defaults::TITLE {
+                options.title = format!("Sandwich Graph: {}", function);
+            }
+            options.sandwich = Some(function);
+        }
 
         // set style options
         options.subtitle = self.subtitle;
         options.image_width = self.width;
+        options.terminal_width = self.terminal_width;
         options.frame_height = self.height;
         options.min_width = self.minwidth;
         options.font_type = self.fonttype;
@@ -333,6 +403,10 @@ fn main() -> io::Result<()> {
     options.palette_map = palette_map.as_mut();
 
     if std::io::stdout().is_terminal() {
+        if options.terminal_width.is_none() {
+            options.terminal_width =
+                terminal_size::terminal_size().map(|(Width(width), _)| usize::from(width));
+        }
         flamegraph::from_files(&mut options, &infiles, io::stdout().lock())?;
     } else {
         flamegraph::from_files(
@@ -375,7 +449,7 @@ fn save_consistent_palette_if_needed(
 mod tests {
     use super::Opt;
     use clap::Parser;
-    use inferno::flamegraph::{color, Direction, Options, Palette, TextTruncateDirection};
+    use inferno::flamegraph::{color, Direction, Format, Options, Palette, TextTruncateDirection};
     use pretty_assertions::assert_eq;
     use std::path::PathBuf;
     use std::str::FromStr;
@@ -430,6 +504,12 @@ mod tests {
             "--pretty-xml",
             "--reverse",
             "--no-javascript",
+            "--format",
+            "speedscope",
+            "--png-scale",
+            "2",
+            "--terminal-width",
+            "120",
             "test_infile1",
             "test_infile2",
         ];
@@ -460,6 +540,9 @@ mod tests {
         expected_options.reverse_stack_order = true;
         expected_options.no_javascript = true;
         expected_options.color_diffusion = false;
+        expected_options.format = Format::Speedscope;
+        expected_options.png_scale = 2.0;
+        expected_options.terminal_width = Some(120);
 
         assert_eq!(options, expected_options);
         assert_eq!(infiles.len(), 2, "expected 2 input files");
@@ -0,0 +1,126 @@
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::fold::{self, Options};
+use regex::Regex;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-fold",
+    about,
+    after_help = "\
+Filters and transforms folded stack files, and writes the result as folded
+stacks that can be passed to inferno-flamegraph.
+
+  $ inferno-fold --keep '^parse$' --reroot '^parse$' stacks.folded | inferno-flamegraph > parse.svg
+
+The operations are applied in the order they are listed in above: stacks are
+kept or dropped based on all of their frames, then frames are dropped, stacks
+are re-rooted and trimmed, and finally counts are scaled and the top stacks
+picked. Stacks that end up the same are merged."
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Fail on lines that can't be parsed, rather than skip them with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Only keep stacks with a frame that matches REGEX
+    #[clap(long = "keep", value_name = "REGEX", value_parser = Regex::new)]
+    keep: Option<Regex>,
+
+    /// Drop stacks with a frame that matches REGEX
+    #[clap(long = "drop", value_name = "REGEX", value_parser = Regex::new)]
+    drop: Option<Regex>,
+
+    /// Remove the frames that match REGEX from every stack
+    #[clap(long = "drop-frames", value_name = "REGEX", value_parser = Regex::new)]
+    drop_frames: Option<Regex>,
+
+    /// Start every stack at its innermost frame that matches REGEX, and drop stacks without one
+    #[clap(long = "reroot", value_name = "REGEX", value_parser = Regex::new)]
+    reroot: Option<Regex>,
+
+    /// Cut every stack off after UINT frames from the root
+    #[clap(long = "max-depth", value_name = "UINT")]
+    max_depth: Option<usize>,
+
+    /// Multiply every count by FLOAT, rounding to the nearest integer
+    #[clap(
+        long = "scale",
+        value_name = "FLOAT",
+        allow_negative_numbers = true,
+        value_parser = |s: &str| {
+            s.parse::<f64>()
+                .ok()
+                .filter(|scale| scale.is_finite() && *scale > 0.0)
+                .ok_or_else(|| format!("Expected a positive number, got: {}", s))
+        }
+    )]
+    scale: Option<f64>,
+
+    /// Only keep the UINT stacks with the highest counts
+    #[clap(long = "top", value_name = "UINT")]
+    top: Option<usize>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Folded stack files, or STDIN if not specified; `-` is also STDIN
+    #[clap(value_name = "PATH")]
+    infiles: Vec<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Vec<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.keep = self.keep;
+        options.drop = self.drop;
+        options.drop_frames = self.drop_frames;
+        options.reroot = self.reroot;
+        options.max_depth = self.max_depth;
+        options.scale = self.scale;
+        options.top = self.top;
+        options.strict = self.strict;
+        (self.infiles, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infiles, options) = opt.into_parts();
+
+    if std::io::stdout().is_terminal() {
+        fold::from_files(&options, &infiles, io::stdout().lock())
+    } else {
+        fold::from_files(&options, &infiles, io::BufWriter::new(io::stdout().lock()))
+    }
+}
@@ -0,0 +1,160 @@
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::fold::merge::{self, Input, Options};
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-merge-folded",
+    about,
+    after_help = "\
+Sums the counts of several folded stack files, and writes the result as folded
+stacks that can be passed to inferno-flamegraph.
+
+  $ inferno-merge-folded --normalize --tag-files host-a.folded host-b.folded | inferno-flamegraph > fleet.svg
+
+Unlike passing several files to inferno-flamegraph, every profile can be given
+a weight, and be normalized so that it contributes as much as the others no
+matter how long or at what frequency it was recorded. Tagged profiles get an
+extra root frame, so they can be split back apart with inferno-fold --reroot."
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Fail on lines that can't be parsed, rather than skip them with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
+    /// Tag every profile with its file name, without the extension, or with `stdin`
+    #[clap(long = "tag-files", conflicts_with = "tags")]
+    tag_files: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Scale every profile so its counts add up to UINT, or to the largest total of the profiles
+    #[clap(
+        long = "normalize",
+        value_name = "UINT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0"
+    )]
+    normalize: Option<u64>,
+
+    /// Weight of each profile, in the order the files are given (default: 1)
+    #[clap(long = "weight", value_name = "FLOAT", allow_negative_numbers = true)]
+    weights: Vec<f64>,
+
+    /// Root frame to add to the stacks of each profile, in the order the files are given
+    #[clap(long = "tag", value_name = "STRING")]
+    tags: Vec<String>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Folded stack files, or STDIN if not specified; `-` is also STDIN
+    #[clap(value_name = "PATH")]
+    infiles: Vec<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> io::Result<(Vec<(PathBuf, Input)>, Options)> {
+        let mut infiles = self.infiles;
+        if infiles.is_empty() {
+            infiles.push(PathBuf::from("-"));
+        }
+        for (flag, given) in [("--weight", self.weights.len()), ("--tag", self.tags.len())] {
+            if given != 0 && given != infiles.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} was given {} times for {} input files",
+                        flag,
+                        given,
+                        infiles.len()
+                    ),
+                ));
+            }
+        }
+
+        if let Some(weight) = self.weights.iter().find(|w| !(w.is_finite() && **w > 0.0)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--weight must be a positive number, not {}", weight),
+            ));
+        }
+        let mut weights = self.weights.into_iter();
+        let mut tags = self.tags.into_iter();
+        let inputs: Vec<_> = infiles
+            .into_iter()
+            .map(|path| {
+                let mut input = Input::default();
+                if let Some(weight) = weights.next() {
+                    input.weight = weight;
+                }
+                input.tag = if self.tag_files && path == Path::new("-") {
+                    Some("stdin".to_string())
+                } else if self.tag_files {
+                    path.file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                } else {
+                    tags.next()
+                };
+                (path, input)
+            })
+            .collect();
+        // Tags from file names are checked too, since they end up in the stacks all the same.
+        if let Some(tag) = inputs
+            .iter()
+            .filter_map(|(_, input)| input.tag.as_ref())
+            .find(|tag| tag.contains(';'))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Tags must not contain `;`, which separates frames: {}", tag),
+            ));
+        }
+
+        let mut options = Options::default();
+        options.normalize = self.normalize.is_some();
+        options.total = self.normalize.filter(|&total| total != 0);
+        options.strict = self.strict;
+        Ok((inputs, options))
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (inputs, options) = opt.into_parts()?;
+
+    if std::io::stdout().is_terminal() {
+        merge::from_files(&options, &inputs, io::stdout().lock())
+    } else {
+        merge::from_files(&options, &inputs, io::BufWriter::new(io::stdout().lock()))
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::pprof::{self, defaults, Options};
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-pprof",
+    about,
+    after_help = "\
+Converts folded stack files, or the output of inferno-diff-folded, to a gzipped
+pprof profile.proto that can be read by pprof and tools that ingest pprof.
+
+  $ inferno-pprof --sample-type cpu --unit nanoseconds stacks.folded > profile.pb.gz
+  $ go tool pprof -http=: profile.pb.gz
+
+Input with two counts per stack gets two sample types, named SAMPLE_TYPE_before
+and SAMPLE_TYPE_after."
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Fail on lines that can't be parsed, rather than skip them with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Type of the sample values, like cpu or alloc_space
+    #[clap(
+        long = "sample-type",
+        default_value = defaults::SAMPLE_TYPE,
+        value_name = "STRING"
+    )]
+    sample_type: String,
+
+    /// Unit of the sample values, like nanoseconds or bytes
+    #[clap(long = "unit", default_value = defaults::UNIT, value_name = "STRING")]
+    unit: String,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Folded stack files, or STDIN if not specified; `-` is also STDIN
+    #[clap(value_name = "PATH")]
+    infiles: Vec<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Vec<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.sample_type = self.sample_type;
+        options.unit = self.unit;
+        options.strict = self.strict;
+        (self.infiles, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infiles, options) = opt.into_parts();
+
+    if std::io::stdout().is_terminal() {
+        pprof::from_files(&options, &infiles, io::stdout().lock())
+    } else {
+        pprof::from_files(&options, &infiles, io::BufWriter::new(io::stdout().lock()))
+    }
+}
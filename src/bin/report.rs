@@ -0,0 +1,117 @@
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use clap::builder::TypedValueParser;
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::report::{self, Format, Options, SortBy};
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-report",
+    about,
+    after_help = "\
+Ranks the functions in folded stack files by the samples they are the leaf of
+(self) or on the stack of (total), with their share of all samples.
+
+  $ inferno-report --top 20 stacks.folded
+  $ inferno-report --sort total --baseline before.folded after.folded
+
+With a baseline, or with the two-count output of inferno-diff-folded, the table
+shows how much each share changed, in percentage points, and the CSV and JSON
+have the baseline counts as well."
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Fail on lines that can't be parsed, rather than skip them with a warning
+    #[clap(long = "strict")]
+    strict: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Output format
+    #[clap(
+        long = "format",
+        default_value = "table",
+        value_parser = clap::builder::PossibleValuesParser::new(Format::VARIANTS).map(|s| s.parse::<Format>().unwrap()),
+        value_name = "STRING"
+    )]
+    format: Format,
+
+    /// Count to rank the functions by
+    #[clap(
+        long = "sort",
+        default_value = "self",
+        value_parser = clap::builder::PossibleValuesParser::new(SortBy::VARIANTS).map(|s| s.parse::<SortBy>().unwrap()),
+        value_name = "STRING"
+    )]
+    sort: SortBy,
+
+    /// Only report the top UINT functions
+    #[clap(long = "top", value_name = "UINT")]
+    top: Option<usize>,
+
+    /// Folded stack file to compare against; may be given more than once
+    #[clap(long = "baseline", value_name = "PATH")]
+    baseline: Vec<PathBuf>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Folded stack files, or STDIN if not specified; `-` is also STDIN
+    #[clap(value_name = "PATH")]
+    infiles: Vec<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Vec<PathBuf>, Vec<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.format = self.format;
+        options.sort = self.sort;
+        options.top = self.top;
+        options.strict = self.strict;
+        (self.baseline, self.infiles, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (baseline, infiles, options) = opt.into_parts();
+    let write = |writer: &mut dyn io::Write| {
+        if baseline.is_empty() {
+            report::from_files(&options, &infiles, writer)
+        } else {
+            report::diff_files(&options, &baseline, &infiles, writer)
+        }
+    };
+
+    if std::io::stdout().is_terminal() {
+        write(&mut io::stdout().lock())
+    } else {
+        write(&mut io::BufWriter::new(io::stdout().lock()))
+    }
+}
@@ -0,0 +1,102 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::collapse::svg::{Folder, Options};
+use inferno::collapse::{Collapse, CollapseStats};
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-svg-to-folded",
+    about,
+    after_help = "\
+Rebuilds the folded stacks from a flame graph SVG made by inferno-flamegraph,
+for when the input it was made from is gone.
+
+  $ inferno-svg-to-folded flamegraph.svg > stacks.folded
+
+The samples of frames that were too narrow to be drawn are counted in their
+parent. A differential flame graph gives two counts per stack, whose difference
+is only as precise as the percentages in the SVG."
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Fail on frames that can't be parsed or an SVG without frames, rather than warn
+    #[clap(long = "strict")]
+    strict: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Print counts of what was read and collapsed to STDERR, as `text` (the default) or
+    /// `json`, like `--stats=json`
+    #[clap(
+        long = "stats",
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text",
+        value_parser = ["text", "json"]
+    )]
+    stats: Option<String>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Flame graph SVG, or STDIN if not specified
+    #[clap(value_name = "PATH")]
+    infile: Option<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Option<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.strict = self.strict;
+        (self.infile, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let stats = opt.stats.clone();
+    let (infile, options) = opt.into_parts();
+    let mut folder = Folder::from(options);
+    let result = folder.collapse_file_to_stdout(infile.as_ref());
+    if let Some(format) = stats {
+        print_stats(&format, folder.stats());
+    }
+    result
+}
+
+fn print_stats(format: &str, stats: Option<&CollapseStats>) {
+    if let Some(stats) = stats {
+        match format {
+            "json" => eprintln!("{}", stats.to_json()),
+            _ => eprint!("{}", stats),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Command-line arguments that several of the inferno binaries share.
+//!
+//! This module only exists so that the binaries don't each carry their own copy of these
+//! arguments. It is not part of the stable API of the library.
+
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Args;
+
+use crate::collapse::rewrite::Rules;
+use crate::collapse::{stream, CollapseStats, Demangle};
+
+/// Arguments that every collapse binary takes.
+#[derive(Clone, Debug, Args)]
+#[non_exhaustive]
+pub struct CollapseArgs {
+    /// File of rules to rewrite frames with, one per line, like `replace /\(.*\)$//`,
+    /// `drop /^\[unknown\]$/`, `truncate-after /^malloc$/`, or `skip-after /^main$/`
+    #[clap(long = "rewrite-rules", value_name = "PATH")]
+    pub rewrite_rules: Option<PathBuf>,
+
+    /// Print counts of what was read and collapsed to STDERR, as `text` (the default) or
+    /// `json`, like `--stats=json`
+    #[clap(
+        long = "stats",
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text",
+        value_parser = ["text", "json"]
+    )]
+    pub stats: Option<String>,
+}
+
+impl CollapseArgs {
+    /// Reads the rules given with `--rewrite-rules`, or returns no rules if there are none.
+    pub fn rewrite_rules(&self) -> io::Result<Rules> {
+        match &self.rewrite_rules {
+            Some(path) => Rules::from_file(path),
+            None => Ok(Rules::default()),
+        }
+    }
+
+    /// Prints the given counts to STDERR in the format asked for with `--stats`, if any.
+    pub fn print_stats(&self, stats: Option<&CollapseStats>) {
+        if let (Some(format), Some(stats)) = (&self.stats, stats) {
+            match &**format {
+                "json" => eprintln!("{}", stats.to_json()),
+                _ => eprint!("{}", stats),
+            }
+        }
+    }
+}
+
+/// Arguments of the collapse binaries that can demangle symbols.
+#[derive(Clone, Debug, Args)]
+#[non_exhaustive]
+pub struct DemangleArgs {
+    /// Demangle C++ and Rust symbols that were left mangled
+    #[clap(long = "demangle")]
+    pub demangle: bool,
+
+    /// Remove template and generic arguments from demangled names (implies --demangle)
+    #[clap(long = "strip-generics")]
+    pub strip_generics: bool,
+
+    /// Remove hashes from demangled Rust names (implies --demangle)
+    #[clap(long = "strip-rust-hash")]
+    pub strip_rust_hash: bool,
+}
+
+impl DemangleArgs {
+    /// Returns how to demangle symbols, or `None` if they should be left as they are.
+    pub fn demangle(&self) -> Option<Demangle> {
+        (self.demangle || self.strip_generics || self.strip_rust_hash).then_some(Demangle {
+            strip_generics: self.strip_generics,
+            strip_rust_hash: self.strip_rust_hash,
+        })
+    }
+}
+
+/// Arguments of the collapse binaries that can stream their input.
+#[derive(Clone, Debug, Args)]
+#[non_exhaustive]
+pub struct StreamArgs {
+    /// Stream the input, and make every snapshot include the stacks of the snapshots before it
+    #[clap(long = "cumulative")]
+    pub cumulative: bool,
+
+    /// Stream the input, writing a snapshot of the folded stacks every SECS seconds
+    #[clap(long = "flush-interval", value_parser = parse_secs, value_name = "SECS")]
+    pub flush_interval: Option<Duration>,
+
+    /// Stream the input, writing a snapshot of the folded stacks every UINT stacks
+    #[clap(long = "flush-stacks", value_parser = clap::value_parser!(u64).range(1..), value_name = "UINT")]
+    pub flush_stacks: Option<u64>,
+
+    /// Stream the input, keeping at most UINT distinct stacks in memory by merging the rarest
+    /// ones into `[other]`
+    #[clap(long = "max-stacks", value_parser = clap::value_parser!(u64).range(1..), value_name = "UINT")]
+    pub max_stacks: Option<u64>,
+}
+
+impl StreamArgs {
+    /// Returns the options to stream the input with, or `None` if it should not be streamed.
+    pub fn options(&self) -> Option<stream::Options> {
+        if !self.cumulative
+            && self.flush_interval.is_none()
+            && self.flush_stacks.is_none()
+            && self.max_stacks.is_none()
+        {
+            return None;
+        }
+        Some(stream::Options {
+            interval: self.flush_interval,
+            stacks: self.flush_stacks,
+            cumulative: self.cumulative,
+            max_stacks: self.max_stacks.map(|n| n as usize),
+            ..Default::default()
+        })
+    }
+}
+
+fn parse_secs(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .filter(|interval| !interval.is_zero())
+        .ok_or_else(|| format!("`{}` is not a positive number of seconds", s))
+}
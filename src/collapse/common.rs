@@ -1,5 +1,5 @@
 use std::borrow::Cow;
-use std::io;
+use std::io::{self, BufRead, Read};
 #[cfg(feature = "multithreaded")]
 use std::mem;
 #[cfg(feature = "multithreaded")]
@@ -10,6 +10,8 @@ use ahash::AHashMap;
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
 
+use super::{CollapseStats, FoldedStacks};
+
 macro_rules! invalid_data_error {
     ($($arg:tt)*) => {{
         Err(io::Error::new(
@@ -23,12 +25,13 @@ const CAPACITY_HASHMAP: usize = 512;
 
 pub(crate) const CAPACITY_READER: usize = 128 * 1024;
 
-/// Internal parameter (not exposed to users) that determines how many stacks of
-/// input data make up a "chunk" (unit that is sent to the threadpool for
-/// processing). Chosen by benchmarking various values using the following tests:
+/// The default number of stacks of input data that make up a "chunk" (unit that is sent to the
+/// threadpool for processing) for a [`ParallelCollapse`] implementation.
+///
+/// Chosen by benchmarking various values using the following tests:
 /// * cargo test bench_nstacks_dtrace --release -- --ignored --nocapture
 /// * cargo test bench_nstacks_perf --release -- --ignored --nocapture
-pub(crate) const DEFAULT_NSTACKS_PER_JOB: usize = 100;
+pub const DEFAULT_NSTACKS_PER_JOB: usize = 100;
 
 /// A guess at the number of bytes contained in any given stack of any given format.
 /// Used to calculate the initial capacity of the vector used for sending input
@@ -46,17 +49,129 @@ pub static DEFAULT_NTHREADS: Lazy<usize> =
 #[doc(hidden)]
 pub static DEFAULT_NTHREADS: Lazy<usize> = Lazy::new(|| 1);
 
-/// Sealed trait for internal library authors.
+/// Chunked, multithreaded stack collapsing.
 ///
 /// If you implement this trait, your type will implement the public-facing
-/// `Collapse` trait as well. Implementing this trait gives you parallelism
-/// for free as long as you adhere to the requirements described in the
+/// [`Collapse`](super::Collapse) trait as well. Implementing this trait gives you
+/// parallelism for free as long as you adhere to the requirements described in the
 /// comments below.
-pub trait CollapsePrivate: Send + Sized {
-    // *********************************************************** //
-    // ********************* REQUIRED METHODS ******************** //
-    // *********************************************************** //
-
+///
+/// Input is collapsed in three steps:
+///
+/// 1. [`pre_process`](ParallelCollapse::pre_process) consumes any header on the main
+///    thread.
+/// 2. The main thread reads the rest of the input line by line, and uses
+///    [`would_end_stack`](ParallelCollapse::would_end_stack) to split it into chunks of
+///    [`nstacks_per_job`](ParallelCollapse::nstacks_per_job) whole stacks.
+/// 3. Each worker thread folds the chunks it receives with
+///    [`collapse_single_threaded`](ParallelCollapse::collapse_single_threaded), using its own
+///    copy of the collapser made by
+///    [`clone_and_reset_stack_context`](ParallelCollapse::clone_and_reset_stack_context),
+///    into an [`Occurrences`] map shared by all threads.
+///
+/// If [`nthreads`](ParallelCollapse::nthreads) is 1, or the `multithreaded` feature is
+/// disabled, the whole input is instead passed to `collapse_single_threaded` in one go.
+///
+/// For example, a format where each stack is a block of frames (innermost first) that ends with
+/// an empty line could be implemented like this:
+///
+/// ```
+/// use std::io;
+///
+/// use inferno::collapse::{Collapse, Occurrences, ParallelCollapse, DEFAULT_NSTACKS_PER_JOB};
+///
+/// #[derive(Clone)]
+/// struct Folder {
+///     stack: Vec<String>,
+///     nthreads: usize,
+///     nstacks_per_job: usize,
+/// }
+///
+/// impl Folder {
+///     fn end_stack(&mut self, occurrences: &mut Occurrences) {
+///         if !self.stack.is_empty() {
+///             self.stack.reverse();
+///             occurrences.insert_or_add(self.stack.join(";"), 1);
+///             self.stack.clear();
+///         }
+///     }
+/// }
+///
+/// impl ParallelCollapse for Folder {
+///     fn pre_process<R>(&mut self, _: &mut R, _: &mut Occurrences) -> io::Result<()>
+///     where
+///         R: io::BufRead,
+///     {
+///         Ok(())
+///     }
+///
+///     fn collapse_single_threaded<R>(
+///         &mut self,
+///         reader: R,
+///         occurrences: &mut Occurrences,
+///     ) -> io::Result<()>
+///     where
+///         R: io::BufRead,
+///     {
+///         for line in reader.lines() {
+///             let line = line?;
+///             if line.trim().is_empty() {
+///                 self.end_stack(occurrences);
+///             } else {
+///                 self.stack.push(line.trim().to_string());
+///             }
+///         }
+///         self.end_stack(occurrences);
+///         Ok(())
+///     }
+///
+///     fn would_end_stack(&mut self, line: &[u8]) -> bool {
+///         line.iter().all(u8::is_ascii_whitespace)
+///     }
+///
+///     fn clone_and_reset_stack_context(&self) -> Self {
+///         Folder {
+///             stack: Vec::new(),
+///             ..self.clone()
+///         }
+///     }
+///
+///     fn is_applicable(&mut self, _: &str) -> Option<bool> {
+///         None
+///     }
+///
+///     fn nstacks_per_job(&self) -> usize {
+///         self.nstacks_per_job
+///     }
+///
+///     fn set_nstacks_per_job(&mut self, n: usize) {
+///         self.nstacks_per_job = n;
+///     }
+///
+///     fn nthreads(&self) -> usize {
+///         self.nthreads
+///     }
+///
+///     fn set_nthreads(&mut self, n: usize) {
+///         self.nthreads = n;
+///     }
+/// }
+///
+/// let mut folder = Folder {
+///     stack: Vec::new(),
+///     nthreads: 4,
+///     nstacks_per_job: DEFAULT_NSTACKS_PER_JOB,
+/// };
+/// let input = "bar\nfoo\nmain\n\nfoo\nmain\n\nbar\nfoo\nmain\n";
+/// let mut output = Vec::new();
+/// folder.collapse(input.as_bytes(), &mut output)?;
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     "main;foo 1\nmain;foo;bar 2\n"
+/// );
+/// # Ok::<(), io::Error>(())
+/// ```
+pub trait ParallelCollapse: Send + Sized {
     /// Process any header lines that precede the main body of samples.
     ///
     /// Some formats, such as `dtrace`, contain a header or other non-stack
@@ -144,219 +259,251 @@ pub trait CollapsePrivate: Send + Sized {
     /// Sets the number of threads to use.
     fn set_nthreads(&mut self, n: usize);
 
-    // *********************************************************** //
-    // ******************** PROVIDED METHODS ********************* //
-    // *********************************************************** //
+    /// Returns the counts kept while collapsing, if this implementation keeps any.
+    ///
+    /// The counts are reset before [`pre_process`](ParallelCollapse::pre_process), and the
+    /// counts of the copies made for worker threads are added to them once the input ends.
+    /// [`CollapseStats::unique_stacks`] and [`CollapseStats::samples`] are filled in from the
+    /// [`Occurrences`], so implementations only need to count the rest.
+    fn stats(&self) -> Option<&CollapseStats> {
+        None
+    }
 
-    fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
-    where
-        R: io::BufRead,
-        W: io::Write,
-    {
-        let mut occurrences = Occurrences::new(self.nthreads());
+    /// Returns the counts that [`stats`](ParallelCollapse::stats) returns, to be updated.
+    fn stats_mut(&mut self) -> Option<&mut CollapseStats> {
+        None
+    }
+}
 
-        // Consume the header, if any, and do any other pre-processing
-        // that needs to occur.
-        self.pre_process(&mut reader, &mut occurrences)?;
+/// Collapses the contents of `reader` with `collapser`, using as many threads as it asks for.
+pub(crate) fn collapse_to_occurrences<C, R>(
+    collapser: &mut C,
+    mut reader: R,
+) -> io::Result<Occurrences>
+where
+    C: ParallelCollapse,
+    R: io::BufRead,
+{
+    let mut occurrences = Occurrences::new(collapser.nthreads());
+    if let Some(stats) = collapser.stats_mut() {
+        *stats = CollapseStats::default();
+    }
 
-        // Do collapsing.
-        if occurrences.is_concurrent() {
-            self.collapse_multi_threaded(reader, &mut occurrences)?;
-        } else {
-            self.collapse_single_threaded(reader, &mut occurrences)?;
-        }
+    // Consume the header, if any, and do any other pre-processing
+    // that needs to occur.
+    collapser.pre_process(&mut reader, &mut occurrences)?;
 
-        // Write results.
-        occurrences.write_and_clear(writer)
+    // Do collapsing.
+    if occurrences.is_concurrent() {
+        collapse_multi_threaded(collapser, reader, &mut occurrences)?;
+    } else {
+        collapser.collapse_single_threaded(reader, &mut occurrences)?;
     }
 
-    #[cfg(not(feature = "multithreaded"))]
-    fn collapse_multi_threaded<R>(&mut self, _: R, _: &mut Occurrences) -> io::Result<()>
-    where
-        R: io::BufRead,
-    {
-        unimplemented!();
+    if let Some(stats) = collapser.stats_mut() {
+        stats.count_occurrences(&occurrences);
     }
+    Ok(occurrences)
+}
 
-    #[cfg(feature = "multithreaded")]
-    fn collapse_multi_threaded<R>(
-        &mut self,
-        mut reader: R,
-        occurrences: &mut Occurrences,
-    ) -> io::Result<()>
-    where
-        R: io::BufRead,
-    {
-        let nstacks_per_job = self.nstacks_per_job();
-        let nthreads = self.nthreads();
-
-        assert_ne!(nstacks_per_job, 0);
-        assert!(nthreads > 1);
-        assert!(occurrences.is_concurrent());
-
-        crossbeam_utils::thread::scope(|scope| {
-            // Channel for sending an error from the worker threads to the main thread
-            // in the event a worker has failed.
-            let (tx_error, rx_error) = crossbeam_channel::bounded::<io::Error>(1);
-
-            // Channel for sending input data from the main thread to the worker threads.
-            // We choose `2 * nthreads` as the channel size here in order to limit memory
-            // usage in the case of particularly large input files.
-            let (tx_input, rx_input) = crossbeam_channel::bounded::<Vec<u8>>(2 * nthreads);
-
-            // Channel for worker threads that have errored to signal to all the other
-            // worker threads that they should stop work immediately and return.
-            let (tx_stop, rx_stop) = crossbeam_channel::bounded::<()>(nthreads - 1);
-
-            let mut handles = Vec::with_capacity(nthreads);
-            for _ in 0..nthreads {
-                let tx_error = tx_error.clone();
-                let rx_input = rx_input.clone();
-                let (tx_stop, rx_stop) = (tx_stop.clone(), rx_stop.clone());
-
-                let mut folder = self.clone_and_reset_stack_context();
-                let mut occurrences = occurrences.clone();
-
-                // Launch the worker thread...
-                let handle = scope.spawn(move |_| loop {
-                    crossbeam_channel::select! {
-                        recv(rx_input) -> input => {
-                            // Receive input from the main thread.
-                            let data = match input {
-                                Ok(data) => data,
-                                // The main threads drops it's handle to the input sender once it's
-                                // finished sending data; so if we get an error here, it means
-                                // there is no more data to be sent and we should exit.
-                                Err(_) => return,
-                            };
-                            // If there is input data, process it.
-                            if let Err(e) = folder.collapse_single_threaded(&data[..], &mut occurrences) {
-                                // In the event of an error...
-                                //
-                                // We notify all the threads about it here, rather than wait for the main input
-                                // loop to see the error, so that we can also stop the input loop from iterating
-                                // through the rest of the file.
-                                //
-                                // If the channel is full, it means another thread has also errored
-                                // and already sent a stop signal to the other threads; so there is
-                                // no need to wait or to check for a `SendError` here.
-                                for _ in 0..(nthreads - 1) {
-                                    let _ = tx_stop.try_send(());
-                                }
-
-                                // Then, send the error produced to the main thread for
-                                // propagation. If the channel is full, it means another thread
-                                // has also errored and already sent its error back to the
-                                // main thread; so there is no need to wait or to check for a
-                                // `SendError` here.
-                                let _ = tx_error.try_send(e);
-
-                                // Finally, return.
-                                return;
-                            }
-                            // If successful, return to the top of the loop and continue to poll
-                            // the input and stop channels.
-                        },
-                        recv(rx_stop) -> _ => {
-                            // Received a signal from another worker thread that it has errored;
-                            // so should cease work immediately and return.
-                            return;
-                        },
-                    }
-                });
-                handles.push(handle);
-            }
-
-            // On the main thread, we're about to start sending data to the worker threads,
-            // but we only want to send data to the worker threads **if** they're still alive!
-            // (if one of them produces an error, all of them will exit early). To ensure we don't try
-            // to send data to dead worker threads, drop the main thread's handle to the input receiver
-            // here. This way, if all the workers die, every handle to the input receiver will have
-            // been dropped and we'll get an error when trying to send data on the input sender,
-            // which will tell us (the main thread) to stop trying to send data and, instead,
-            // skip to trying to pull an error off the error channel.
-            drop(rx_input);
+#[cfg(not(feature = "multithreaded"))]
+fn collapse_multi_threaded<C, R>(_: &mut C, _: R, _: &mut Occurrences) -> io::Result<()>
+where
+    C: ParallelCollapse,
+    R: io::BufRead,
+{
+    unimplemented!();
+}
 
-            // Now that we've dropped the main thread's handle to the input sender, start
-            // trying to send data to the worker threads...
+#[cfg(feature = "multithreaded")]
+fn collapse_multi_threaded<C, R>(
+    collapser: &mut C,
+    mut reader: R,
+    occurrences: &mut Occurrences,
+) -> io::Result<()>
+where
+    C: ParallelCollapse,
+    R: io::BufRead,
+{
+    let nstacks_per_job = collapser.nstacks_per_job();
+    let nthreads = collapser.nthreads();
+
+    assert_ne!(nstacks_per_job, 0);
+    assert!(nthreads > 1);
+    assert!(occurrences.is_concurrent());
+
+    crossbeam_utils::thread::scope(|scope| {
+        // Channel for sending an error from the worker threads to the main thread
+        // in the event a worker has failed.
+        let (tx_error, rx_error) = crossbeam_channel::bounded::<io::Error>(1);
+
+        // Channel for sending input data from the main thread to the worker threads.
+        // We choose `2 * nthreads` as the channel size here in order to limit memory
+        // usage in the case of particularly large input files.
+        let (tx_input, rx_input) = crossbeam_channel::bounded::<Vec<u8>>(2 * nthreads);
+
+        // Channel for worker threads that have errored to signal to all the other
+        // worker threads that they should stop work immediately and return.
+        let (tx_stop, rx_stop) = crossbeam_channel::bounded::<()>(nthreads - 1);
+
+        let mut handles = Vec::with_capacity(nthreads);
+        for _ in 0..nthreads {
+            let tx_error = tx_error.clone();
+            let rx_input = rx_input.clone();
+            let (tx_stop, rx_stop) = (tx_stop.clone(), rx_stop.clone());
+
+            let mut folder = collapser.clone_and_reset_stack_context();
+            if let Some(stats) = folder.stats_mut() {
+                *stats = CollapseStats::default();
+            }
+            let mut occurrences = occurrences.clone();
+
+            // Launch the worker thread...
+            let handle = scope.spawn(move |_| loop {
+                crossbeam_channel::select! {
+                    recv(rx_input) -> input => {
+                        // Receive input from the main thread.
+                        let data = match input {
+                            Ok(data) => data,
+                            // The main threads drops it's handle to the input sender once it's
+                            // finished sending data; so if we get an error here, it means
+                            // there is no more data to be sent and we should exit.
+                            Err(_) => return folder.stats().cloned(),
+                        };
+                        // If there is input data, process it.
+                        if let Err(e) = folder.collapse_single_threaded(&data[..], &mut occurrences) {
+                            // In the event of an error...
+                            //
+                            // We notify all the threads about it here, rather than wait for the main input
+                            // loop to see the error, so that we can also stop the input loop from iterating
+                            // through the rest of the file.
+                            //
+                            // If the channel is full, it means another thread has also errored
+                            // and already sent a stop signal to the other threads; so there is
+                            // no need to wait or to check for a `SendError` here.
+                            for _ in 0..(nthreads - 1) {
+                                let _ = tx_stop.try_send(());
+                            }
 
-            let buf_capacity = usize::next_power_of_two(NBYTES_PER_STACK_GUESS * nstacks_per_job);
-            let mut buf = Vec::with_capacity(buf_capacity);
-            let (mut index, mut nstacks) = (0, 0);
+                            // Then, send the error produced to the main thread for
+                            // propagation. If the channel is full, it means another thread
+                            // has also errored and already sent its error back to the
+                            // main thread; so there is no need to wait or to check for a
+                            // `SendError` here.
+                            let _ = tx_error.try_send(e);
 
-            loop {
-                let n = reader.read_until(b'\n', &mut buf)?;
-                if n == 0 {
-                    // If we've reached the end of the data, send the final chunk to the worker
-                    // threads and break from the loop, The worker threads may or may not still
-                    // be alive (depending on if one errored in between the sending of the last
-                    // chunk and the sending of this one), but either way we should break the loop;
-                    // so there's no need to check for a `SendError` here.
-                    let _ = tx_input.send(buf);
-                    break;
-                }
-                let line = &buf[index..index + n];
-                index += n;
-                if self.would_end_stack(line) {
-                    // If we've reached the end of a stack, count it.
-                    nstacks += 1;
-                    if nstacks == nstacks_per_job {
-                        // If we've accumulated enough stacks to make up a chunk to send to the
-                        // worker threads, try to send it.
-                        let buf_capacity = usize::next_power_of_two(buf.capacity());
-                        let chunk = mem::replace(&mut buf, Vec::with_capacity(buf_capacity));
-                        if tx_input.send(chunk).is_err() {
-                            // If sending the chunk produces a `SendError`, this means that one
-                            // of the worker threads has errored, sent a signal to all the other
-                            // worker threads to shut down, and they have all shutdown, in which
-                            // case we know there will be an error waiting for us on the error
-                            // channel; so we should stop parsing input data (i.e. break).
-                            break;
+                            // Finally, return.
+                            return None;
                         }
-                        index = 0;
-                        nstacks = 0;
+                        // If successful, return to the top of the loop and continue to poll
+                        // the input and stop channels.
+                    },
+                    recv(rx_stop) -> _ => {
+                        // Received a signal from another worker thread that it has errored;
+                        // so should cease work immediately and return.
+                        return None;
+                    },
+                }
+            });
+            handles.push(handle);
+        }
+
+        // On the main thread, we're about to start sending data to the worker threads,
+        // but we only want to send data to the worker threads **if** they're still alive!
+        // (if one of them produces an error, all of them will exit early). To ensure we don't try
+        // to send data to dead worker threads, drop the main thread's handle to the input receiver
+        // here. This way, if all the workers die, every handle to the input receiver will have
+        // been dropped and we'll get an error when trying to send data on the input sender,
+        // which will tell us (the main thread) to stop trying to send data and, instead,
+        // skip to trying to pull an error off the error channel.
+        drop(rx_input);
+
+        // Now that we've dropped the main thread's handle to the input sender, start
+        // trying to send data to the worker threads...
+
+        let buf_capacity = usize::next_power_of_two(NBYTES_PER_STACK_GUESS * nstacks_per_job);
+        let mut buf = Vec::with_capacity(buf_capacity);
+        let (mut index, mut nstacks) = (0, 0);
+
+        loop {
+            let n = reader.read_until(b'\n', &mut buf)?;
+            if n == 0 {
+                // If we've reached the end of the data, send the final chunk to the worker
+                // threads and break from the loop, The worker threads may or may not still
+                // be alive (depending on if one errored in between the sending of the last
+                // chunk and the sending of this one), but either way we should break the loop;
+                // so there's no need to check for a `SendError` here.
+                let _ = tx_input.send(buf);
+                break;
+            }
+            let line = &buf[index..index + n];
+            index += n;
+            if collapser.would_end_stack(line) {
+                // If we've reached the end of a stack, count it.
+                nstacks += 1;
+                if nstacks == nstacks_per_job {
+                    // If we've accumulated enough stacks to make up a chunk to send to the
+                    // worker threads, try to send it.
+                    let buf_capacity = usize::next_power_of_two(buf.capacity());
+                    let chunk = mem::replace(&mut buf, Vec::with_capacity(buf_capacity));
+                    if tx_input.send(chunk).is_err() {
+                        // If sending the chunk produces a `SendError`, this means that one
+                        // of the worker threads has errored, sent a signal to all the other
+                        // worker threads to shut down, and they have all shutdown, in which
+                        // case we know there will be an error waiting for us on the error
+                        // channel; so we should stop parsing input data (i.e. break).
+                        break;
                     }
-                    continue
+                    index = 0;
+                    nstacks = 0;
                 }
+                continue
             }
+        }
 
-            // The main thread needs to drop its handle to the input sender here because
-            // that's how we signal to the worker threads that there is no more data coming
-            // on the input channel, in which case they should exit.
-            drop(tx_input);
-
-            // The main thread needs to drop its handle to the error sender here because we
-            // are about to poll the error receiver for errors, which will block until all
-            // the error senders have been dropped (including ours).
-            drop(tx_error);
-
-            // Now we poll the error channel, which will block until either:
-            // * all work has been completely successfully,
-            //   in which case the expression below will evaluate to `None`, or
-            // * an error has occurred on one of the worker theads,
-            //   in which case the expression below will evaluate to `Some(<io::Error>)`.
-            if let Some(e) = rx_error.iter().next() {
-                return Err(e);
-            }
+        // The main thread needs to drop its handle to the input sender here because
+        // that's how we signal to the worker threads that there is no more data coming
+        // on the input channel, in which case they should exit.
+        drop(tx_input);
+
+        // The main thread needs to drop its handle to the error sender here because we
+        // are about to poll the error receiver for errors, which will block until all
+        // the error senders have been dropped (including ours).
+        drop(tx_error);
+
+        // Now we poll the error channel, which will block until either:
+        // * all work has been completely successfully,
+        //   in which case the expression below will evaluate to `None`, or
+        // * an error has occurred on one of the worker theads,
+        //   in which case the expression below will evaluate to `Some(<io::Error>)`.
+        if let Some(e) = rx_error.iter().next() {
+            return Err(e);
+        }
 
-            for handle in handles {
-                handle.join().unwrap();
+        for handle in handles {
+            let worker_stats = handle.join().unwrap();
+            if let (Some(stats), Some(worker_stats)) = (collapser.stats_mut(), worker_stats) {
+                stats.add(&worker_stats);
             }
+        }
 
-            Ok(())
-        })
-        .unwrap()
-    }
+        Ok(())
+    })
+    .unwrap()
 }
 
-/// Occurrences is a HashMap, which uses:
-/// * AHashMap if single-threaded
-/// * DashMap if multi-threaded
+/// The counts of folded stacks collected by a [`ParallelCollapse`] implementation.
 ///
-/// This is public because it is part of the sealed `CollapsePrivate` trait's API, but it
-/// is in a crate-private module so is not nameable by downstream library users.
+/// Keys are stacks in the folded format, that is, frames separated by `;` with the outermost
+/// frame first. When collapsing with several threads, all the threads share the same counts.
 #[derive(Clone, Debug)]
-pub enum Occurrences {
+pub struct Occurrences(OccurrencesMap);
+
+// A HashMap, which uses:
+// * AHashMap if single-threaded
+// * DashMap if multi-threaded
+#[derive(Clone, Debug)]
+enum OccurrencesMap {
     SingleThreaded(AHashMap<String, u64>),
     #[cfg(feature = "multithreaded")]
     MultiThreaded(Arc<DashMap<String, u64, ahash::RandomState>>),
@@ -382,22 +529,22 @@ impl Occurrences {
     fn new_single_threaded() -> Self {
         let map =
             AHashMap::with_capacity_and_hasher(CAPACITY_HASHMAP, ahash::RandomState::default());
-        Occurrences::SingleThreaded(map)
+        Occurrences(OccurrencesMap::SingleThreaded(map))
     }
 
     #[cfg(feature = "multithreaded")]
     fn new_multi_threaded() -> Self {
         let map =
             DashMap::with_capacity_and_hasher(CAPACITY_HASHMAP, ahash::RandomState::default());
-        Occurrences::MultiThreaded(Arc::new(map))
+        Occurrences(OccurrencesMap::MultiThreaded(Arc::new(map)))
     }
 
     /// Inserts a key-count pair into the map. If the map did not have this key
     /// present, `None` is returned. If the map did have this key present, the
     /// value is updated, and the old value is returned.
-    pub(crate) fn insert(&mut self, key: String, count: u64) -> Option<u64> {
-        use self::Occurrences::*;
-        match self {
+    pub fn insert(&mut self, key: String, count: u64) -> Option<u64> {
+        use self::OccurrencesMap::*;
+        match &mut self.0 {
             SingleThreaded(map) => map.insert(key, count),
             #[cfg(feature = "multithreaded")]
             MultiThreaded(arc) => arc.insert(key, count),
@@ -407,18 +554,32 @@ impl Occurrences {
     /// Inserts a key-count pair into the map if the key does not already exist.
     /// If the key does already exist, adds count to the current value of the
     /// existing key.
-    pub(crate) fn insert_or_add(&mut self, key: String, count: u64) {
-        use self::Occurrences::*;
-        match self {
+    pub fn insert_or_add(&mut self, key: String, count: u64) {
+        use self::OccurrencesMap::*;
+        match &mut self.0 {
             SingleThreaded(map) => *map.entry(key).or_insert(0) += count,
             #[cfg(feature = "multithreaded")]
             MultiThreaded(arc) => *arc.entry(key).or_insert(0) += count,
         }
     }
 
-    pub(crate) fn is_concurrent(&self) -> bool {
-        use self::Occurrences::*;
-        match self {
+    /// Returns the number of stacks in the map and the sum of their counts.
+    pub(crate) fn totals(&self) -> (u64, u64) {
+        use self::OccurrencesMap::*;
+        match &self.0 {
+            SingleThreaded(map) => (map.len() as u64, map.values().sum()),
+            #[cfg(feature = "multithreaded")]
+            MultiThreaded(arc) => (
+                arc.len() as u64,
+                arc.iter().map(|entry| *entry.value()).sum(),
+            ),
+        }
+    }
+
+    /// Returns `true` if this map is shared between several threads.
+    pub fn is_concurrent(&self) -> bool {
+        use self::OccurrencesMap::*;
+        match &self.0 {
             SingleThreaded(_) => false,
             #[cfg(feature = "multithreaded")]
             MultiThreaded(_) => true,
@@ -429,9 +590,9 @@ impl Occurrences {
     where
         W: io::Write,
     {
-        use self::Occurrences::*;
-        match self {
-            SingleThreaded(ref mut map) => {
+        use self::OccurrencesMap::*;
+        match &mut self.0 {
+            SingleThreaded(map) => {
                 let mut contents: Vec<_> = map.drain().collect();
                 contents.sort();
                 for (key, value) in contents {
@@ -439,7 +600,7 @@ impl Occurrences {
                 }
             }
             #[cfg(feature = "multithreaded")]
-            MultiThreaded(ref mut arc) => {
+            MultiThreaded(arc) => {
                 let map = match Arc::get_mut(arc) {
                     Some(map) => map,
                     None => panic!(
@@ -466,6 +627,45 @@ impl Occurrences {
         writer.flush()?;
         Ok(())
     }
+
+    /// Moves the contents of the map into a [`FoldedStacks`], in the same order as
+    /// `write_and_clear` would write them.
+    pub(crate) fn drain_to_stacks(&mut self) -> FoldedStacks {
+        let mut contents = Vec::new();
+        self.drain_into(&mut contents);
+        contents.sort();
+        let mut stacks = FoldedStacks::new();
+        for (key, value) in contents {
+            stacks.add(&key, value);
+        }
+        stacks
+    }
+
+    pub(crate) fn drain_into(&mut self, contents: &mut Vec<(String, u64)>) {
+        use self::OccurrencesMap::*;
+        match &mut self.0 {
+            SingleThreaded(map) => contents.extend(map.drain()),
+            #[cfg(feature = "multithreaded")]
+            MultiThreaded(arc) => {
+                let map = match Arc::get_mut(arc) {
+                    Some(map) => map,
+                    None => panic!(
+                        "Attempting to drain the contents of a concurrent HashMap \
+                         when more than one thread has access to it, which is \
+                         not allowed."
+                    ),
+                };
+                let map = mem::replace(
+                    map,
+                    DashMap::with_capacity_and_hasher(
+                        CAPACITY_HASHMAP,
+                        ahash::RandomState::default(),
+                    ),
+                );
+                contents.extend(map);
+            }
+        }
+    }
 }
 
 /// Demangles partially demangled Rust symbols that were demangled incorrectly by profilers like
@@ -563,6 +763,46 @@ pub(crate) fn fix_partially_demangled_rust_symbol<'s>(symbol: &'s str) -> Cow<'s
     Cow::Owned(demangled)
 }
 
+/// Counts the lines that a reader has consumed, so that errors can say where they are.
+pub(crate) struct LineCounter<R> {
+    inner: R,
+    newlines: usize,
+}
+
+impl<R> LineCounter<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self { inner, newlines: 0 }
+    }
+
+    /// The number of the line that the reader is at.
+    pub(crate) fn line_number(&self) -> usize {
+        self.newlines + 1
+    }
+}
+
+impl<R: Read> Read for LineCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.newlines += buf[..n].iter().filter(|&&b| b == b'\n').count();
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for LineCounter<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // The bytes being consumed were returned by the last call to `fill_buf`, so getting them
+        // again doesn't read anything.
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.newlines += buf[..amt].iter().filter(|&&b| b == b'\n').count();
+        }
+        self.inner.consume(amt);
+    }
+}
+
 #[cfg(test)]
 pub(crate) mod testing {
     use std::collections::HashMap;
@@ -603,7 +843,7 @@ pub(crate) mod testing {
 
     pub(crate) fn test_collapse_multi<C, P>(folder: &mut C, inputs: &[P]) -> io::Result<()>
     where
-        C: Collapse + CollapsePrivate,
+        C: Collapse + ParallelCollapse,
         P: AsRef<Path>,
     {
         const MAX_THREADS: usize = 16;
@@ -634,7 +874,7 @@ pub(crate) mod testing {
 
     pub(crate) fn bench_nstacks<C, P>(folder: &mut C, inputs: &[P]) -> io::Result<()>
     where
-        C: CollapsePrivate,
+        C: ParallelCollapse,
         P: AsRef<Path>,
     {
         const MIN_LINES: usize = 2000;
@@ -663,7 +903,7 @@ pub(crate) mod testing {
                 stdout: &mut io::StdoutLock,
             ) -> io::Result<Option<Self>>
             where
-                C: CollapsePrivate,
+                C: ParallelCollapse,
             {
                 let default = folder.nstacks_per_job();
 
@@ -688,7 +928,7 @@ pub(crate) mod testing {
                     let avg_duration =
                         (durations.iter().sum::<u128>() as f64 / durations.len() as f64) as u64;
                     results.insert(nstacks_per_job, avg_duration);
-                    stdout.write_all(&[b'.'])?;
+                    stdout.write_all(b".")?;
                     stdout.flush()?;
                 }
                 Ok(Some(Self {
@@ -771,12 +1011,12 @@ pub(crate) mod testing {
             if let Some(foo) = Foo::new(folder, path, bytes, &mut stdout)? {
                 foos.push(foo);
             }
-            stdout.write_all(&[b'\n'])?;
+            stdout.write_all(b"\n")?;
             stdout.flush()?;
         }
-        stdout.write_all(&[b'\n'])?;
+        stdout.write_all(b"\n")?;
         stdout.flush()?;
-        foos.sort_by(|a, b| b.nstacks.cmp(&a.nstacks));
+        foos.sort_by_key(|foo| std::cmp::Reverse(foo.nstacks));
         for foo in foos {
             stdout.write_fmt(format_args!("{}", foo))?;
             stdout.flush()?;
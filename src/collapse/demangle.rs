@@ -0,0 +1,238 @@
+use std::borrow::Cow;
+
+/// Symbol demangling that collapsers can apply to every frame they produce.
+///
+/// Itanium C++ symbols (`_Z...`), as well as Rust symbols in both the legacy (`_ZN...E`) and
+/// the v0 (`_R...`) mangling schemes, are demangled. Symbols with an extra leading underscore, as
+/// is common on macOS, are recognized too. Anything else is left untouched, so it is safe to
+/// enable this for input that is already (partially) demangled.
+///
+/// Frames may carry a module prefix (like dtrace's `libfoo.so`\`) and an offset suffix (like
+/// `+0x1f`); these are kept as-is around the demangled symbol.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Demangle {
+    /// Remove template and generic arguments, like `<T, A>`, from demangled names.
+    ///
+    /// Qualified paths such as `<T as Trait>::method` keep their angle brackets.
+    ///
+    /// Default is `false`.
+    pub strip_generics: bool,
+
+    /// Remove the hash suffix (`::h0123456789abcdef`) from Rust legacy symbols, and the crate
+    /// disambiguators (`[0123abcd]`) from Rust v0 symbols.
+    ///
+    /// Default is `false`.
+    pub strip_rust_hash: bool,
+}
+
+impl Demangle {
+    /// Demangles the symbol in the given frame.
+    ///
+    /// Returns the frame unchanged if it does not contain a mangled symbol.
+    pub fn demangle<'a>(&self, frame: &'a str) -> Cow<'a, str> {
+        let (module, symbol) = match frame.split_once('`') {
+            Some((module, symbol)) => (Some(module), symbol),
+            None => (None, frame),
+        };
+        let (symbol, offset) = match symbol.rfind("+0x") {
+            Some(i) if symbol[i + 3..].chars().all(|c| c.is_ascii_hexdigit()) => {
+                (&symbol[..i], Some(&symbol[i..]))
+            }
+            _ => (symbol, None),
+        };
+
+        let mut demangled = match self.demangle_symbol(symbol) {
+            Some(demangled) => demangled,
+            None => return Cow::Borrowed(frame),
+        };
+        if self.strip_generics {
+            if let Cow::Owned(stripped) = strip_generic_args(&demangled) {
+                demangled = stripped;
+            }
+        }
+
+        let mut result =
+            String::with_capacity(frame.len().max(demangled.len()) + module.map_or(0, str::len));
+        if let Some(module) = module {
+            result.push_str(module);
+            result.push('`');
+        }
+        result.push_str(&demangled);
+        if let Some(offset) = offset {
+            result.push_str(offset);
+        }
+        Cow::Owned(result)
+    }
+
+    fn demangle_symbol(&self, symbol: &str) -> Option<String> {
+        // macOS prepends an extra underscore to C and C++ symbols.
+        let symbol = match symbol.strip_prefix('_') {
+            Some(stripped) if stripped.starts_with("_Z") || stripped.starts_with("_R") => stripped,
+            _ => symbol,
+        };
+        if !symbol.starts_with("_Z") && !symbol.starts_with("_R") {
+            return None;
+        }
+
+        // Rust legacy symbols are also valid Itanium symbols, so try Rust first to get
+        // Rust-specific treatment of hashes and escapes.
+        if let Ok(demangled) = rustc_demangle::try_demangle(symbol) {
+            return Some(if self.strip_rust_hash {
+                format!("{:#}", demangled)
+            } else {
+                demangled.to_string()
+            });
+        }
+
+        cpp_demangle::Symbol::new(symbol)
+            .ok()
+            .and_then(|symbol| symbol.demangle().ok())
+    }
+}
+
+/// Removes template and generic argument lists from a demangled name.
+///
+/// An argument list is an angle-bracketed group that directly follows an identifier (or `::`,
+/// for Rust's turbofish). Angle brackets that open a path segment, like in
+/// `<T as Trait>::method`, and the comparison and shift operators, are left alone.
+fn strip_generic_args(name: &str) -> Cow<'_, str> {
+    if !name.contains('<') {
+        return Cow::Borrowed(name);
+    }
+
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    for (idx, c) in name.char_indices() {
+        match c {
+            '<' if depth > 0 => depth += 1,
+            '>' if depth > 0 => depth -= 1,
+            _ if depth > 0 => {}
+            '<' if result.ends_with("operator") || result.ends_with("operator<") => {
+                result.push(c);
+            }
+            '<' if result.ends_with(is_ident) => depth = 1,
+            '<' if result.ends_with("::")
+                && result[..result.len() - 2].ends_with(is_ident)
+                && !name[idx..].starts_with("<impl ") =>
+            {
+                // turbofish, like `collect::<Vec<T>>`
+                result.truncate(result.len() - 2);
+                depth = 1;
+            }
+            _ => result.push(c),
+        }
+    }
+    Cow::Owned(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn demangle(frame: &str) -> String {
+        Demangle::default().demangle(frame).into_owned()
+    }
+
+    #[test]
+    fn demangles_cpp() {
+        assert_eq!(demangle("_Z3fooi"), "foo(int)");
+        assert_eq!(
+            demangle("_ZNSt6vectorIiSaIiEE9push_backERKi"),
+            "std::vector<int, std::allocator<int> >::push_back(int const&)"
+        );
+        assert_eq!(demangle("__ZN3foo3barEv"), "foo::bar()");
+    }
+
+    #[test]
+    fn demangles_rust() {
+        assert_eq!(
+            demangle("_ZN7inferno10flamegraph5merge6frames17hacfe2d67301633c2E"),
+            "inferno::flamegraph::merge::frames::hacfe2d67301633c2"
+        );
+        assert_eq!(demangle("_RNvCs1234_7mycrate3foo"), "mycrate[3c1c0]::foo");
+
+        let demangler = Demangle {
+            strip_rust_hash: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            demangler.demangle("_ZN7inferno10flamegraph5merge6frames17hacfe2d67301633c2E"),
+            "inferno::flamegraph::merge::frames"
+        );
+        assert_eq!(
+            demangler.demangle("_RNvCs1234_7mycrate3foo"),
+            "mycrate::foo"
+        );
+    }
+
+    #[test]
+    fn keeps_module_and_offset() {
+        assert_eq!(
+            demangle("libfoo.so`_Z3fooi+0x1f"),
+            "libfoo.so`foo(int)+0x1f"
+        );
+        assert_eq!(demangle("_Z3fooi+0x1f"), "foo(int)+0x1f");
+    }
+
+    #[test]
+    fn leaves_other_symbols_alone() {
+        for frame in [
+            "main",
+            "std::sys::unix::fs::File::open",
+            "libc.so.6`__libc_start_main+0xf3",
+            "_Z_not_mangled",
+            "[unknown]",
+        ] {
+            assert!(matches!(
+                Demangle::default().demangle(frame),
+                Cow::Borrowed(f) if f == frame
+            ));
+        }
+    }
+
+    #[test]
+    fn strips_generics() {
+        let demangler = Demangle {
+            strip_generics: true,
+            strip_rust_hash: true,
+        };
+        assert_eq!(
+            demangler.demangle("_ZNSt6vectorIiSaIiEE9push_backERKi"),
+            "std::vector::push_back(int const&)"
+        );
+        assert_eq!(
+            demangler
+                .demangle("_RINvNtCs4fqI2P2rA04_4core3ptr13drop_in_placeNtCs1234_5hello3FooEB4_"),
+            "core::ptr::drop_in_place"
+        );
+
+        let tests = [
+            (
+                "<alloc::vec::Vec<T,A> as core::ops::drop::Drop>::drop",
+                "<alloc::vec::Vec as core::ops::drop::Drop>::drop",
+            ),
+            (
+                "core::iter::Iterator::collect::<Vec<u8>>",
+                "core::iter::Iterator::collect",
+            ),
+            (
+                "core::ptr::<impl *const T>::is_null",
+                "core::ptr::<impl *const T>::is_null",
+            ),
+            ("foo::operator<<(int)", "foo::operator<<(int)"),
+            (
+                "foo<int>::operator<(foo<int> const&)",
+                "foo::operator<(foo const&)",
+            ),
+            ("foo::operator->()", "foo::operator->()"),
+            ("no_generics", "no_generics"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(strip_generic_args(input), expected);
+        }
+    }
+}
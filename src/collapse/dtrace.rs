@@ -4,7 +4,9 @@ use std::io::{self, prelude::*};
 
 use log::warn;
 
-use crate::collapse::common::{self, CollapsePrivate, Occurrences};
+use crate::collapse::common::{self, Occurrences, ParallelCollapse};
+use crate::collapse::{CollapseStats, Demangle};
+use crate::Error;
 
 /// `dtrace` folder configuration options.
 #[derive(Clone, Debug)]
@@ -19,6 +21,78 @@ pub struct Options {
     ///
     /// Default is the number of logical cores on your machine.
     pub nthreads: usize,
+
+    /// Demangle C++ and Rust symbols that `dtrace` left mangled.
+    ///
+    /// Default is `None`, which leaves symbols as they are.
+    pub demangle: Option<Demangle>,
+
+    /// Treat aggregation values as durations in nanoseconds, like those produced by off-CPU
+    /// scripts that `sum(timestamp - self->ts)`, and rescale them to the given unit.
+    ///
+    /// Stacks whose duration rounds to zero in the chosen unit are dropped.
+    ///
+    /// Default is `None`, which treats values as plain counts.
+    pub duration_unit: Option<TimeUnit>,
+
+    /// Only fold the stacks of the aggregation with this name, with or without its leading `@`.
+    ///
+    /// `dtrace` does not label the aggregations it prints, so each aggregation must be preceded
+    /// by a line holding its name, as printed by:
+    ///
+    /// ```text
+    /// printf("\n@offcpu\n"); printa(@offcpu);
+    /// ```
+    ///
+    /// Such lines are skipped when this is `None`, in which case stacks from all aggregations are
+    /// folded together. Selecting an aggregation disables multi-threaded collapsing.
+    ///
+    /// Default is `None`.
+    pub aggregation: Option<String>,
+
+    /// Fail with an [`Error::Parse`] if a stack line is neither a `module`function` frame nor an
+    /// address, and with [`Error::EmptyInput`] rather than log a warning if the input ends before
+    /// the first stack, or has no aggregation named [`Options::aggregation`]. Since line numbers
+    /// can only be counted on a single thread, this disables multithreading.
+    ///
+    /// Default is `false`.
+    pub strict: bool,
+}
+
+/// The unit that [`Options::duration_unit`] rescales durations to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TimeUnit {
+    /// Keep durations in nanoseconds.
+    #[default]
+    Nanoseconds,
+
+    /// Rescale durations to microseconds.
+    Microseconds,
+
+    /// Rescale durations to milliseconds.
+    Milliseconds,
+}
+
+impl TimeUnit {
+    /// The name of the unit, suitable for [`crate::flamegraph::Options::count_name`].
+    pub fn count_name(&self) -> &'static str {
+        match self {
+            TimeUnit::Nanoseconds => "ns",
+            TimeUnit::Microseconds => "µs",
+            TimeUnit::Milliseconds => "ms",
+        }
+    }
+
+    fn scale(&self, ns: u64) -> u64 {
+        let divisor = match self {
+            TimeUnit::Nanoseconds => return ns,
+            TimeUnit::Microseconds => 1_000,
+            TimeUnit::Milliseconds => 1_000_000,
+        };
+        // Rounds to the nearest unit, without overflowing for durations close to `u64::MAX`.
+        ns / divisor + u64::from(ns % divisor >= divisor / 2)
+    }
 }
 
 impl Default for Options {
@@ -26,6 +100,10 @@ impl Default for Options {
         Self {
             includeoffset: false,
             nthreads: *common::DEFAULT_NTHREADS,
+            demangle: None,
+            duration_unit: None,
+            aggregation: None,
+            strict: false,
         }
     }
 }
@@ -47,6 +125,18 @@ pub struct Folder {
     /// Keep track of stack string size while we consume a stack
     stack_str_size: usize,
 
+    /// The name of the aggregation whose stacks we are reading, if any has been named yet.
+    aggregation: Option<String>,
+
+    /// Whether we have seen the aggregation selected by `opt.aggregation`.
+    found_aggregation: bool,
+
+    /// The number of the line we are reading, counted from the start of the input.
+    line_number: usize,
+
+    /// What has been counted while collapsing.
+    stats: CollapseStats,
+
     opt: Options,
 }
 
@@ -55,11 +145,20 @@ impl From<Options> for Folder {
         if opt.nthreads == 0 {
             opt.nthreads = 1;
         }
+        if let Some(name) = opt.aggregation.as_mut() {
+            if let Some(stripped) = name.strip_prefix('@') {
+                *name = stripped.to_string();
+            }
+        }
         Self {
             cache_inlines: Vec::new(),
             nstacks_per_job: common::DEFAULT_NSTACKS_PER_JOB,
             stack: VecDeque::default(),
             stack_str_size: 0,
+            aggregation: None,
+            found_aggregation: false,
+            line_number: 0,
+            stats: CollapseStats::default(),
             opt,
         }
     }
@@ -71,20 +170,26 @@ impl Default for Folder {
     }
 }
 
-impl CollapsePrivate for Folder {
+impl ParallelCollapse for Folder {
     fn pre_process<R>(&mut self, reader: &mut R, _: &mut Occurrences) -> io::Result<()>
     where
         R: io::BufRead,
     {
+        self.line_number = 0;
+
         // Consumer the header...
         let mut line = Vec::new();
         loop {
             line.clear();
             if reader.read_until(0x0A, &mut line)? == 0 {
                 // We reached the end :( this should not happen.
+                if self.opt.strict {
+                    return Err(Error::EmptyInput.into());
+                }
                 warn!("File ended while skipping headers");
                 return Ok(());
             };
+            self.line_number += 1;
             if String::from_utf8_lossy(&line).trim().is_empty() {
                 return Ok(());
             }
@@ -105,16 +210,37 @@ impl CollapsePrivate for Folder {
             if reader.read_until(0x0A, &mut line)? == 0 {
                 break;
             }
+            self.line_number += 1;
             let s = String::from_utf8_lossy(&line);
             let line = s.trim();
             if line.is_empty() {
                 continue;
+            } else if let Some(name) = Self::aggregation_name(line) {
+                self.on_aggregation(name);
+            } else if !self.in_selected_aggregation() {
+                if line.parse::<u64>().is_ok() {
+                    self.stats.filtered_events += 1;
+                }
             } else if let Ok(count) = line.parse::<u64>() {
+                self.stats.events += 1;
                 self.on_stack_end(count, occurrences);
             } else {
+                if self.opt.strict && !Self::is_frame(line) {
+                    return Err(Error::parse(self.line_number, line, "Weird stack line").into());
+                }
                 self.on_stack_line(line);
             }
         }
+        if let Some(aggregation) = &self.opt.aggregation {
+            if !self.found_aggregation {
+                if self.opt.strict {
+                    return Err(Error::EmptyInput.into());
+                }
+                warn!("No aggregation named @{} found in input", aggregation);
+            }
+        }
+        self.aggregation = None;
+        self.found_aggregation = false;
         // If we reach this point in the code and there's still something in our
         // state (`self.stack` and `self.stack_str_size`), it means the input
         // did not terminate at the end of a stack; rather, it terminated in
@@ -150,9 +276,9 @@ impl CollapsePrivate for Folder {
             } else if found_empty_line {
                 if line.parse::<u64>().is_ok() {
                     return Some(found_stack_line);
-                } else if line.contains('`')
-                    || (line.starts_with("0x") && u64::from_str_radix(&line[2..], 16).is_ok())
-                {
+                } else if Self::aggregation_name(line).is_some() {
+                    continue;
+                } else if Self::is_frame(line) {
                     found_stack_line = true;
                 } else {
                     // This is not a stack or count line
@@ -235,6 +361,10 @@ impl CollapsePrivate for Folder {
             nstacks_per_job: self.nstacks_per_job,
             stack: VecDeque::default(),
             stack_str_size: 0,
+            aggregation: None,
+            found_aggregation: false,
+            line_number: 0,
+            stats: CollapseStats::default(),
             opt: self.opt.clone(),
         }
     }
@@ -248,12 +378,26 @@ impl CollapsePrivate for Folder {
     }
 
     fn nthreads(&self) -> usize {
-        self.opt.nthreads
+        // Which aggregation a stack belongs to depends on the lines before it, and line numbers
+        // depend on every line before them, neither of which the worker threads get to see.
+        if self.opt.aggregation.is_some() || self.opt.strict {
+            1
+        } else {
+            self.opt.nthreads
+        }
     }
 
     fn set_nthreads(&mut self, n: usize) {
         self.opt.nthreads = n;
     }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut CollapseStats> {
+        Some(&mut self.stats)
+    }
 }
 
 impl Folder {
@@ -297,6 +441,42 @@ impl Folder {
         )
     }
 
+    // Frames are either `module`function` or, for addresses dtrace could not resolve, `0x1234`.
+    fn is_frame(line: &str) -> bool {
+        line.contains('`')
+            || line
+                .strip_prefix("0x")
+                .is_some_and(|addr| u64::from_str_radix(addr, 16).is_ok())
+    }
+
+    // Aggregation names are not part of the dtrace output, but scripts with several aggregations
+    // can print them on a line of their own, like:
+    //
+    //     @offcpu
+    fn aggregation_name(line: &str) -> Option<&str> {
+        let name = line.strip_prefix('@')?;
+        let name = name.strip_suffix(':').unwrap_or(name);
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            Some(name)
+        } else {
+            None
+        }
+    }
+
+    fn on_aggregation(&mut self, name: &str) {
+        if self.opt.aggregation.as_deref() == Some(name) {
+            self.found_aggregation = true;
+        }
+        self.aggregation = Some(name.to_string());
+    }
+
+    fn in_selected_aggregation(&self) -> bool {
+        match &self.opt.aggregation {
+            Some(selected) => self.aggregation.as_ref() == Some(selected),
+            None => true,
+        }
+    }
+
     // DTrace doesn't properly demangle Rust function names, so fix those.
     fn fix_rust_symbol<'a>(&self, frame: &'a str) -> Cow<'a, str> {
         let mut parts = frame.splitn(2, '`');
@@ -330,12 +510,21 @@ impl Folder {
     //     unix`sys_syscall+0x10e
     //       1
     fn on_stack_line(&mut self, line: &str) {
-        let (has_inlines, could_be_cpp, has_semicolon, mut frame) = if self.opt.includeoffset {
+        let (has_inlines, mut could_be_cpp, has_semicolon, mut frame) = if self.opt.includeoffset {
             (true, true, true, line)
         } else {
             Self::remove_offset(line)
         };
 
+        let demangled;
+        if let Some(demangle) = &self.opt.demangle {
+            if let Cow::Owned(d) = demangle.demangle(frame) {
+                demangled = d;
+                frame = &demangled;
+                could_be_cpp = could_be_cpp || frame.contains("::");
+            }
+        }
+
         if could_be_cpp {
             frame = Self::uncpp(frame);
         }
@@ -372,6 +561,16 @@ impl Folder {
     }
 
     fn on_stack_end(&mut self, count: u64, occurrences: &mut Occurrences) {
+        let count = match self.opt.duration_unit {
+            Some(unit) => unit.scale(count),
+            None => count,
+        };
+        if count == 0 && self.opt.duration_unit.is_some() {
+            self.stack_str_size = 0;
+            self.stack.clear();
+            return;
+        }
+
         // allocate a string that is long enough to hold the entire stack string
         let mut stack_str = String::with_capacity(self.stack_str_size);
 
@@ -422,6 +621,7 @@ mod tests {
             "./tests/data/collapse-dtrace/flamegraph-bug.txt",
             "./tests/data/collapse-dtrace/hex-addresses.txt",
             "./tests/data/collapse-dtrace/java.txt",
+            "./tests/data/collapse-dtrace/off-cpu.txt",
             "./tests/data/collapse-dtrace/only-header-lines.txt",
             "./tests/data/collapse-dtrace/scope_with_no_argument_list.txt",
         ]
@@ -430,6 +630,18 @@ mod tests {
         .collect::<Vec<_>>()
     });
 
+    #[test]
+    fn time_units_round_without_overflowing() {
+        assert_eq!(TimeUnit::Microseconds.scale(1_499), 1);
+        assert_eq!(TimeUnit::Microseconds.scale(1_500), 2);
+        assert_eq!(TimeUnit::Milliseconds.scale(499_999), 0);
+        assert_eq!(
+            TimeUnit::Milliseconds.scale(u64::MAX),
+            u64::MAX / 1_000_000 + 1
+        );
+        assert_eq!(TimeUnit::Nanoseconds.scale(u64::MAX), u64::MAX);
+    }
+
     #[test]
     fn cpp_test() {
         let probe = "TestClass::TestClass2(const char*)[__1cJTestClass2t6Mpkc_v_]";
@@ -569,6 +781,10 @@ mod tests {
             let options = Options {
                 includeoffset: rng.random(),
                 nthreads: rng.random_range(2..=32),
+                demangle: None,
+                duration_unit: None,
+                aggregation: None,
+                strict: false,
             };
 
             for (path, input) in inputs.iter() {
@@ -0,0 +1,233 @@
+use std::io;
+use std::str::FromStr;
+
+use ahash::AHashMap;
+
+use crate::Error;
+
+/// Identifies a frame name interned in a [`FoldedStacks`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FrameId(u32);
+
+/// An in-memory collection of folded stacks and their counts.
+///
+/// This holds the same information as the folded text format produced by the collapsers and read
+/// by [`crate::flamegraph::from_lines`], where every line is a `;`-separated list of frames
+/// (outermost first) followed by a space and a count, like
+///
+/// ```text
+/// main;foo;bar 42
+/// ```
+///
+/// Frame names are interned, so each distinct name is only stored once no matter how many stacks
+/// it appears in. Adding a stack that is already present adds to its count.
+///
+/// Use [`Collapse::collapse_to_stacks`](crate::collapse::Collapse::collapse_to_stacks) to collapse
+/// profiler output into a `FoldedStacks`, and [`crate::flamegraph::from_stacks`] to render one.
+/// [`FoldedStacks::from_reader`] and [`FoldedStacks::write_to`] convert from and to the folded
+/// text format.
+#[derive(Clone, Debug, Default)]
+pub struct FoldedStacks {
+    frames: Vec<Box<str>>,
+    frame_ids: AHashMap<Box<str>, FrameId>,
+    stacks: Vec<(Box<[FrameId]>, u64)>,
+    stack_ids: AHashMap<Box<[FrameId]>, usize>,
+}
+
+impl FoldedStacks {
+    /// Creates an empty collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads stacks in the folded text format.
+    ///
+    /// Empty lines, and lines that start with `# `, are skipped. Any other line that does not end
+    /// in a space followed by an integer count is an [`Error::Parse`].
+    pub fn from_reader<R: io::BufRead>(reader: R) -> io::Result<Self> {
+        let mut stacks = Self::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("# ") {
+                continue;
+            }
+            let parsed = line
+                .rsplit_once(' ')
+                .and_then(|(stack, count)| Some((stack.trim_end(), count.parse::<u64>().ok()?)));
+            match parsed {
+                Some((stack, count)) => stacks.add(stack, count),
+                None => return Err(Error::parse(i + 1, line, "Unable to parse folded line").into()),
+            }
+        }
+        Ok(stacks)
+    }
+
+    /// Writes the stacks in the folded text format, sorted by stack.
+    pub fn write_to<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut lines: Vec<_> = self
+            .stacks
+            .iter()
+            .map(|(stack, count)| (self.join(stack), *count))
+            .collect();
+        lines.sort();
+        for (stack, count) in lines {
+            writeln!(writer, "{} {}", stack, count)?;
+        }
+        writer.flush()
+    }
+
+    /// Adds `count` to the given `;`-separated stack.
+    pub fn add(&mut self, stack: &str, count: u64) {
+        self.add_frames(stack.split(';'), count);
+    }
+
+    /// Adds `count` to the stack made up of the given frames, outermost first.
+    pub fn add_frames<'a, I>(&mut self, frames: I, count: u64)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let stack: Box<[FrameId]> = frames.into_iter().map(|f| self.intern(f)).collect();
+        self.add_ids(stack, count);
+    }
+
+    /// Adds `count` to the stack made up of the given interned frames, outermost first.
+    ///
+    /// # Panics
+    ///
+    /// If any of the frames were not interned in this collection.
+    pub fn add_frame_ids(&mut self, frames: &[FrameId], count: u64) {
+        assert!(
+            frames.iter().all(|id| (id.0 as usize) < self.frames.len()),
+            "frame id from another FoldedStacks"
+        );
+        self.add_ids(frames.into(), count);
+    }
+
+    fn add_ids(&mut self, stack: Box<[FrameId]>, count: u64) {
+        if let Some(&i) = self.stack_ids.get(&stack) {
+            self.stacks[i].1 += count;
+        } else {
+            self.stack_ids.insert(stack.clone(), self.stacks.len());
+            self.stacks.push((stack, count));
+        }
+    }
+
+    /// Returns the id of the given frame name, interning it if it is new.
+    pub fn intern(&mut self, frame: &str) -> FrameId {
+        if let Some(&id) = self.frame_ids.get(frame) {
+            return id;
+        }
+        let id = FrameId(
+            u32::try_from(self.frames.len()).expect("more than u32::MAX distinct frame names"),
+        );
+        self.frames.push(frame.into());
+        self.frame_ids.insert(frame.into(), id);
+        id
+    }
+
+    /// Returns the name of an interned frame.
+    ///
+    /// # Panics
+    ///
+    /// If the frame was not interned in this collection.
+    pub fn frame(&self, id: FrameId) -> &str {
+        &self.frames[id.0 as usize]
+    }
+
+    /// Returns the stacks, as interned frames (outermost first) with their counts, in the order
+    /// they were first added.
+    pub fn stacks(&self) -> impl Iterator<Item = (&[FrameId], u64)> + '_ {
+        self.stacks
+            .iter()
+            .map(|(stack, count)| (&stack[..], *count))
+    }
+
+    /// Returns the number of distinct stacks.
+    pub fn len(&self) -> usize {
+        self.stacks.len()
+    }
+
+    /// Returns `true` if there are no stacks.
+    pub fn is_empty(&self) -> bool {
+        self.stacks.is_empty()
+    }
+
+    /// Returns the sum of the counts of all stacks.
+    pub fn total_count(&self) -> u64 {
+        self.stacks.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Joins the names of the given frames with `;`, as in the folded text format.
+    pub fn join(&self, frames: &[FrameId]) -> String {
+        let mut stack = String::new();
+        for (i, &id) in frames.iter().enumerate() {
+            if i != 0 {
+                stack.push(';');
+            }
+            stack.push_str(self.frame(id));
+        }
+        stack
+    }
+}
+
+impl FromStr for FoldedStacks {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        Self::from_reader(s.as_bytes())
+    }
+}
+
+impl<'a> Extend<(&'a str, u64)> for FoldedStacks {
+    fn extend<T: IntoIterator<Item = (&'a str, u64)>>(&mut self, iter: T) {
+        for (stack, count) in iter {
+            self.add(stack, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn to_string(stacks: &FoldedStacks) -> String {
+        let mut out = Vec::new();
+        stacks.write_to(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn interns_frames_and_merges_stacks() {
+        let mut stacks = FoldedStacks::new();
+        stacks.add("main;foo;bar", 2);
+        stacks.add("main;foo", 1);
+        stacks.add_frames(["main", "foo", "bar"], 3);
+
+        assert_eq!(stacks.len(), 2);
+        assert_eq!(stacks.total_count(), 6);
+        assert_eq!(stacks.frames.len(), 3);
+
+        let main = stacks.intern("main");
+        assert_eq!(stacks.frame(main), "main");
+        let (first, count) = stacks.stacks().next().unwrap();
+        assert_eq!(first[0], main);
+        assert_eq!(stacks.join(first), "main;foo;bar");
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn round_trips_folded_text() {
+        let text = "main;foo 1\nmain;bar 3\n\n# comment\nmain;foo 2\n";
+        let stacks: FoldedStacks = text.parse().unwrap();
+        assert_eq!(to_string(&stacks), "main;bar 3\nmain;foo 3\n");
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert!("main;foo".parse::<FoldedStacks>().is_err());
+        assert!("main;foo 1.5".parse::<FoldedStacks>().is_err());
+    }
+}
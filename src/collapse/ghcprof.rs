@@ -3,7 +3,8 @@ use std::io::{self, BufRead};
 use log::warn;
 
 use crate::collapse::common::Occurrences;
-use crate::collapse::Collapse;
+use crate::collapse::{Collapse, CollapseStats};
+use crate::Error;
 
 // These are the identifying words of the callgraph table, note that ticks and bytes columns are optional so not present
 static START_LINE: &[&str] = &[
@@ -16,6 +17,11 @@ static START_LINE: &[&str] = &[
 pub struct Options {
     /// Column to source associated value from, default is `Source::PercentTime`.
     pub source: Source,
+
+    /// Fail with [`Error::EmptyInput`] rather than log a warning if the input has no call graph.
+    ///
+    /// Default is `false`.
+    pub strict: bool,
 }
 
 /// Which prof column to use as the cost centre of the output stacks
@@ -43,6 +49,12 @@ pub struct Folder {
     /// Function on the stack in this entry thus far.
     stack: Vec<String>,
 
+    /// The number of the line being processed.
+    line_number: usize,
+
+    /// What has been counted while collapsing.
+    stats: CollapseStats,
+
     opt: Options,
 }
 
@@ -62,12 +74,18 @@ impl Collapse for Folder {
     {
         // Consume the header...
         let mut line = Vec::new();
+        self.line_number = 0;
+        self.stats = CollapseStats::default();
         let cols = loop {
             line.clear();
             if reader.read_until(b'\n', &mut line)? == 0 {
+                if self.opt.strict {
+                    return Err(Error::EmptyInput.into());
+                }
                 warn!("File ended before start of call graph");
                 return Ok(());
             };
+            self.line_number += 1;
             let l = String::from_utf8_lossy(&line);
 
             if l.split_whitespace()
@@ -84,12 +102,16 @@ impl Collapse for Folder {
                 //   - BUT it has a max width of 9 whilst its values can exceed (but are always space separted)
                 // "%time %alloc   %time %alloc  ticks  bytes"
                 let source = match self.opt.source {
-                    Source::PercentTime => l
+                    Source::PercentTime => Ok(l
                         .find("%time")
-                        .expect("%time is present from matching START_LINE"),
+                        .expect("%time is present from matching START_LINE")),
                     // See note above about ticks and bytes columns
-                    Source::Ticks => one_off_end_of_col_before(l.as_ref(), "ticks")?,
-                    Source::Bytes => one_off_end_of_col_before(l.as_ref(), "bytes")?,
+                    Source::Ticks => one_off_end_of_col_before(l.as_ref(), "ticks"),
+                    Source::Bytes => one_off_end_of_col_before(l.as_ref(), "bytes"),
+                };
+                let source = match source {
+                    Ok(source) => source,
+                    Err(message) => return self.parse_error(&l, message),
                 };
                 break Cols {
                     cost_centre,
@@ -100,6 +122,7 @@ impl Collapse for Folder {
         };
         // Skip one line
         reader.read_until(b'\n', &mut line)?;
+        self.line_number += 1;
 
         // Process the data...
         let mut occurrences = Occurrences::new(1);
@@ -109,6 +132,7 @@ impl Collapse for Folder {
                 // The format is not expected to contain any blank lines within the callgraph
                 break;
             }
+            self.line_number += 1;
             let l = String::from_utf8_lossy(&line);
             let line = l.trim_end();
             if line.is_empty() {
@@ -119,6 +143,7 @@ impl Collapse for Folder {
         }
 
         // Write the results...
+        self.stats.count_occurrences(&occurrences);
         occurrences.write_and_clear(writer)?;
 
         // Reset the state...
@@ -151,16 +176,24 @@ impl Collapse for Folder {
         }
         None
     }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
 }
 
-fn one_off_end_of_col_before(line: &str, col: &str) -> io::Result<usize> {
+fn one_off_end_of_col_before(line: &str, col: &str) -> Result<usize, String> {
     let col_start = match line.find(col) {
         Some(col_start) => col_start,
-        _ => return invalid_data_error!("Expected '{col}' column but it was not present"),
+        _ => return Err(format!("Expected '{col}' column but it was not present")),
     };
     let col_end = match line[..col_start].rfind(|c: char| !c.is_whitespace()) {
         Some(col_end) => col_end,
-        _ => return invalid_data_error!("Expected a column before '{col}' but there was none"),
+        _ => {
+            return Err(format!(
+                "Expected a column before '{col}' but there was none"
+            ))
+        }
     };
     Ok(col_end + 1)
 }
@@ -205,7 +238,7 @@ impl Folder {
                 // If the line is not a child, pop stack to the stack before the new depth
                 self.stack.truncate(depth);
             } else if depth != prev_len {
-                return invalid_data_error!("Skipped indentation level at line:\n{}", line);
+                return self.parse_error(line, "Skipped indentation level");
             }
             // There can be non-ascii names so take care to char offset not byte offset
             let string_range = |col_start: usize| {
@@ -231,12 +264,17 @@ impl Folder {
                 self.stack
                     .push(format!("{}.{}", module.trim(), func.trim()));
                 // identical stacks from other threads can appear so need to insert or add
+                self.stats.events += 1;
                 occurrences.insert_or_add(self.stack.join(";"), self.current_cost);
             } else {
-                return invalid_data_error!("Invalid cost field: \"{}\"", cost);
+                return self.parse_error(line, format!("Invalid cost field '{}'", cost));
             }
         }
 
         Ok(())
     }
+
+    fn parse_error<T>(&self, line: &str, message: impl Into<String>) -> io::Result<T> {
+        Err(Error::parse(self.line_number, line, message).into())
+    }
 }
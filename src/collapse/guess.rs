@@ -1,11 +1,24 @@
+use std::cmp::Ordering;
 use std::io::prelude::*;
 use std::io::{self, Cursor};
 
 use log::{error, info};
 
-use crate::collapse::{self, dtrace, ghcprof, perf, sample, vsprof, vtune, xctrace, Collapse};
+use crate::collapse::{
+    self, dtrace, ghcprof, perf, recursive, sample, vsprof, vtune, xctrace, Collapse,
+    CollapseStats, Demangle, FoldedStacks,
+};
+use crate::Error;
 
-const LINES_PER_ITERATION: usize = 10;
+/// The number of lines the formats are first scored on. If none of them is a good enough match,
+/// the sample is doubled until one is, the input ends, or it reaches [`MAX_SAMPLE_LINES`].
+const SAMPLE_LINES: usize = 1000;
+
+/// The most lines the formats are scored on.
+const MAX_SAMPLE_LINES: usize = 64 * SAMPLE_LINES;
+
+/// The confidence a format needs for `guess` to use it.
+const MIN_CONFIDENCE: f64 = 0.5;
 
 /// Folder configuration options.
 #[derive(Clone, Debug)]
@@ -15,29 +28,70 @@ pub struct Options {
     ///
     /// Default is the number of logical cores on your machine.
     pub nthreads: usize,
+
+    /// Fail with [`Error::UnknownFormat`] rather than log an error if no collapser applies to the
+    /// input, and enable the strict mode of the collapser that does.
+    ///
+    /// Default is `false`.
+    pub strict: bool,
+
+    /// Demangle C++ and Rust symbols with the collapser that applies to the input, if it can.
+    ///
+    /// Default is `None`, which leaves symbols as they are.
+    pub demangle: Option<Demangle>,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             nthreads: *collapse::DEFAULT_NTHREADS,
+            strict: false,
+            demangle: None,
         }
     }
 }
 
+/// How well the input matches one of the formats that [`Folder`] can pick.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Candidate {
+    /// The name of the format, like `perf`, which is also the name of its module in
+    /// [`collapse`](crate::collapse).
+    pub format: &'static str,
+
+    /// How confident the guess is that the input is in this format, from 0 to 1.
+    ///
+    /// A format whose own check rules the input out scores 0. Otherwise, the score depends on
+    /// whether its check accepted the input and on how much of the sample its collapser could
+    /// parse in strict mode.
+    pub confidence: f64,
+
+    /// Why the format got this confidence.
+    pub reason: String,
+}
+
 /// A collapser that tries to find an appropriate implementation of `Collapse`
 /// based on the input, then delegates to that collapser if one is found.
 ///
+/// Every format is scored on a sample of the input, and the one with the highest confidence is
+/// used. The ranking of the last input collapsed is kept in [`Folder::candidates`].
+///
 /// If no applicable collapser is found, an error will be logged and
-/// nothing will be written.
+/// nothing will be written, unless [`Options::strict`] is set.
 #[derive(Clone)]
 pub struct Folder {
+    candidates: Vec<Candidate>,
+    stats: CollapseStats,
     opt: Options,
 }
 
 impl From<Options> for Folder {
     fn from(opt: Options) -> Self {
-        Self { opt }
+        Self {
+            candidates: Vec::new(),
+            stats: CollapseStats::default(),
+            opt,
+        }
     }
 }
 
@@ -47,77 +101,263 @@ impl Default for Folder {
     }
 }
 
+// The part of `Collapse` that `guess` uses, in a form that lets collapsers of different types be
+// listed together.
+trait Format {
+    fn is_applicable(&mut self, input: &str) -> Option<bool>;
+
+    fn collapse(&mut self, reader: &mut dyn BufRead, writer: &mut dyn Write) -> io::Result<()>;
+
+    fn collapse_to_stacks(&mut self, reader: &mut dyn BufRead) -> io::Result<FoldedStacks>;
+
+    fn stats(&self) -> Option<&CollapseStats>;
+}
+
+impl<C: Collapse> Format for C {
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        Collapse::is_applicable(self, input)
+    }
+
+    fn collapse(&mut self, reader: &mut dyn BufRead, writer: &mut dyn Write) -> io::Result<()> {
+        Collapse::collapse(self, reader, writer)
+    }
+
+    fn collapse_to_stacks(&mut self, reader: &mut dyn BufRead) -> io::Result<FoldedStacks> {
+        Collapse::collapse_to_stacks(self, reader)
+    }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        Collapse::stats(self)
+    }
+}
+
+// The formats to pick from, in the order that ties are broken in. A new format only has to be
+// added here.
+fn formats(
+    nthreads: usize,
+    strict: bool,
+    demangle: Option<Demangle>,
+) -> Vec<(&'static str, Box<dyn Format>)> {
+    vec![
+        (
+            "perf",
+            Box::new(perf::Folder::from(perf::Options {
+                nthreads,
+                strict,
+                demangle,
+                ..Default::default()
+            })),
+        ),
+        (
+            "dtrace",
+            Box::new(dtrace::Folder::from(dtrace::Options {
+                nthreads,
+                strict,
+                demangle,
+                ..Default::default()
+            })),
+        ),
+        (
+            "sample",
+            Box::new(sample::Folder::from(sample::Options {
+                strict,
+                demangle,
+                ..Default::default()
+            })),
+        ),
+        (
+            "vtune",
+            Box::new(vtune::Folder::from(vtune::Options {
+                strict,
+                demangle,
+                ..Default::default()
+            })),
+        ),
+        (
+            "vsprof",
+            Box::new(vsprof::Folder::from(vsprof::Options { strict, demangle })),
+        ),
+        (
+            "ghcprof",
+            Box::new(ghcprof::Folder::from(ghcprof::Options {
+                strict,
+                ..Default::default()
+            })),
+        ),
+        (
+            "xctrace",
+            Box::new(xctrace::Folder::from(xctrace::Options { demangle, strict })),
+        ),
+        (
+            "recursive",
+            Box::new(recursive::Folder::from(recursive::Options {
+                nthreads,
+                strict,
+                demangle,
+                ..Default::default()
+            })),
+        ),
+    ]
+}
+
+// What scoring a format on a sample found out that scoring it on a longer sample would find out
+// again, so that the next, doubled sample doesn't have to be parsed with that format.
+#[derive(Clone, Debug)]
+enum Settled {
+    Not,
+    RuledOut,
+    FailedOn { line: usize, message: String },
+}
+
+impl Folder {
+    /// Scores every format on `input`, which is taken to be the start of a profile, and returns
+    /// them from most to least likely.
+    ///
+    /// Since `input` may end in the middle of a stack, a format whose check accepts `input` does
+    /// not lose confidence if its collapser fails only because `input` ends.
+    pub fn rank(&self, input: &str) -> Vec<Candidate> {
+        self.score(input, false, &mut Vec::new())
+    }
+
+    /// The formats scored on the last input that was collapsed, from most to least likely.
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    // `settled` carries what was found out about each format from scoring a shorter sample that
+    // `input` starts with.
+    fn score(&self, input: &str, complete: bool, settled: &mut Vec<Settled>) -> Vec<Candidate> {
+        let nlines = input.lines().count().max(1);
+        let formats = formats(1, true, None);
+        settled.resize(formats.len(), Settled::Not);
+        let mut candidates: Vec<_> = formats
+            .into_iter()
+            .zip(settled.iter_mut())
+            .map(|((format, mut folder), settled)| {
+                let ruled_out = Candidate {
+                    format,
+                    confidence: 0.0,
+                    reason: "ruled out by its format check".to_string(),
+                };
+                if let Settled::RuledOut = settled {
+                    return ruled_out;
+                }
+                let check = folder.is_applicable(input);
+                if check == Some(false) {
+                    *settled = Settled::RuledOut;
+                    return ruled_out;
+                }
+
+                let failed_on = |line: usize, message: &str| {
+                    (
+                        line.saturating_sub(1) as f64 / nlines as f64,
+                        format!("failed on line {} of {}: {}", line, nlines, message),
+                    )
+                };
+                if let Settled::FailedOn { line, message } = settled {
+                    let (parsed, outcome) = failed_on(*line, message);
+                    return Self::candidate(format, check, parsed, outcome);
+                }
+
+                let (parsed, outcome) = match folder.collapse_to_stacks(&mut input.as_bytes()) {
+                    Ok(stacks) if stacks.is_empty() => (0.0, "found no stacks".to_string()),
+                    Ok(_) => (1.0, "parsed the sample".to_string()),
+                    Err(e) => match Error::from_io(e) {
+                        // A line that isn't the last was parsed with all of the lines after it
+                        // that it depends on, so a longer sample would fail on it too.
+                        Error::Parse { line, message, .. } => {
+                            let scored = failed_on(line, &message);
+                            if line < nlines {
+                                *settled = Settled::FailedOn { line, message };
+                            }
+                            scored
+                        }
+                        Error::EmptyInput => (0.0, "found no stacks".to_string()),
+                        e if check == Some(true) && !complete => {
+                            (1.0, format!("parsed until the sample ended ({})", e))
+                        }
+                        e => (0.0, format!("failed: {}", e)),
+                    },
+                };
+                Self::candidate(format, check, parsed, outcome)
+            })
+            .collect();
+
+        // The sort is stable, so ties are broken by the order of `formats`.
+        candidates.sort_by(|a, b| {
+            b.confidence
+                .partial_cmp(&a.confidence)
+                .unwrap_or(Ordering::Equal)
+        });
+        candidates
+    }
+
+    fn candidate(
+        format: &'static str,
+        check: Option<bool>,
+        parsed: f64,
+        outcome: String,
+    ) -> Candidate {
+        let (confidence, check) = if check == Some(true) {
+            (0.5 + parsed / 2.0, "accepted by its format check")
+        } else {
+            (parsed / 2.0, "not recognized by its format check")
+        };
+        Candidate {
+            format,
+            confidence,
+            reason: format!("{}, and {}", check, outcome),
+        }
+    }
+}
+
 impl Collapse for Folder {
-    fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
+    fn collapse<R, W>(&mut self, mut reader: R, mut writer: W) -> io::Result<()>
     where
         R: io::BufRead,
         W: io::Write,
     {
-        let mut dtrace = {
-            let options = dtrace::Options {
-                nthreads: self.opt.nthreads,
-                ..Default::default()
-            };
-            dtrace::Folder::from(options)
-        };
-        let mut perf = {
-            let options = perf::Options {
-                nthreads: self.opt.nthreads,
-                ..Default::default()
-            };
-            perf::Folder::from(options)
-        };
-        let mut sample = sample::Folder::default();
-        let mut vtune = vtune::Folder::default();
-        let mut vsprof = vsprof::Folder::default();
-        let mut xctrace = xctrace::Folder::default();
-        let mut ghcprof = ghcprof::Folder::default();
-
-        // Each Collapse impl gets its own flag in this array.
-        // It gets set to true when the impl has been ruled out.
-        let mut not_applicable = [false; 7];
-
+        self.stats = CollapseStats::default();
         let mut buffer = String::new();
+        let mut nlines = 0;
+        let mut sample_lines = SAMPLE_LINES;
+        let mut settled = Vec::new();
         loop {
             let mut eof = false;
-            for _ in 0..LINES_PER_ITERATION {
+            while nlines < sample_lines {
                 if reader.read_line(&mut buffer)? == 0 {
                     eof = true;
+                    break;
                 }
+                nlines += 1;
             }
 
-            macro_rules! try_collapse_impl {
-                ($collapse:ident, $index:expr) => {
-                    if !not_applicable[$index] {
-                        match $collapse.is_applicable(&buffer) {
-                            Some(false) => {
-                                // We can rule this collapser out.
-                                not_applicable[$index] = true;
-                            }
-                            Some(true) => {
-                                // We found a collapser that works! Let's use it.
-                                info!("Using {} collapser", stringify!($collapse));
-                                let cursor = Cursor::new(buffer).chain(reader);
-                                return $collapse.collapse(cursor, writer);
-                            }
-                            None => (), // We're not yet sure if this collapser is appropriate
-                        }
-                    }
-                };
-            }
-            try_collapse_impl!(perf, 0);
-            try_collapse_impl!(dtrace, 1);
-            try_collapse_impl!(sample, 2);
-            try_collapse_impl!(vtune, 3);
-            try_collapse_impl!(vsprof, 4);
-            try_collapse_impl!(ghcprof, 5);
-            try_collapse_impl!(xctrace, 6);
-
-            if eof {
+            self.candidates = self.score(&buffer, eof, &mut settled);
+            if eof
+                || self.candidates[0].confidence >= MIN_CONFIDENCE
+                || sample_lines >= MAX_SAMPLE_LINES
+            {
                 break;
             }
+            sample_lines *= 2;
         }
 
+        let best = &self.candidates[0];
+        if best.confidence >= MIN_CONFIDENCE {
+            info!("Using {} collapser", best.format);
+            let (_, mut folder) = formats(self.opt.nthreads, self.opt.strict, self.opt.demangle)
+                .into_iter()
+                .find(|(format, _)| *format == best.format)
+                .expect("candidates are scored from the same formats");
+            let mut cursor = Cursor::new(buffer).chain(reader);
+            let result = folder.collapse(&mut cursor, &mut writer);
+            self.stats = folder.stats().cloned().unwrap_or_default();
+            return result;
+        }
+
+        if self.opt.strict {
+            return Err(Error::UnknownFormat.into());
+        }
         error!("No applicable collapse implementation found for input");
 
         Ok(())
@@ -126,4 +366,34 @@ impl Collapse for Folder {
     fn is_applicable(&mut self, _line: &str) -> Option<bool> {
         unreachable!()
     }
+
+    /// Returns the counts of the collapser that was used, or all zeros if none applied.
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scoring_a_longer_sample_reuses_what_was_settled() {
+        let sample =
+            std::fs::read_to_string("./tests/data/collapse-vtune/bad-stack-line.csv").unwrap();
+        let longer = sample.repeat(3);
+        let folder = Folder::default();
+
+        let mut settled = Vec::new();
+        folder.score(&sample, false, &mut settled);
+        assert!(settled
+            .iter()
+            .any(|s| matches!(s, Settled::FailedOn { .. })));
+        assert!(settled.iter().any(|s| matches!(s, Settled::RuledOut)));
+
+        assert_eq!(
+            folder.score(&longer, true, &mut settled),
+            folder.score(&longer, true, &mut Vec::new())
+        );
+    }
 }
@@ -1,6 +1,10 @@
 #[macro_use]
 pub(crate) mod common;
 
+mod demangle;
+mod folded;
+mod stats;
+
 /// Stack collapsing for the output of [`dtrace`](https://www.joyent.com/dtrace).
 ///
 /// See the [crate-level documentation] for details.
@@ -53,6 +57,34 @@ pub mod vtune;
 ///   [crate-level documentation]: ../../index.html
 pub mod recursive;
 
+/// Rebuild folded stacks from a flame graph SVG made by inferno.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod svg;
+
+/// Rewrite the frames of collapsed stacks with user-supplied rules.
+///
+/// Wraps any [`Collapse`] implementation to replace parts of frame names, drop frames, or cut
+/// stacks short, as described by a [`rewrite::Rules`] file.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+#[cfg(feature = "regex")]
+pub mod rewrite;
+
+/// Collapse unbounded input, like a `perf script` pipe, in bounded memory.
+///
+/// Wraps any [`ParallelCollapse`] implementation to write periodic snapshots of the folded stacks,
+/// and to merge rare stacks into an `[other]` stack once there are too many of them.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod stream;
+
 /// Stack collapsing for the output of the [Visual Studio built-in profiler](https://docs.microsoft.com/en-us/visualstudio/profiling/profiling-feature-tour?view=vs-2019).
 ///
 /// See the [crate-level documentation] for details.
@@ -79,11 +111,18 @@ pub mod ghcprof;
 #[doc(hidden)]
 pub use self::common::DEFAULT_NTHREADS;
 
+pub use self::common::{Occurrences, ParallelCollapse, DEFAULT_NSTACKS_PER_JOB};
+pub use self::demangle::Demangle;
+pub use self::folded::{FoldedStacks, FrameId};
+pub use self::stats::CollapseStats;
+
 use std::fs::File;
 use std::io::{self, IsTerminal};
 use std::path::Path;
 
-use self::common::{CollapsePrivate, CAPACITY_READER};
+use self::common::CAPACITY_READER;
+use crate::compression;
+use crate::Error;
 
 /// The abstract behavior of stack collapsing.
 ///
@@ -91,6 +130,9 @@ use self::common::{CollapsePrivate, CAPACITY_READER};
 /// particular profiler's output (like `perf script`) and produce lines in the folded stack format
 /// expected by [`crate::flamegraph::from_lines`].
 ///
+/// Formats whose input can be split into independent stacks should implement
+/// [`ParallelCollapse`] instead, which implements this trait and collapses with several threads.
+///
 /// See also the [crate-level documentation] for details.
 ///
 ///   [crate-level documentation]: ../index.html
@@ -104,8 +146,25 @@ pub trait Collapse {
         R: io::BufRead,
         W: io::Write;
 
+    /// Collapses the contents of the provided `reader` into an in-memory [`FoldedStacks`].
+    ///
+    /// This holds the same stacks that [`Collapse::collapse`] would write, without formatting
+    /// them as text.
+    fn collapse_to_stacks<R>(&mut self, reader: R) -> io::Result<FoldedStacks>
+    where
+        R: io::BufRead,
+    {
+        let mut folded = Vec::new();
+        self.collapse(reader, &mut folded)?;
+        FoldedStacks::from_reader(&folded[..])
+    }
+
     /// Collapses the contents of the provided file (or of STDIN if `infile` is `None`) and
     /// writes folded stack lines to provided `writer`.
+    ///
+    /// Input compressed with gzip, zstd, or xz is decompressed if the cargo feature of the same
+    /// name is enabled. If a line of a file cannot be parsed, the [`Error::Parse`] that is returned
+    /// includes the file's path.
     fn collapse_file<P, W>(&mut self, infile: Option<P>, writer: W) -> io::Result<()>
     where
         P: AsRef<Path>,
@@ -115,13 +174,14 @@ pub trait Collapse {
             Some(ref path) => {
                 let file = File::open(path)?;
                 let reader = io::BufReader::with_capacity(CAPACITY_READER, file);
-                self.collapse(reader, writer)
+                self.collapse(compression::decompress(reader)?, writer)
+                    .map_err(|e| Error::with_path(e, path.as_ref()))
             }
             None => {
                 let stdin = io::stdin();
                 let stdin_guard = stdin.lock();
                 let reader = io::BufReader::with_capacity(CAPACITY_READER, stdin_guard);
-                self.collapse(reader, writer)
+                self.collapse(compression::decompress(reader)?, writer)
             }
         }
     }
@@ -146,21 +206,44 @@ pub trait Collapse {
     /// - `Some(false)` means "no, this implementation definitely won't work"
     #[allow(clippy::wrong_self_convention)]
     fn is_applicable(&mut self, input: &str) -> Option<bool>;
+
+    /// Returns what was counted while collapsing the last input, or `None` if this
+    /// implementation does not keep count.
+    ///
+    /// All the collapsers in this crate keep count.
+    fn stats(&self) -> Option<&CollapseStats> {
+        None
+    }
 }
 
 impl<T> Collapse for T
 where
-    T: CollapsePrivate,
+    T: ParallelCollapse,
 {
     fn collapse<R, W>(&mut self, reader: R, writer: W) -> io::Result<()>
     where
         R: io::BufRead,
         W: io::Write,
     {
-        <Self as CollapsePrivate>::collapse(self, reader, writer)
+        let mut occurrences = common::collapse_to_occurrences(self, reader)?;
+
+        // Write results.
+        occurrences.write_and_clear(writer)
+    }
+
+    fn collapse_to_stacks<R>(&mut self, reader: R) -> io::Result<FoldedStacks>
+    where
+        R: io::BufRead,
+    {
+        let mut occurrences = common::collapse_to_occurrences(self, reader)?;
+        Ok(occurrences.drain_to_stacks())
     }
 
     fn is_applicable(&mut self, input: &str) -> Option<bool> {
-        <Self as CollapsePrivate>::is_applicable(self, input)
+        <Self as ParallelCollapse>::is_applicable(self, input)
+    }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        <Self as ParallelCollapse>::stats(self)
     }
 }
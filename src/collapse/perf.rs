@@ -1,12 +1,18 @@
+use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::io::{self, BufRead};
 
-use crate::collapse::common::{self, CollapsePrivate, Occurrences};
+use crate::collapse::common::{self, Occurrences, ParallelCollapse};
 use crate::collapse::matcher::is_kernel;
+use crate::collapse::{CollapseStats, Demangle};
+use crate::Error;
 
 const TIDY_GENERIC: bool = true;
 const TIDY_JAVA: bool = true;
 
+/// The pseudo-module perf prints for frames of inlined functions.
+const INLINED: &str = "inlined";
+
 mod logging {
     use log::{info, warn};
 
@@ -30,6 +36,41 @@ enum StackFilter {
     SkipRemaining,
 }
 
+/// What to do with the source locations that `perf script -F +srcline` prints after each stack
+/// frame.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum SrcLine {
+    /// Parse and discard source locations.
+    #[default]
+    Ignore,
+
+    /// Append the source location to every function name, like `func (src/foo.rs:42)`.
+    Annotate,
+
+    /// Emit the source location of the innermost frame as an additional leaf frame.
+    Leaf,
+}
+
+/// What to do with the inlined functions that `perf script` prints as `(inlined)` stack frames.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum InlineFrames {
+    /// Keep inlined functions as regular frames.
+    #[default]
+    Keep,
+
+    /// Keep inlined functions, and annotate them with an `_[i]` suffix.
+    Annotate,
+
+    /// Remove inlined functions, attributing their samples to the function they were inlined into.
+    Drop,
+
+    /// Merge inlined functions into the frame of the function they were inlined into, like
+    /// `caller->inlined`.
+    Merge,
+}
+
 /// `perf` folder configuration options.
 #[derive(Clone, Debug)]
 #[non_exhaustive]
@@ -76,6 +117,28 @@ pub struct Options {
     /// In case no function is matched the whole stack is returned.
     /// Default is not omitting any.
     pub skip_after: Vec<String>,
+
+    /// How to use source locations from `perf script -F +srcline` output.
+    ///
+    /// Default is `SrcLine::Ignore`.
+    pub srcline: SrcLine,
+
+    /// How to handle frames of inlined functions.
+    ///
+    /// Default is `InlineFrames::Keep`.
+    pub inline_frames: InlineFrames,
+
+    /// Demangle C++ and Rust symbols that `perf` left mangled.
+    ///
+    /// Default is `None`, which leaves symbols as they are.
+    pub demangle: Option<Demangle>,
+
+    /// Fail with an [`Error::Parse`] rather than log a warning when a line can't be parsed, and
+    /// with [`Error::EmptyInput`] if the input has no events. Since line numbers can only be
+    /// counted on a single thread, this disables multithreading.
+    ///
+    /// Default is `false`.
+    pub strict: bool,
 }
 
 impl Default for Options {
@@ -89,6 +152,10 @@ impl Default for Options {
             include_tid: false,
             nthreads: *common::DEFAULT_NTHREADS,
             skip_after: Vec::default(),
+            srcline: SrcLine::default(),
+            inline_frames: InlineFrames::default(),
+            demangle: None,
+            strict: false,
         }
     }
 }
@@ -133,6 +200,25 @@ pub struct Folder {
     /// period of current event
     period: Option<u64>,
 
+    /// Number of frame lines seen in the current event.
+    nframes: usize,
+
+    /// Whether the last stack line produced a frame that a following source line belongs to.
+    srcline_target: bool,
+
+    /// Inlined functions waiting to be merged into the function they were inlined into.
+    inlined: Vec<String>,
+
+    /// A frame line without a module, which is an inlined function if the source line that
+    /// follows it says so.
+    moduleless: Option<String>,
+
+    /// The number of the line being processed, if it is being counted.
+    line_number: usize,
+
+    /// What has been counted while collapsing.
+    stats: CollapseStats,
+
     // Options...
     opt: Options,
 }
@@ -152,6 +238,12 @@ impl From<Options> for Folder {
             stack_filter: StackFilter::Keep,
             stack: VecDeque::default(),
             period: None,
+            nframes: 0,
+            srcline_target: false,
+            inlined: Vec::default(),
+            moduleless: None,
+            line_number: 0,
+            stats: CollapseStats::default(),
             opt,
         }
     }
@@ -163,11 +255,13 @@ impl Default for Folder {
     }
 }
 
-impl CollapsePrivate for Folder {
+impl ParallelCollapse for Folder {
     fn pre_process<R>(&mut self, reader: &mut R, occurrences: &mut Occurrences) -> io::Result<()>
     where
         R: io::BufRead,
     {
+        self.line_number = 0;
+
         // If user has provided an event filter, do nothing...
         if self.event_filter.is_some() {
             return Ok(());
@@ -183,6 +277,9 @@ impl CollapsePrivate for Folder {
         if eof {
             // If we hit EOF, it may be that the input was completely empty.
             // In that case, we don't do the event_filter assertion below.
+            if self.opt.strict && self.event_filter.is_none() {
+                return Err(Error::EmptyInput.into());
+            }
             return Ok(());
         }
 
@@ -209,6 +306,10 @@ impl CollapsePrivate for Folder {
         self.in_event = false;
         self.stack_filter = StackFilter::Keep;
         self.stack.clear();
+        self.nframes = 0;
+        self.srcline_target = false;
+        self.inlined.clear();
+        self.moduleless = None;
         Ok(())
     }
 
@@ -267,6 +368,12 @@ impl CollapsePrivate for Folder {
             stack_filter: StackFilter::Keep,
             stack: VecDeque::default(),
             period: None,
+            nframes: 0,
+            srcline_target: false,
+            inlined: Vec::default(),
+            moduleless: None,
+            line_number: 0,
+            stats: CollapseStats::default(),
             opt: self.opt.clone(),
         }
     }
@@ -280,12 +387,24 @@ impl CollapsePrivate for Folder {
     }
 
     fn nthreads(&self) -> usize {
-        self.opt.nthreads
+        if self.opt.strict {
+            1
+        } else {
+            self.opt.nthreads
+        }
     }
 
     fn set_nthreads(&mut self, n: usize) {
         self.opt.nthreads = n;
     }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut CollapseStats> {
+        Some(&mut self.stats)
+    }
 }
 
 impl Folder {
@@ -302,24 +421,28 @@ impl Folder {
         loop {
             line_buffer.clear();
             if reader.read_until(0x0A, line_buffer)? == 0 {
+                self.flush_moduleless()?;
                 if !self.stack.is_empty() {
                     self.after_event(occurrences);
                 }
                 return Ok(true);
             }
+            self.line_number += 1;
             let line = String::from_utf8_lossy(line_buffer);
             if line.starts_with('#') {
                 continue;
             }
             let line = line.trim_end();
             if line.is_empty() {
+                self.flush_moduleless()?;
                 self.after_event(occurrences);
                 return Ok(false);
             } else if self.in_event {
-                self.on_stack_line(line);
+                self.on_stack_line(line)?;
             } else {
                 assert!(self.stack.is_empty());
-                self.on_event_line(line);
+                self.on_event_line(line)?;
+                self.flush_moduleless()?;
                 if !self.stack.is_empty() {
                     // we must have hit a combined event/stack line
                     self.after_event(occurrences);
@@ -375,10 +498,11 @@ impl Folder {
     //     V8 WorkerThread 24636/25607 [000] 94564.109216: cycles:
     //     vote   913    72.176760:     257597 cycles:uppp:
     //     false 64414 20110.539270:      34467 cycles:u:  ffffffff9aa3c8de [unknown] ([unknown])
-    fn on_event_line(&mut self, line: &str) {
+    fn on_event_line(&mut self, line: &str) -> io::Result<()> {
         self.in_event = true;
 
         if let Some((comm, pid, tid, end)) = Self::event_line_parts(line) {
+            self.stats.events += 1;
             let mut by_colons = line[end..].splitn(3, ':').skip(1);
             let event = by_colons.next().and_then(|period_and_event| {
                 let mut it = period_and_event.rsplit(' ');
@@ -389,8 +513,9 @@ impl Folder {
             if let Some(event) = event {
                 if let Some(ref event_filter) = self.event_filter {
                     if event != event_filter {
+                        self.stats.filtered_events += 1;
                         self.stack_filter = StackFilter::Skip;
-                        return;
+                        return Ok(());
                     }
                 } else {
                     // By default only show events of the first encountered event type.
@@ -438,13 +563,18 @@ impl Folder {
             }
 
             if let Some(stack_line) = single_stack {
-                self.on_stack_line(stack_line);
+                self.on_stack_line(stack_line)?;
                 self.in_event = false;
             }
         } else {
+            if self.opt.strict {
+                return Err(Error::parse(self.line_number, line, "Weird event line").into());
+            }
             logging::weird_event_line(line);
+            self.stats.weird_lines += 1;
             self.in_event = false;
         }
+        Ok(())
     }
 
     fn stack_line_parts(line: &str) -> Option<(&str, &str, &str)> {
@@ -473,6 +603,84 @@ impl Folder {
         Some((pc, rawfunc, module))
     }
 
+    // when source lines are printed, perf moves the `(inlined)` marker of inlined functions
+    // from the frame line to the source line, which leaves a frame line without a module:
+    //
+    //     55d0c6e5a13d compute
+    //       /tmp/a.c:5 (inlined)
+    //     55d0c6e5a13d main+0x1d (/tmp/a.out)
+    //       /tmp/a.c:16
+    fn inlined_stack_line_parts(line: &str) -> Option<(&str, &str, &str)> {
+        let (pc, rawfunc) = line.trim().split_once(' ')?;
+        if pc.is_empty() || !pc.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let rawfunc = rawfunc.trim();
+        if rawfunc.is_empty() {
+            return None;
+        }
+        Some((pc, rawfunc, INLINED))
+    }
+
+    // with `perf script -F +srcline`, frame lines are followed by the source location of the
+    // frame, like:
+    //
+    //     5586b8f1d1a9 main+0x20 (/tmp/a.out)
+    //       /tmp/a.c:12
+    //     7f2c0b7c2d09 __libc_start_main+0xe9 (/usr/lib/libc-2.31.so)
+    //       ??:0
+    //
+    // addr2line may also append a discriminator, like `/tmp/a.c:12 (discriminator 3)`, and the
+    // source lines of inlined functions end in ` (inlined)`.
+    fn srcline_parts(line: &str) -> Option<&str> {
+        let mut line = line.trim();
+        line = line.strip_suffix(" (inlined)").unwrap_or(line);
+        if let Some(start) = line.rfind(" (discriminator ") {
+            line = &line[..start];
+        }
+        let (file, lineno) = line.rsplit_once(':')?;
+        if file.is_empty()
+            || lineno.is_empty()
+            || !(lineno == "?" || lineno.chars().all(|c| c.is_ascii_digit()))
+        {
+            return None;
+        }
+        Some(line)
+    }
+
+    fn on_srcline(&mut self, srcline: &str) {
+        if !self.srcline_target {
+            // the frame this belongs to was skipped, or we've already seen its source line
+            return;
+        }
+        self.srcline_target = false;
+
+        // perf prints `??:0` (or `??:?`) when it can't resolve the source location
+        if srcline.starts_with("??:") {
+            return;
+        }
+
+        match self.opt.srcline {
+            SrcLine::Ignore => {}
+            SrcLine::Annotate => {
+                if let Some(func) = self.stack.front_mut() {
+                    // keep annotations like `_[i]` at the end, where the palettes look for them
+                    let end = match func.rfind("_[") {
+                        Some(ai) if func.len() - ai == 4 && func.ends_with(']') => ai,
+                        _ => func.len(),
+                    };
+                    func.insert_str(end, &format!(" ({})", srcline.replace(';', ":")));
+                }
+            }
+            SrcLine::Leaf => {
+                // frames are listed leaf first, so only the first frame's location is a leaf
+                if self.nframes == 1 {
+                    self.stack.push_back(srcline.replace(';', ":"));
+                }
+            }
+        }
+    }
+
     // we have a stack line that shows one stack entry from the preceding event, like:
     //
     //     ffffffff8103ce3b native_safe_halt ([kernel.kallsyms])
@@ -483,95 +691,175 @@ impl Folder {
     //     7f533952bc77 _dl_check_map_versions+0x597 (/usr/lib/ld-2.28.so)
     //     7f53389994d0 [unknown] ([unknown])
     //                0 [unknown] ([unknown])
-    fn on_stack_line(&mut self, line: &str) {
+    fn on_stack_line(&mut self, line: &str) -> io::Result<()> {
+        let moduleless = self.moduleless.take();
+        if let Some(srcline) = Self::srcline_parts(line) {
+            if let Some(frame) = moduleless {
+                if line.ends_with(" (inlined)") {
+                    if let Some((pc, rawfunc, module)) = Self::inlined_stack_line_parts(&frame) {
+                        self.on_frame(pc, rawfunc, module);
+                    }
+                } else {
+                    self.on_weird_stack_line(self.line_number - 1, &frame)?;
+                }
+            }
+            self.on_srcline(srcline);
+            return Ok(());
+        }
+        if let Some(frame) = moduleless {
+            self.on_weird_stack_line(self.line_number - 1, &frame)?;
+        }
+        self.srcline_target = false;
+
         let should_omit = matches!(
             self.stack_filter,
             StackFilter::Skip | StackFilter::SkipRemaining
         );
         if should_omit {
+            return Ok(());
+        }
+
+        if let Some((pc, rawfunc, module)) = Self::stack_line_parts(line) {
+            self.on_frame(pc, rawfunc, module);
+        } else if Self::inlined_stack_line_parts(line).is_some() {
+            // we won't know if this is an inlined function until we see its source line
+            self.moduleless = Some(line.to_string());
+        } else {
+            self.on_weird_stack_line(self.line_number, line)?;
+        }
+        Ok(())
+    }
+
+    // a frame line that could not be parsed
+    fn on_weird_stack_line(&mut self, line_number: usize, line: &str) -> io::Result<()> {
+        if self.opt.strict {
+            return Err(Error::parse(line_number, line, "Weird stack line").into());
+        }
+        logging::weird_stack_line(line);
+        self.stats.weird_lines += 1;
+        Ok(())
+    }
+
+    // a frame line without a module that was not followed by an inlined source line
+    fn flush_moduleless(&mut self) -> io::Result<()> {
+        match self.moduleless.take() {
+            Some(frame) => self.on_weird_stack_line(self.line_number - 1, &frame),
+            None => Ok(()),
+        }
+    }
+
+    fn on_frame(&mut self, pc: &str, mut rawfunc: &str, module: &str) {
+        let inlined = module == INLINED;
+        if inlined && self.opt.inline_frames == InlineFrames::Drop {
             return;
         }
 
-        if let Some((pc, mut rawfunc, module)) = Self::stack_line_parts(line) {
-            // Strip off symbol offsets
-            if let Some(offset) = rawfunc.rfind("+0x") {
-                let end = &rawfunc[(offset + 3)..];
-                if end.chars().all(|c| char::is_ascii_hexdigit(&c)) {
-                    // it's a symbol offset!
-                    rawfunc = &rawfunc[..offset];
-                }
+        // Strip off symbol offsets
+        if let Some(offset) = rawfunc.rfind("+0x") {
+            let end = &rawfunc[(offset + 3)..];
+            if end.chars().all(|c| char::is_ascii_hexdigit(&c)) {
+                // it's a symbol offset!
+                rawfunc = &rawfunc[..offset];
             }
+        }
+
+        // skip process names?
+        // see https://github.com/brendangregg/FlameGraph/blob/f857ebc94bfe2a9bfdc4f1536ebacfb7466f69ba/stackcollapse-perf.pl#L269
+        if rawfunc.starts_with('(') {
+            return;
+        }
 
-            // skip process names?
-            // see https://github.com/brendangregg/FlameGraph/blob/f857ebc94bfe2a9bfdc4f1536ebacfb7466f69ba/stackcollapse-perf.pl#L269
-            if rawfunc.starts_with('(') {
-                return;
+        // perf mostly demangles Rust symbols,
+        // but this will fix the things it gets wrong
+        let mut rawfunc = common::fix_partially_demangled_rust_symbol(rawfunc);
+        if let Some(demangle) = &self.opt.demangle {
+            if let Cow::Owned(demangled) = demangle.demangle(&rawfunc) {
+                rawfunc = Cow::Owned(demangled);
             }
+        }
 
-            // perf mostly demangles Rust symbols,
-            // but this will fix the things it gets wrong
-            let rawfunc = common::fix_partially_demangled_rust_symbol(rawfunc);
+        // Support Java inlining by splitting on "->". After the first func, the
+        // rest are annotated with "_[i]" to mark them as inlined.
+        // See https://github.com/brendangregg/FlameGraph/pull/89.
+        for func in rawfunc.split("->") {
+            let mut func = with_module_fallback(module, func, pc, self.opt.include_addrs);
+            if TIDY_GENERIC {
+                func = tidy_generic(func);
+            }
 
-            // Support Java inlining by splitting on "->". After the first func, the
-            // rest are annotated with "_[i]" to mark them as inlined.
-            // See https://github.com/brendangregg/FlameGraph/pull/89.
-            for func in rawfunc.split("->") {
-                let mut func = with_module_fallback(module, func, pc, self.opt.include_addrs);
-                if TIDY_GENERIC {
-                    func = tidy_generic(func);
-                }
+            if TIDY_JAVA && self.pname.starts_with("java") {
+                func = tidy_java(func);
+            }
 
-                if TIDY_JAVA && self.pname.starts_with("java") {
-                    func = tidy_java(func);
+            // Annotations
+            //
+            // detect inlined when self.cache_line has funcs
+            // detect kernel from the module name; eg, frames to parse include:
+            //
+            //     ffffffff8103ce3b native_safe_halt ([kernel.kallsyms])
+            //     8c3453 tcp_sendmsg (/lib/modules/4.3.0-rc1-virtual/build/vmlinux)
+            //     7d8 ipv4_conntrack_local+0x7f8f80b8 ([nf_conntrack_ipv4])
+            //
+            // detect jit from the module name; eg:
+            //
+            //     7f722d142778 Ljava/io/PrintStream;::print (/tmp/perf-19982.map)
+            if !self.cache_line.is_empty()
+                || (inlined && self.opt.inline_frames == InlineFrames::Annotate)
+            {
+                if !func.contains("_[i]") {
+                    func.push_str("_[i]"); // inlined
                 }
+            } else if self.opt.annotate_kernel && is_kernel(module) {
+                func.push_str("_[k]"); // kernel
+            } else if self.opt.annotate_jit
+                && ((module.starts_with("/tmp/perf-") && module.ends_with(".map"))
+                    || (module.contains("/jitted-") && module.ends_with(".so")))
+                && !func.contains("_[j]")
+            {
+                func.push_str("_[j]"); // jitted
+            }
 
-                // Annotations
-                //
-                // detect inlined when self.cache_line has funcs
-                // detect kernel from the module name; eg, frames to parse include:
-                //
-                //     ffffffff8103ce3b native_safe_halt ([kernel.kallsyms])
-                //     8c3453 tcp_sendmsg (/lib/modules/4.3.0-rc1-virtual/build/vmlinux)
-                //     7d8 ipv4_conntrack_local+0x7f8f80b8 ([nf_conntrack_ipv4])
-                //
-                // detect jit from the module name; eg:
-                //
-                //     7f722d142778 Ljava/io/PrintStream;::print (/tmp/perf-19982.map)
-                if !self.cache_line.is_empty() {
-                    if !func.contains("_[i]") {
-                        func.push_str("_[i]"); // inlined
+            self.cache_line.push(func);
+        }
+
+        if inlined && self.opt.inline_frames == InlineFrames::Merge {
+            // hold on to it until we see the function it was inlined into
+            self.inlined.append(&mut self.cache_line);
+        } else {
+            if self.opt.inline_frames == InlineFrames::Merge {
+                // inlined functions are listed innermost first
+                if let Some(caller) = self.cache_line.last_mut() {
+                    for callee in self.inlined.drain(..).rev() {
+                        caller.push_str("->");
+                        caller.push_str(&callee);
                     }
-                } else if self.opt.annotate_kernel && is_kernel(module) {
-                    func.push_str("_[k]"); // kernel
-                } else if self.opt.annotate_jit
-                    && ((module.starts_with("/tmp/perf-") && module.ends_with(".map"))
-                        || (module.contains("/jitted-") && module.ends_with(".so")))
-                    && !func.contains("_[j]")
-                {
-                    func.push_str("_[j]"); // jitted
                 }
-
-                self.cache_line.push(func);
             }
 
             while let Some(func) = self.cache_line.pop() {
                 self.stack.push_front(func);
             }
+            self.nframes += 1;
+            self.srcline_target = true;
+        }
 
-            if self
-                .opt
-                .skip_after
-                .iter()
-                .any(|skip_after| rawfunc == *skip_after)
-            {
-                self.stack_filter = StackFilter::SkipRemaining;
-            }
-        } else {
-            logging::weird_stack_line(line);
+        if self
+            .opt
+            .skip_after
+            .iter()
+            .any(|skip_after| rawfunc == *skip_after)
+        {
+            self.stats.truncated_stacks += 1;
+            self.stack_filter = StackFilter::SkipRemaining;
         }
     }
 
     fn after_event(&mut self, occurrences: &mut Occurrences) {
+        // the stack ended before we found the function these were inlined into
+        for func in self.inlined.drain(..) {
+            self.stack.push_front(func);
+        }
+
         // end of stack, so emit stack entry
         if !self.stack.is_empty() {
             // allocate a string that is long enough to hold the entire stack string
@@ -602,6 +890,8 @@ impl Folder {
         self.stack_filter = StackFilter::Keep;
         self.stack.clear();
         self.period = None;
+        self.nframes = 0;
+        self.srcline_target = false;
     }
 }
 
@@ -775,6 +1065,8 @@ mod tests {
             "./tests/data/collapse-perf/java-inline.txt",
             "./tests/data/collapse-perf/weird-stack-line.txt",
             "./tests/data/collapse-perf/cpp-stacks-std-function.txt",
+            "./tests/data/collapse-perf/srcline.txt",
+            "./tests/data/collapse-perf/inline-frames.txt",
         ]
         .iter()
         .map(PathBuf::from)
@@ -892,6 +1184,10 @@ mod tests {
                 include_tid: rng.random(),
                 nthreads: rng.random_range(2..=32),
                 skip_after: Vec::default(),
+                srcline: SrcLine::default(),
+                inline_frames: InlineFrames::default(),
+                demangle: None,
+                strict: false,
             };
 
             for (path, input) in inputs.iter() {
@@ -1,5 +1,9 @@
-use super::common::{self, CollapsePrivate};
-use std::{borrow::Cow, io};
+use super::common::{self, ParallelCollapse};
+use super::{CollapseStats, Demangle};
+use crate::Error;
+use ahash::AHashSet;
+use std::borrow::Cow;
+use std::io;
 
 /// Recursive backtrace folder configuration options.
 #[derive(Clone, Debug)]
@@ -9,24 +13,64 @@ pub struct Options {
     ///
     /// Default is the number of logical cores on your machine.
     pub nthreads: usize,
+
+    /// The longest cycle of frames to fold. A cycle that is repeated right after
+    /// itself, like `parse;eval` in `main;parse;eval;parse;eval;parse`, is
+    /// folded into a single occurrence, giving `main;parse;eval;parse`.
+    ///
+    /// Default is `1`, which only folds direct recursion like `a;a;a`.
+    pub max_cycle_len: usize,
+
+    /// Drop every frame that already appears earlier in the stack, so that a
+    /// function is only shown where it was first called. This folds recursion
+    /// of any depth, whatever [`Options::max_cycle_len`] is.
+    ///
+    /// Default is `false`.
+    pub first_occurrence_only: bool,
+
+    /// Demangle C++ and Rust symbols in the input before folding recursion.
+    ///
+    /// Default is `None`, which leaves symbols as they are.
+    pub demangle: Option<Demangle>,
+
+    /// Report a line that is not a stack followed by a count as an [`Error::Parse`] with its line
+    /// number, rather than as a plain [`std::io::ErrorKind::InvalidData`] error, and fail with
+    /// [`Error::EmptyInput`] if the input has no stacks. Since line numbers can only be counted
+    /// on a single thread, this disables multithreading.
+    ///
+    /// Default is `false`.
+    pub strict: bool,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             nthreads: *common::DEFAULT_NTHREADS,
+            max_cycle_len: 1,
+            first_occurrence_only: false,
+            demangle: None,
+            strict: false,
         }
     }
 }
 
 /// A "middleware" folder that receives and outputs the folded stack format
-/// expected by [`crate::flamegraph::from_lines`], collapsing direct recursive
+/// expected by [`crate::flamegraph::from_lines`], collapsing recursive
 /// backtraces.
+///
+/// Folding only removes frames, and the samples of stacks that end up the
+/// same are added together, so the total sample count is unchanged.
 #[derive(Clone)]
 pub struct Folder {
     /// The number of stacks per job to send to the threadpool.
     nstacks_per_job: usize,
 
+    /// The number of the line being processed.
+    line_number: usize,
+
+    /// What has been counted while collapsing.
+    stats: CollapseStats,
+
     // Options...
     opt: Options,
 }
@@ -36,8 +80,13 @@ impl From<Options> for Folder {
         if opt.nthreads == 0 {
             opt.nthreads = 1;
         }
+        if opt.max_cycle_len == 0 {
+            opt.max_cycle_len = 1;
+        }
         Self {
             nstacks_per_job: common::DEFAULT_NSTACKS_PER_JOB,
+            line_number: 0,
+            stats: CollapseStats::default(),
             opt,
         }
     }
@@ -49,7 +98,7 @@ impl Default for Folder {
     }
 }
 
-impl CollapsePrivate for Folder {
+impl ParallelCollapse for Folder {
     fn pre_process<R>(
         &mut self,
         _reader: &mut R,
@@ -59,6 +108,7 @@ impl CollapsePrivate for Folder {
         R: std::io::BufRead,
     {
         // Don't expect any header.
+        self.line_number = 0;
         Ok(())
     }
 
@@ -72,10 +122,25 @@ impl CollapsePrivate for Folder {
     {
         for line in reader.lines() {
             let line = line?;
-            let (stack, count) = Self::line_parts(&line)
-                .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+            self.line_number += 1;
+            let Some((stack, count)) = Self::line_parts(&line) else {
+                if self.opt.strict {
+                    return Err(Error::parse(
+                        self.line_number,
+                        &line,
+                        "Unable to parse folded line",
+                    )
+                    .into());
+                }
+                return invalid_data_error!("Unable to parse folded line: {}", line);
+            };
+            self.stats.events += 1;
 
-            occurrences.insert_or_add(Self::collapse_stack(stack.into()).into_owned(), count);
+            let stack = self.demangle_stack(stack.into());
+            occurrences.insert_or_add(self.collapse_stack(stack).into_owned(), count);
+        }
+        if self.opt.strict && self.stats.events == 0 {
+            return Err(Error::EmptyInput.into());
         }
         Ok(())
     }
@@ -89,10 +154,11 @@ impl CollapsePrivate for Folder {
         self.clone()
     }
 
-    fn is_applicable(&mut self, _input: &str) -> Option<bool> {
-        // It seems doubtful that the user would ever want to guess to collapse
-        // recursive traces, so let's just never consider ourselves applicable.
-        Some(false)
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        // Every line of folded input is a stack followed by a count.
+        let mut lines = input.lines().peekable();
+        lines.peek()?;
+        Some(lines.all(|line| Self::line_parts(line).is_some()))
     }
 
     fn nstacks_per_job(&self) -> usize {
@@ -104,12 +170,24 @@ impl CollapsePrivate for Folder {
     }
 
     fn nthreads(&self) -> usize {
-        self.opt.nthreads
+        if self.opt.strict {
+            1
+        } else {
+            self.opt.nthreads
+        }
     }
 
     fn set_nthreads(&mut self, n: usize) {
         self.opt.nthreads = n;
     }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut CollapseStats> {
+        Some(&mut self.stats)
+    }
 }
 
 impl Folder {
@@ -118,7 +196,37 @@ impl Folder {
             .and_then(|(stack, count)| Some((stack, count.parse().ok()?)))
     }
 
-    fn collapse_stack(stack: Cow<str>) -> Cow<str> {
+    fn demangle_stack<'a>(&self, stack: Cow<'a, str>) -> Cow<'a, str> {
+        let Some(demangle) = &self.opt.demangle else {
+            return stack;
+        };
+
+        let mut result = String::with_capacity(stack.len());
+        let mut demangled = false;
+        for (i, frame) in stack.split(';').enumerate() {
+            if i > 0 {
+                result.push(';');
+            }
+            let frame = demangle.demangle(frame);
+            demangled |= matches!(frame, Cow::Owned(_));
+            result.push_str(&frame);
+        }
+
+        if demangled {
+            result.into()
+        } else {
+            stack
+        }
+    }
+
+    fn collapse_stack<'a>(&self, stack: Cow<'a, str>) -> Cow<'a, str> {
+        if self.opt.first_occurrence_only {
+            return Self::keep_first_occurrences(stack);
+        }
+        if self.opt.max_cycle_len > 1 {
+            return Self::fold_cycles(stack, self.opt.max_cycle_len);
+        }
+
         // First, determine whether we can avoid allocation by just returning
         // the original stack (in the case that there is no recursion, which is
         // likely the mainline case).
@@ -144,6 +252,44 @@ impl Folder {
         result.into()
     }
 
+    /// Fold every cycle of up to `max_cycle_len` frames that is repeated right
+    /// after itself. Shorter cycles are folded first.
+    fn fold_cycles(stack: Cow<str>, max_cycle_len: usize) -> Cow<str> {
+        let mut frames: Vec<&str> = Vec::new();
+        let mut folded = false;
+        for frame in stack.split(';') {
+            frames.push(frame);
+            // Folding a cycle can leave the frames before it ending in
+            // another repeated cycle, so keep going until there are none.
+            while let Some(len) = (1..=max_cycle_len).find(|&len| {
+                frames.len() >= 2 * len
+                    && frames[frames.len() - len..] == frames[frames.len() - 2 * len..][..len]
+            }) {
+                frames.truncate(frames.len() - len);
+                folded = true;
+            }
+        }
+
+        if folded {
+            frames.join(";").into()
+        } else {
+            stack
+        }
+    }
+
+    /// Drop every frame that already appears earlier in the stack.
+    fn keep_first_occurrences(stack: Cow<str>) -> Cow<str> {
+        let mut seen = AHashSet::new();
+        let frames: Vec<&str> = stack
+            .split(';')
+            .filter(|frame| seen.insert(*frame))
+            .collect();
+        if frames.len() == stack.split(';').count() {
+            return stack;
+        }
+        frames.join(";").into()
+    }
+
     /// Determine whether or not a stack contains direct recursion.
     fn is_recursive(stack: &str) -> bool {
         let mut last = None;
@@ -152,13 +298,12 @@ impl Folder {
                 None => {
                     last = Some(current);
                 }
-                Some(l) => {
-                    if l == current {
-                        // Recursion!
-                        return true;
-                    } else {
-                        last = Some(current);
-                    }
+                Some(l) if l == current => {
+                    // Recursion!
+                    return true;
+                }
+                Some(_) => {
+                    last = Some(current);
                 }
             }
         }
@@ -173,22 +318,71 @@ mod test {
 
     #[test]
     fn test_collapse_stack() {
-        assert_eq!(Folder::collapse_stack("".into()), "");
-        assert_eq!(Folder::collapse_stack("single".into()), "single");
+        let folder = Folder::default();
+        assert_eq!(folder.collapse_stack("".into()), "");
+        assert_eq!(folder.collapse_stack("single".into()), "single");
         assert_eq!(
-            Folder::collapse_stack("not;recursive".into()),
+            folder.collapse_stack("not;recursive".into()),
             "not;recursive"
         );
         assert_eq!(
-            Folder::collapse_stack("has;some;some;recursion;recursion".into()),
+            folder.collapse_stack("has;some;some;recursion;recursion".into()),
             "has;some;recursion"
         );
         assert_eq!(
-            Folder::collapse_stack("co;recursive;co;recursive".into()),
+            folder.collapse_stack("co;recursive;co;recursive".into()),
             "co;recursive;co;recursive"
         );
     }
 
+    #[test]
+    fn test_collapse_stack_cycles() {
+        let folder = Folder::from(Options {
+            max_cycle_len: 2,
+            ..Default::default()
+        });
+        assert_eq!(
+            folder.collapse_stack("not;recursive".into()),
+            "not;recursive"
+        );
+        assert_eq!(
+            folder.collapse_stack("has;some;some;recursion;recursion".into()),
+            "has;some;recursion"
+        );
+        assert_eq!(
+            folder.collapse_stack("co;recursive;co;recursive".into()),
+            "co;recursive"
+        );
+        assert_eq!(
+            folder.collapse_stack("main;parse;eval;parse;eval;parse;helper".into()),
+            "main;parse;eval;parse;helper"
+        );
+        // Folding `b;b` leaves `a;b;a;b`, which is then folded too.
+        assert_eq!(folder.collapse_stack("a;b;a;b;b".into()), "a;b");
+        // Cycles longer than the maximum are kept.
+        assert_eq!(folder.collapse_stack("a;b;c;a;b;c".into()), "a;b;c;a;b;c");
+    }
+
+    #[test]
+    fn test_collapse_stack_first_occurrence_only() {
+        let folder = Folder::from(Options {
+            first_occurrence_only: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            folder.collapse_stack("not;recursive".into()),
+            "not;recursive"
+        );
+        assert_eq!(
+            folder.collapse_stack("main;a;b;c;a;b;c;d".into()),
+            "main;a;b;c;d"
+        );
+        assert_eq!(
+            folder.collapse_stack("main;parse;eval;helper;parse;eval".into()),
+            "main;parse;eval;helper"
+        );
+    }
+
     #[test]
     fn test_line_parts() {
         assert_eq!(
@@ -0,0 +1,384 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::collapse::{Collapse, CollapseStats, FoldedStacks};
+use crate::Error;
+
+/// An ordered list of rules that rewrite the frames of collapsed stacks.
+///
+/// Rules are usually read from a file with [`Rules::from_file`]. Each non-empty line of the file
+/// that does not start with `#` holds one rule: a keyword, followed by one or more
+/// [regular expressions](https://docs.rs/regex/latest/regex/#syntax) or replacement strings,
+/// each terminated by the same delimiter, like with `sed`. The delimiter is the first character
+/// after the keyword, so it can be picked to not clash with the pattern. A delimiter that is
+/// preceded by a backslash, like `\/`, is part of the pattern or replacement rather than the end
+/// of it, and always matches the delimiter itself.
+///
+/// ```text
+/// # Strip argument lists from C++ frames.
+/// replace /\(.*\)$//
+/// # Hide hex addresses, like `diff-folded --strip-hex` does.
+/// replace /0x[0-9a-fA-F]+/0x.../
+/// # Remove the JIT prefix from frames, keeping the rest of the name.
+/// replace |^LazyCompile:\*?(.*)$|$1|
+/// # Drop frames entirely.
+/// drop /^\[unknown\]$/
+/// # Drop all the frames `malloc` calls, keeping `malloc` itself.
+/// truncate-after /^malloc$/
+/// # Drop all the frames that call `main.init`, like `collapse-perf --skip-after` does.
+/// skip-after /^main\.init$/
+/// # Shorten paths, escaping the delimiter.
+/// replace /^.*\/src\//src\//
+/// ```
+///
+/// Rules are applied to every frame, in the order they are listed in. Frames that a `replace`
+/// rule leaves empty are dropped, as are stacks that end up without any frames.
+#[derive(Clone, Debug, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+enum Rule {
+    Replace { pattern: Regex, replacement: String },
+    Drop(Regex),
+    TruncateAfter(Regex),
+    SkipAfter(Regex),
+}
+
+impl Rules {
+    /// Parses rules in the format described in the [type-level documentation](Rules).
+    ///
+    /// A line that is not a valid rule is an [`Error::Parse`].
+    pub fn parse(rules: &str) -> io::Result<Self> {
+        let mut parsed = Vec::new();
+        for (i, line) in rules.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match Rule::parse(line) {
+                Ok(rule) => parsed.push(rule),
+                Err(e) => {
+                    let message = format!("Invalid rewrite rule ({})", e);
+                    return Err(Error::parse(i + 1, line, message).into());
+                }
+            }
+        }
+        Ok(Rules { rules: parsed })
+    }
+
+    /// Reads and parses rules from the given file.
+    ///
+    /// An [`Error::Parse`] includes the path of the file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        Self::parse(&fs::read_to_string(path)?).map_err(|e| Error::with_path(e, path))
+    }
+
+    /// Returns `true` if there are no rules to apply.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Applies the rules to the frames of the given `;`-separated stack.
+    ///
+    /// Returns `None` if no frames are left after the rules have been applied.
+    pub fn rewrite_stack(&self, stack: &str) -> Option<String> {
+        let frames = self.rewrite(stack.split(';').map(str::to_string).collect());
+        if frames.is_empty() {
+            None
+        } else {
+            Some(frames.join(";"))
+        }
+    }
+
+    fn rewrite(&self, mut frames: Vec<String>) -> Vec<String> {
+        for rule in &self.rules {
+            match rule {
+                Rule::Replace {
+                    pattern,
+                    replacement,
+                } => {
+                    for frame in frames.iter_mut() {
+                        let rewritten = pattern.replace_all(frame, replacement.as_str());
+                        if rewritten != *frame {
+                            // Semicolons would split the frame when the stack is read back in.
+                            *frame = rewritten.replace(';', ":");
+                        }
+                    }
+                    frames.retain(|frame| !frame.is_empty());
+                }
+                Rule::Drop(pattern) => frames.retain(|frame| !pattern.is_match(frame)),
+                Rule::TruncateAfter(pattern) => {
+                    if let Some(i) = frames.iter().position(|frame| pattern.is_match(frame)) {
+                        frames.truncate(i + 1);
+                    }
+                }
+                Rule::SkipAfter(pattern) => {
+                    if let Some(i) = frames.iter().rposition(|frame| pattern.is_match(frame)) {
+                        frames.drain(..i);
+                    }
+                }
+            }
+        }
+        frames
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Result<Self, String> {
+        let (keyword, rest) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("missing pattern in `{}`", line))?;
+        let nargs = if keyword == "replace" { 2 } else { 1 };
+        let args = Self::delimited_args(rest.trim_start(), nargs)?;
+        let pattern = Regex::new(&args[0]).map_err(|e| e.to_string())?;
+        match keyword {
+            "replace" => Ok(Rule::Replace {
+                pattern,
+                replacement: args[1].clone(),
+            }),
+            "drop" => Ok(Rule::Drop(pattern)),
+            "truncate-after" => Ok(Rule::TruncateAfter(pattern)),
+            "skip-after" => Ok(Rule::SkipAfter(pattern)),
+            _ => Err(format!("unknown rule `{}`", keyword)),
+        }
+    }
+
+    // Splits `/foo/bar/` into `foo` and `bar`, using the first character as the delimiter. An
+    // escaped delimiter in the pattern, which is the first argument, is escaped for the regex
+    // too, in case the delimiter is a character like `|` that means something there.
+    fn delimited_args(s: &str, nargs: usize) -> Result<Vec<String>, String> {
+        let mut chars = s.chars();
+        let delimiter = chars.next().ok_or_else(|| "missing pattern".to_string())?;
+        let mut args = vec![String::new()];
+        let mut escaped = false;
+        for c in chars {
+            let in_pattern = args.len() == 1;
+            let arg = args.last_mut().expect("there is always an argument");
+            if escaped {
+                escaped = false;
+                if c != delimiter {
+                    arg.push('\\');
+                    arg.push(c);
+                } else if in_pattern {
+                    arg.push_str(&regex::escape(&c.to_string()));
+                } else {
+                    arg.push(c);
+                }
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == delimiter {
+                args.push(String::new());
+            } else {
+                arg.push(c);
+            }
+        }
+        if escaped {
+            args.last_mut()
+                .expect("there is always an argument")
+                .push('\\');
+        }
+
+        // The arguments are followed by whatever is after the last delimiter.
+        match args.pop() {
+            Some(trailing) if args.len() == nargs && trailing.trim().is_empty() => Ok(args),
+            _ => Err(format!(
+                "expected {} arguments delimited by `{}`",
+                nargs, delimiter
+            )),
+        }
+    }
+}
+
+/// A "middleware" folder that applies [`Rules`] to the output of another [`Collapse`]
+/// implementation.
+///
+/// Stacks that end up the same after rewriting are merged.
+#[derive(Clone)]
+pub struct Folder<C> {
+    inner: C,
+    rules: Rules,
+    stats: Option<CollapseStats>,
+}
+
+impl<C> Folder<C> {
+    /// Creates a folder that rewrites the stacks produced by `inner` with the given `rules`.
+    pub fn new(inner: C, rules: Rules) -> Self {
+        Self {
+            inner,
+            rules,
+            stats: None,
+        }
+    }
+
+    /// Returns the wrapped folder.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C> Collapse for Folder<C>
+where
+    C: Collapse,
+{
+    fn collapse<R, W>(&mut self, reader: R, writer: W) -> io::Result<()>
+    where
+        R: io::BufRead,
+        W: io::Write,
+    {
+        if self.rules.is_empty() {
+            self.stats = None;
+            return self.inner.collapse(reader, writer);
+        }
+        self.collapse_to_stacks(reader)?.write_to(writer)
+    }
+
+    fn collapse_to_stacks<R>(&mut self, reader: R) -> io::Result<FoldedStacks>
+    where
+        R: io::BufRead,
+    {
+        self.stats = None;
+        let stacks = self.inner.collapse_to_stacks(reader)?;
+        if self.rules.is_empty() {
+            return Ok(stacks);
+        }
+
+        let mut rewritten = FoldedStacks::new();
+        for (frames, count) in stacks.stacks() {
+            let frames = frames.iter().map(|&id| stacks.frame(id).to_string());
+            let frames = self.rules.rewrite(frames.collect());
+            if !frames.is_empty() {
+                rewritten.add_frames(frames.iter().map(String::as_str), count);
+            }
+        }
+        self.stats = self.inner.stats().cloned().map(|mut stats| {
+            stats.count_stacks(&rewritten);
+            stats
+        });
+        Ok(rewritten)
+    }
+
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        self.inner.is_applicable(input)
+    }
+
+    /// Returns the counts of the wrapped folder, with the stacks counted after rewriting.
+    fn stats(&self) -> Option<&CollapseStats> {
+        self.stats.as_ref().or_else(|| self.inner.stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn rewrite(rules: &str, stack: &str) -> Option<String> {
+        Rules::parse(rules).unwrap().rewrite_stack(stack)
+    }
+
+    #[test]
+    fn replaces_in_every_frame() {
+        assert_eq!(
+            rewrite(r"replace /\(.*\)$//", "main;foo(int);bar(char const*)"),
+            Some("main;foo;bar".to_string())
+        );
+        assert_eq!(
+            rewrite("replace |0x[0-9a-f]+|0x...|", "a`0x7f12;b`0xbeef+0x1"),
+            Some("a`0x...;b`0x...+0x...".to_string())
+        );
+        assert_eq!(
+            rewrite(
+                r"replace /^LazyCompile:\*?(.*)$/$1/",
+                "main;LazyCompile:*foo"
+            ),
+            Some("main;foo".to_string())
+        );
+        assert_eq!(
+            rewrite("replace /^thunk$//", "main;thunk;foo"),
+            Some("main;foo".to_string())
+        );
+        assert_eq!(rewrite("replace /::/;/", "a::b"), Some("a:b".to_string()));
+    }
+
+    #[test]
+    fn drops_frames() {
+        assert_eq!(
+            rewrite(r"drop /^\[unknown\]$/", "main;[unknown];foo"),
+            Some("main;foo".to_string())
+        );
+        assert_eq!(rewrite("drop /.*/", "main;foo"), None);
+    }
+
+    #[test]
+    fn escapes_delimiters() {
+        assert_eq!(
+            rewrite(r"replace /^.*\/src\//src\//", "main;/home/me/src/lib.rs"),
+            Some("main;src/lib.rs".to_string())
+        );
+        // `|` is escaped for the regex too, so it matches itself rather than anything.
+        assert_eq!(
+            rewrite(r"replace |a\|b|a\|c|", "a|b;ab"),
+            Some("a|c;ab".to_string())
+        );
+        assert_eq!(
+            rewrite(r"drop /\(anonymous\)/", "main;(anonymous)"),
+            Some("main".to_string())
+        );
+    }
+
+    #[test]
+    fn truncates_and_skips() {
+        assert_eq!(
+            rewrite("truncate-after /^malloc$/", "main;malloc;sbrk;mmap"),
+            Some("main;malloc".to_string())
+        );
+        assert_eq!(
+            rewrite("skip-after /^init/", "start;init;main;init2;foo"),
+            Some("init2;foo".to_string())
+        );
+    }
+
+    #[test]
+    fn applies_rules_in_order() {
+        let rules = "\
+# drop the argument lists first, so that `foo` matches below
+replace /\\(.*\\)$//
+
+truncate-after /^foo$/
+";
+        assert_eq!(
+            rewrite(rules, "main;foo(int);bar(int)"),
+            Some("main;foo".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for rules in [
+            "frobnicate /foo/",
+            "drop",
+            "drop /foo",
+            "drop /foo/bar/",
+            "replace /foo/",
+            "drop /(/",
+        ] {
+            assert!(Rules::parse(rules).is_err(), "{} should not parse", rules);
+        }
+
+        let error = Rules::parse("# comment\ndrop /foo/\ndrop /(/\n").unwrap_err();
+        match Error::from_io(error) {
+            Error::Parse { line, text, .. } => {
+                assert_eq!(line, 3);
+                assert_eq!(text, "drop /(/");
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
@@ -1,9 +1,11 @@
+use std::borrow::Cow;
 use std::io::{self, BufRead};
 
 use log::warn;
 
 use crate::collapse::common::{self, Occurrences};
-use crate::collapse::Collapse;
+use crate::collapse::{Collapse, CollapseStats, Demangle};
+use crate::Error;
 
 // The set of symbols to ignore for 'waiting' threads, for ease of use.
 // This will hide waiting threads from the view, making it easier to
@@ -36,6 +38,16 @@ pub struct Options {
     ///
     /// Default is `false`.
     pub no_modules: bool,
+
+    /// Demangle C++ and Rust symbols that `sample` left mangled.
+    ///
+    /// Default is `None`, which leaves symbols as they are.
+    pub demangle: Option<Demangle>,
+
+    /// Fail with [`Error::EmptyInput`] rather than log a warning if the input has no call graph.
+    ///
+    /// Default is `false`.
+    pub strict: bool,
 }
 
 /// A stack collapser for the output of `sample` on macOS.
@@ -50,6 +62,12 @@ pub struct Folder {
     /// Function on the stack in this entry thus far.
     stack: Vec<String>,
 
+    /// The number of the line being processed.
+    line_number: usize,
+
+    /// What has been counted while collapsing.
+    stats: CollapseStats,
+
     opt: Options,
 }
 
@@ -61,12 +79,18 @@ impl Collapse for Folder {
     {
         // Consume the header...
         let mut line = Vec::new();
+        self.line_number = 0;
+        self.stats = CollapseStats::default();
         loop {
             line.clear();
             if reader.read_until(0x0A, &mut line)? == 0 {
+                if self.opt.strict {
+                    return Err(Error::EmptyInput.into());
+                }
                 warn!("File ended before start of call graph");
                 return Ok(());
             };
+            self.line_number += 1;
             let l = String::from_utf8_lossy(&line);
             if l.starts_with(START_LINE) {
                 break;
@@ -80,6 +104,7 @@ impl Collapse for Folder {
             if reader.read_until(0x0A, &mut line)? == 0 {
                 return invalid_data_error!("File ended before end of call graph");
             }
+            self.line_number += 1;
             let l = String::from_utf8_lossy(&line);
             let line = l.trim_end();
             if line.is_empty() {
@@ -90,11 +115,12 @@ impl Collapse for Folder {
                 self.write_stack(&mut occurrences);
                 break;
             } else {
-                return invalid_data_error!("Stack line doesn't start with 4 spaces:\n{}", line);
+                return self.parse_error(line, "Stack line doesn't start with 4 spaces");
             }
         }
 
         // Write the results...
+        self.stats.count_occurrences(&occurrences);
         occurrences.write_and_clear(writer)?;
 
         // Reset the state...
@@ -127,6 +153,10 @@ impl Collapse for Folder {
         }
         None
     }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
 }
 
 impl From<Options> for Folder {
@@ -185,10 +215,7 @@ impl Folder {
         if let Some(indent_chars) = line[4..].find(|c| !Self::is_indent_char(c)) {
             // Each indent is two characters
             if indent_chars % 2 != 0 {
-                return invalid_data_error!(
-                    "Odd number of indentation characters for line:\n{}",
-                    line
-                );
+                return self.parse_error(line, "Odd number of indentation characters");
             }
 
             let prev_depth = self.stack.len();
@@ -204,7 +231,7 @@ impl Folder {
                     self.stack.pop();
                 }
             } else if depth > prev_depth + 1 {
-                return invalid_data_error!("Skipped indentation level at line:\n{}", line);
+                return self.parse_error(line, "Skipped indentation level");
             }
 
             if let Some((samples, func, module)) = self.line_parts(&line[4 + indent_chars..]) {
@@ -214,26 +241,35 @@ impl Folder {
                     // sample count at the top of the stack.
                     self.current_samples = samples;
                     // sample doesn't properly demangle Rust symbols, so fix those.
-                    let func = common::fix_partially_demangled_rust_symbol(func);
+                    let mut func = common::fix_partially_demangled_rust_symbol(func);
+                    if let Some(demangle) = &self.opt.demangle {
+                        if let Cow::Owned(demangled) = demangle.demangle(&func) {
+                            func = Cow::Owned(demangled);
+                        }
+                    }
                     if module.is_empty() {
                         self.stack.push(func.to_string());
                     } else {
                         self.stack.push(format!("{}`{}", module, func));
                     }
                 } else {
-                    return invalid_data_error!("Invalid samples field: {}", samples);
+                    return self.parse_error(line, format!("Invalid samples field '{}'", samples));
                 }
             } else {
-                return invalid_data_error!("Unable to parse stack line:\n{}", line);
+                return self.parse_error(line, "Unable to parse stack line");
             }
         } else {
-            return invalid_data_error!("Found stack line with only indent characters:\n{}", line);
+            return self.parse_error(line, "Found stack line with only indent characters");
         }
 
         Ok(())
     }
 
-    fn write_stack(&self, occurrences: &mut Occurrences) {
+    fn parse_error<T>(&self, line: &str, message: impl Into<String>) -> io::Result<T> {
+        Err(Error::parse(self.line_number, line, message).into())
+    }
+
+    fn write_stack(&mut self, occurrences: &mut Occurrences) {
         if let Some(func) = self.stack.last() {
             for symbol in IGNORE_SYMBOLS {
                 if func.ends_with(symbol) {
@@ -242,6 +278,7 @@ impl Folder {
                 }
             }
         }
+        self.stats.events += 1;
         occurrences.insert(self.stack.join(";"), self.current_samples);
     }
 }
@@ -0,0 +1,126 @@
+use std::fmt;
+
+#[cfg(feature = "regex")]
+use super::FoldedStacks;
+use super::Occurrences;
+
+/// Counts of what a collapser did with its input, as returned by
+/// [`Collapse::stats`](crate::collapse::Collapse::stats).
+///
+/// The counts are for the last input collapsed. A count that does not apply to a format, like
+/// [`CollapseStats::truncated_stacks`] for formats without a `skip_after` option, is always 0.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CollapseStats {
+    /// The number of events, or stacks for formats without events, that were read.
+    pub events: u64,
+
+    /// The number of events that were skipped because they were of another event type, or in
+    /// another aggregation, than the one being collapsed.
+    pub filtered_events: u64,
+
+    /// The number of lines that could not be parsed and were dropped.
+    pub weird_lines: u64,
+
+    /// The number of stacks that were cut short by `skip_after`.
+    pub truncated_stacks: u64,
+
+    /// The number of distinct folded stacks produced.
+    pub unique_stacks: u64,
+
+    /// The sum of the counts of the folded stacks produced.
+    pub samples: u64,
+}
+
+impl CollapseStats {
+    /// Formats the counts as a JSON object, like
+    /// `{"events":3,"filtered_events":0,"weird_lines":1,"truncated_stacks":0,"unique_stacks":2,"samples":3}`.
+    pub fn to_json(&self) -> String {
+        let fields = self
+            .fields()
+            .iter()
+            .map(|(name, _, value)| format!("\"{}\":{}", name, value))
+            .collect::<Vec<_>>();
+        format!("{{{}}}", fields.join(","))
+    }
+
+    /// Adds the counts of a worker's copy of a collapser.
+    #[cfg(feature = "multithreaded")]
+    pub(crate) fn add(&mut self, other: &CollapseStats) {
+        self.events += other.events;
+        self.filtered_events += other.filtered_events;
+        self.weird_lines += other.weird_lines;
+        self.truncated_stacks += other.truncated_stacks;
+        self.unique_stacks += other.unique_stacks;
+        self.samples += other.samples;
+    }
+
+    /// Sets [`CollapseStats::unique_stacks`] and [`CollapseStats::samples`] from the stacks that
+    /// are about to be written.
+    pub(crate) fn count_occurrences(&mut self, occurrences: &Occurrences) {
+        let (unique_stacks, samples) = occurrences.totals();
+        self.unique_stacks = unique_stacks;
+        self.samples = samples;
+    }
+
+    /// Like [`CollapseStats::count_occurrences`], for stacks that have already been collected.
+    #[cfg(feature = "regex")]
+    pub(crate) fn count_stacks(&mut self, stacks: &FoldedStacks) {
+        self.unique_stacks = stacks.stacks().count() as u64;
+        self.samples = stacks.stacks().map(|(_, count)| count).sum();
+    }
+
+    fn fields(&self) -> [(&'static str, &'static str, u64); 6] {
+        [
+            ("events", "events read", self.events),
+            ("filtered_events", "events filtered", self.filtered_events),
+            ("weird_lines", "weird lines dropped", self.weird_lines),
+            (
+                "truncated_stacks",
+                "stacks truncated",
+                self.truncated_stacks,
+            ),
+            ("unique_stacks", "unique stacks", self.unique_stacks),
+            ("samples", "samples", self.samples),
+        ]
+    }
+}
+
+/// Writes one `name: count` line per count.
+impl fmt::Display for CollapseStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (_, label, value) in self.fields() {
+            writeln!(f, "{:<20} {}", format!("{}:", label), value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_as_text_and_json() {
+        let stats = CollapseStats {
+            events: 3,
+            weird_lines: 1,
+            unique_stacks: 2,
+            samples: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            stats.to_string(),
+            "events read:         3\n\
+             events filtered:     0\n\
+             weird lines dropped: 1\n\
+             stacks truncated:    0\n\
+             unique stacks:       2\n\
+             samples:             3\n"
+        );
+        assert_eq!(
+            stats.to_json(),
+            r#"{"events":3,"filtered_events":0,"weird_lines":1,"truncated_stacks":0,"unique_stacks":2,"samples":3}"#
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use ahash::AHashMap;
+
+use crate::collapse::common::Occurrences;
+#[cfg(feature = "regex")]
+use crate::collapse::rewrite::Rules;
+use crate::collapse::{Collapse, CollapseStats, FoldedStacks, ParallelCollapse};
+
+/// The stack that the stacks evicted to stay within [`Options::max_stacks`] are counted as.
+pub const OTHER_STACK: &str = "[other]";
+
+/// `stream` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Write a snapshot once this much time has passed since the previous one.
+    ///
+    /// The time is only checked when a stack ends, so a snapshot can be late if the input is
+    /// quiet.
+    ///
+    /// Default is `None`.
+    pub interval: Option<Duration>,
+
+    /// Write a snapshot once this many stacks have been read since the previous one. For `perf`,
+    /// every stack is one sample.
+    ///
+    /// Default is `None`.
+    pub stacks: Option<u64>,
+
+    /// Write the counts of all the stacks read so far in every snapshot, rather than only the
+    /// counts of the stacks read since the previous snapshot.
+    ///
+    /// Default is `false`.
+    pub cumulative: bool,
+
+    /// The most distinct stacks to keep in memory. Whenever there are more, the stacks with the
+    /// lowest counts are evicted, and their counts are added to [`OTHER_STACK`].
+    ///
+    /// Default is `None`, which keeps every stack.
+    pub max_stacks: Option<usize>,
+
+    /// Rules to rewrite stacks with before they are counted.
+    ///
+    /// Default is no rules.
+    #[cfg(feature = "regex")]
+    pub rewrite_rules: Rules,
+}
+
+/// A "middleware" folder that collapses unbounded input, like a `perf script` pipe, in bounded
+/// memory.
+///
+/// Rather than writing the folded stacks once the input ends, this writes a snapshot of them
+/// whenever [`Options::interval`] has passed or [`Options::stacks`] stacks have been read. Every
+/// snapshot starts with a `# snapshot <n>` line, which `flamegraph` skips, followed by the folded
+/// stacks, sorted. A final snapshot is written when the input ends.
+///
+/// The input is collapsed on the calling thread, in chunks of
+/// [`ParallelCollapse::nstacks_per_job`] stacks, whatever the wrapped folder's number of threads.
+#[derive(Clone)]
+pub struct Folder<C> {
+    inner: C,
+    opt: Options,
+    counts: AHashMap<String, u64>,
+    stats: CollapseStats,
+}
+
+impl<C> Folder<C> {
+    /// Creates a folder that streams the stacks produced by `inner`.
+    pub fn new(inner: C, opt: Options) -> Self {
+        Self {
+            inner,
+            opt,
+            counts: AHashMap::default(),
+            stats: CollapseStats::default(),
+        }
+    }
+
+    /// Returns the wrapped folder.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C> Folder<C>
+where
+    C: ParallelCollapse,
+{
+    // Collapses all of `reader` into `self.counts`, writing snapshots to `writer` along the way if
+    // there is one.
+    fn stream<R>(&mut self, mut reader: R, mut writer: Option<&mut dyn Write>) -> io::Result<()>
+    where
+        R: io::BufRead,
+    {
+        self.counts.clear();
+        self.stats = CollapseStats::default();
+        if let Some(stats) = self.inner.stats_mut() {
+            *stats = CollapseStats::default();
+        }
+        let mut occurrences = Occurrences::new(1);
+        let mut recording = Recording {
+            inner: &mut reader,
+            read: Vec::new(),
+        };
+        self.inner.pre_process(&mut recording, &mut occurrences)?;
+        self.count(&mut occurrences);
+
+        // Some folders read the first stack while they pre-process the input, and that stack
+        // counts towards the first snapshot too.
+        let pre_processed = recording.read;
+        let mut nsince_snapshot = pre_processed
+            .split_inclusive(|&b| b == b'\n')
+            .filter(|line| self.inner.would_end_stack(line))
+            .count() as u64;
+        let mut nsnapshots = 0;
+        let mut last_snapshot = Instant::now();
+        if let (true, Some(writer)) = (
+            self.snapshot_due(nsince_snapshot, last_snapshot),
+            writer.as_deref_mut(),
+        ) {
+            nsnapshots += 1;
+            self.write_snapshot(nsnapshots, writer)?;
+            nsince_snapshot = 0;
+            last_snapshot = Instant::now();
+        }
+
+        let nstacks_per_job = self.inner.nstacks_per_job().max(1);
+        let mut buf = Vec::new();
+        let mut nbuffered = 0;
+        loop {
+            let start = buf.len();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+            if !self.inner.would_end_stack(&buf[start..]) {
+                continue;
+            }
+            nbuffered += 1;
+            nsince_snapshot += 1;
+
+            let snapshot_due =
+                writer.is_some() && self.snapshot_due(nsince_snapshot, last_snapshot);
+            if nbuffered >= nstacks_per_job || snapshot_due {
+                self.fold(&buf, &mut occurrences)?;
+                buf.clear();
+                nbuffered = 0;
+            }
+            if let (true, Some(writer)) = (snapshot_due, writer.as_deref_mut()) {
+                nsnapshots += 1;
+                self.write_snapshot(nsnapshots, writer)?;
+                nsince_snapshot = 0;
+                last_snapshot = Instant::now();
+            }
+        }
+
+        let trailing = !buf.is_empty();
+        self.fold(&buf, &mut occurrences)?;
+        match writer {
+            Some(writer) => {
+                if nsnapshots == 0 || nsince_snapshot > 0 || trailing {
+                    self.write_snapshot(nsnapshots + 1, writer)?;
+                }
+            }
+            None => self.stats.unique_stacks = self.counts.len() as u64,
+        }
+
+        // The stacks are counted here, as the inner folder never sees them all at once.
+        if let Some(inner) = ParallelCollapse::stats(&self.inner) {
+            self.stats = CollapseStats {
+                unique_stacks: self.stats.unique_stacks,
+                samples: self.stats.samples,
+                ..inner.clone()
+            };
+        }
+        Ok(())
+    }
+
+    // Whether a snapshot should be written, `nstacks` stacks after the previous one.
+    fn snapshot_due(&self, nstacks: u64, last_snapshot: Instant) -> bool {
+        self.opt.stacks.is_some_and(|n| nstacks >= n)
+            || self
+                .opt
+                .interval
+                .is_some_and(|interval| last_snapshot.elapsed() >= interval)
+    }
+
+    fn fold(&mut self, chunk: &[u8], occurrences: &mut Occurrences) -> io::Result<()> {
+        if !chunk.is_empty() {
+            self.inner.collapse_single_threaded(chunk, occurrences)?;
+            self.count(occurrences);
+        }
+        Ok(())
+    }
+
+    // Moves the stacks in `occurrences` into `self.counts`, and evicts stacks if there are too
+    // many.
+    fn count(&mut self, occurrences: &mut Occurrences) {
+        let mut stacks = Vec::new();
+        occurrences.drain_into(&mut stacks);
+        for (stack, count) in stacks {
+            #[cfg(feature = "regex")]
+            let stack = if self.opt.rewrite_rules.is_empty() {
+                stack
+            } else {
+                match self.opt.rewrite_rules.rewrite_stack(&stack) {
+                    Some(stack) => stack,
+                    None => continue,
+                }
+            };
+            *self.counts.entry(stack).or_insert(0) += count;
+            self.stats.samples += count;
+        }
+
+        if let Some(max_stacks) = self.opt.max_stacks {
+            if self.counts.len() > max_stacks {
+                self.evict(max_stacks);
+            }
+        }
+    }
+
+    // Evicts the stacks with the lowest counts. To not have to do this again for every new stack,
+    // this leaves room for about a quarter of `max_stacks` more.
+    fn evict(&mut self, max_stacks: usize) {
+        let mut other = self.counts.remove(OTHER_STACK).unwrap_or(0);
+        let room = max_stacks.saturating_sub(1);
+        let keep = room - room / 4;
+        let mut stacks: Vec<_> = self.counts.drain().collect();
+        if keep < stacks.len() {
+            // Break ties by stack, so that the same input always evicts the same stacks.
+            stacks.select_nth_unstable_by(keep, |(a, a_count), (b, b_count)| {
+                b_count.cmp(a_count).then_with(|| a.cmp(b))
+            });
+            other += stacks[keep..].iter().map(|(_, count)| count).sum::<u64>();
+            stacks.truncate(keep);
+        }
+        self.counts.extend(stacks);
+        self.counts.insert(OTHER_STACK.to_string(), other);
+    }
+
+    fn write_snapshot(&mut self, n: usize, writer: &mut dyn Write) -> io::Result<()> {
+        self.stats.unique_stacks = self.counts.len() as u64;
+        writeln!(writer, "# snapshot {}", n)?;
+        let mut contents: Vec<_> = self.counts.iter().collect();
+        contents.sort();
+        for (stack, count) in contents {
+            writeln!(writer, "{} {}", stack, count)?;
+        }
+        writer.flush()?;
+        if !self.opt.cumulative {
+            self.counts.clear();
+        }
+        Ok(())
+    }
+}
+
+// A reader that keeps a copy of everything that is read through it.
+struct Recording<'a, R> {
+    inner: &'a mut R,
+    read: Vec<u8>,
+}
+
+impl<R> io::Read for Recording<'_, R>
+where
+    R: io::BufRead,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R> io::BufRead for Recording<'_, R>
+where
+    R: io::BufRead,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // The bytes being consumed are still buffered, so this does not read.
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.read.extend_from_slice(&buf[..amt.min(buf.len())]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+impl<C> Collapse for Folder<C>
+where
+    C: ParallelCollapse,
+{
+    fn collapse<R, W>(&mut self, reader: R, mut writer: W) -> io::Result<()>
+    where
+        R: io::BufRead,
+        W: io::Write,
+    {
+        self.stream(reader, Some(&mut writer))
+    }
+
+    /// Collapses all of `reader` without writing any snapshots, and returns the stacks that are
+    /// left once it ends, which includes [`OTHER_STACK`] if any stacks had to be evicted.
+    fn collapse_to_stacks<R>(&mut self, reader: R) -> io::Result<FoldedStacks>
+    where
+        R: io::BufRead,
+    {
+        self.stream(reader, None)?;
+        let mut contents: Vec<_> = self.counts.drain().collect();
+        contents.sort();
+        let mut stacks = FoldedStacks::new();
+        for (stack, count) in contents {
+            stacks.add(&stack, count);
+        }
+        Ok(stacks)
+    }
+
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        self.inner.is_applicable(input)
+    }
+
+    /// Returns the counts of the wrapped folder, where [`CollapseStats::samples`] covers every
+    /// snapshot and [`CollapseStats::unique_stacks`] is the number of stacks in the last one.
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::collapse::perf;
+
+    const INPUT: &str = "\
+java 1 1.0: cycles:
+\t1 foo (lib)
+\t2 main (lib)
+
+java 1 2.0: cycles:
+\t1 bar (lib)
+\t2 main (lib)
+
+java 1 3.0: cycles:
+\t1 foo (lib)
+\t2 main (lib)
+
+java 1 4.0: cycles:
+\t1 baz (lib)
+\t2 main (lib)
+
+java 1 5.0: cycles:
+\t1 foo (lib)
+\t2 main (lib)
+";
+
+    fn collapse(opt: Options) -> String {
+        let mut folder = Folder::new(perf::Folder::default(), opt);
+        let mut out = Vec::new();
+        folder.collapse(INPUT.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_one_snapshot_by_default() {
+        assert_eq!(
+            collapse(Options::default()),
+            "# snapshot 1\njava;main;bar 1\njava;main;baz 1\njava;main;foo 3\n"
+        );
+    }
+
+    #[test]
+    fn writes_delta_snapshots() {
+        let opt = Options {
+            stacks: Some(2),
+            ..Default::default()
+        };
+        // The last stack only ends with the input, so it gets a snapshot of its own.
+        assert_eq!(
+            collapse(opt),
+            "# snapshot 1\njava;main;bar 1\njava;main;foo 1\n\
+             # snapshot 2\njava;main;baz 1\njava;main;foo 1\n\
+             # snapshot 3\njava;main;foo 1\n"
+        );
+    }
+
+    #[test]
+    fn writes_cumulative_snapshots() {
+        let opt = Options {
+            stacks: Some(2),
+            cumulative: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            collapse(opt),
+            "# snapshot 1\njava;main;bar 1\njava;main;foo 1\n\
+             # snapshot 2\njava;main;bar 1\njava;main;baz 1\njava;main;foo 2\n\
+             # snapshot 3\njava;main;bar 1\njava;main;baz 1\njava;main;foo 3\n"
+        );
+    }
+
+    #[test]
+    fn counts_the_pre_processed_stack() {
+        let opt = Options {
+            stacks: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            collapse(opt),
+            "# snapshot 1\njava;main;foo 1\n\
+             # snapshot 2\njava;main;bar 1\n\
+             # snapshot 3\njava;main;foo 1\n\
+             # snapshot 4\njava;main;baz 1\n\
+             # snapshot 5\njava;main;foo 1\n"
+        );
+    }
+
+    #[test]
+    fn evicts_rare_stacks() {
+        let opt = Options {
+            max_stacks: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(collapse(opt), "# snapshot 1\n[other] 2\njava;main;foo 3\n");
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use log::warn;
+use quick_xml::escape::unescape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::collapse::common::LineCounter;
+use crate::collapse::{Collapse, CollapseStats};
+use crate::flamegraph::walk_frames;
+use crate::Error;
+
+/// `svg` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Fail with an [`Error::Parse`] rather than log a warning when the title of a frame can't be
+    /// parsed, and with [`Error::EmptyInput`] if the SVG has no frames.
+    ///
+    /// Default is `false`.
+    pub strict: bool,
+}
+
+/// A "collapser" that rebuilds the folded stacks from a flame graph SVG made by inferno.
+///
+/// Every frame of such an SVG has its position and width in samples in its `fg:x` and `fg:w`
+/// attributes, and its function name in its `<title>`. The count of a stack is the width of its
+/// last frame minus the widths of that frame's children, so the samples of frames that were
+/// too narrow to be drawn end up in their parent. Annotations like `_[k]` are not kept in the
+/// SVG, and so are lost.
+///
+/// If the SVG is a differential flame graph, every stack gets two counts, like the output of
+/// [`crate::differential`]. The difference between them is recovered from the percentage in the
+/// title, and so is only as precise as the two decimals it is written with. The sign of the
+/// difference of an SVG made with [`crate::flamegraph::Options::negate_differentials`] can't
+/// be told apart from that of any other SVG.
+///
+/// To construct one, either use `svg::Folder::default()` or create an [`Options`] and use
+/// `svg::Folder::from(options)`.
+#[derive(Clone, Debug, Default)]
+pub struct Folder {
+    /// What has been counted while collapsing.
+    stats: CollapseStats,
+
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder {
+            opt,
+            ..Default::default()
+        }
+    }
+}
+
+/// A frame as it is drawn in the SVG.
+#[derive(Debug)]
+struct Frame {
+    /// The line of the SVG that the frame starts on.
+    line: usize,
+    x: u64,
+    width: u64,
+    y: f64,
+    title: String,
+}
+
+/// The parts of the title of a frame, like `main (1,234 samples, 12.34%; +0.56%)`.
+#[derive(Debug, PartialEq)]
+struct Title<'a> {
+    function: &'a str,
+    samples: &'a str,
+    percent: &'a str,
+    delta_percent: Option<f64>,
+}
+
+impl<'a> Title<'a> {
+    fn parse(title: &'a str) -> Option<Self> {
+        let info = title.strip_suffix(')')?;
+        let (function, info) = info.rsplit_once(" (")?;
+        let (info, delta_percent) = match info.split_once("; ") {
+            Some((info, delta)) => (info, Some(delta.strip_suffix('%')?.parse().ok()?)),
+            None => (info, None),
+        };
+        let (samples, percent) = info.rsplit_once(", ")?;
+        let samples = samples.split(' ').next()?;
+        Some(Title {
+            function,
+            samples,
+            percent,
+            delta_percent,
+        })
+    }
+}
+
+impl Collapse for Folder {
+    fn collapse<R, W>(&mut self, reader: R, mut writer: W) -> io::Result<()>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        self.stats = CollapseStats::default();
+        let frames = read_frames(reader)?;
+        self.stats.events = frames.len() as u64;
+        if frames.is_empty() {
+            if self.opt.strict {
+                return Err(Error::EmptyInput.into());
+            }
+            warn!("No frames found, is this a flame graph SVG made by inferno?");
+            return Ok(());
+        }
+
+        let titles = frames
+            .iter()
+            .map(|frame| match Title::parse(&frame.title) {
+                Some(title) => Ok(Some(title)),
+                None if self.opt.strict => Err(io::Error::from(Error::parse(
+                    frame.line,
+                    &frame.title,
+                    "Frame title is not like `function (samples, percent)`",
+                ))),
+                None => {
+                    warn!(
+                        "Using the whole title as the function name of frame: {}",
+                        frame.title
+                    );
+                    self.stats.weird_lines += 1;
+                    Ok(None)
+                }
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // The root frame is the one titled `all`, at the bottom of a flame graph or the top of an
+        // icicle graph.
+        let root = titles.iter().position(|title| {
+            title
+                .as_ref()
+                .is_some_and(|t| t.function == "all" && t.percent == "100%")
+        });
+        let Some(root) = root else {
+            warn!("No `all` frame found, is this a flame graph SVG made by inferno?");
+            return Err(Error::EmptyInput.into());
+        };
+        let root_samples: f64 = titles[root]
+            .as_ref()
+            .and_then(|t| t.samples.replace(',', "").parse().ok())
+            .unwrap_or(frames[root].width as f64);
+
+        // Every level of the graph is at its own height, from the root outwards.
+        let mut levels: Vec<f64> = frames.iter().map(|frame| frame.y).collect();
+        levels.sort_unstable_by(f64::total_cmp);
+        levels.dedup();
+        if levels.first() != Some(&frames[root].y) {
+            levels.reverse();
+        }
+        let depth = |y: f64| levels.iter().position(|&level| level == y).unwrap_or(0);
+
+        let differential = titles.iter().flatten().any(|t| t.delta_percent.is_some());
+        let mut self_widths: Vec<u64> = frames.iter().map(|frame| frame.width).collect();
+        let mut paths: Vec<String> = vec![String::new(); frames.len()];
+        let position = |frame: &Frame| (frame.x, depth(frame.y));
+        walk_frames(&frames, position, |i, ancestors| {
+            let function = titles[i]
+                .as_ref()
+                .map_or(frames[i].title.as_str(), |t| t.function);
+            paths[i] = match ancestors.last() {
+                Some(&parent) => {
+                    self_widths[parent] = self_widths[parent].saturating_sub(frames[i].width);
+                    format!("{};{}", paths[parent], function)
+                }
+                None => function.to_owned(),
+            };
+        });
+
+        // Stacks that appear more than once, like in flame charts, are added together.
+        let mut stacks: BTreeMap<&str, (u64, i64)> = BTreeMap::new();
+        for i in 0..frames.len() {
+            if paths[i].is_empty() || self_widths[i] == 0 {
+                continue;
+            }
+            let delta = titles[i]
+                .as_ref()
+                .and_then(|t| t.delta_percent)
+                .map_or(0, |pct| (pct * root_samples / 100.0).round() as i64);
+            let counts = stacks.entry(&paths[i]).or_default();
+            counts.0 += self_widths[i];
+            counts.1 += delta;
+        }
+
+        for (stack, (count, delta)) in &stacks {
+            if differential {
+                let before = (*count as i64 - delta).max(0);
+                writeln!(writer, "{} {} {}", stack, before, count)?;
+            } else {
+                writeln!(writer, "{} {}", stack, count)?;
+            }
+            self.stats.samples += count;
+        }
+        self.stats.unique_stacks = stacks.len() as u64;
+        writer.flush()
+    }
+
+    /// Checks for the attributes that inferno adds to the frames of its SVGs.
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        if !input.trim_start().starts_with('<') {
+            return Some(false);
+        }
+        if input.contains("fg:x=") {
+            return Some(true);
+        }
+        None
+    }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
+}
+
+/// Reads the frames inside `<svg id="frames">`, in the order they are in the SVG.
+fn read_frames<R: BufRead>(reader: R) -> io::Result<Vec<Frame>> {
+    let mut reader = Reader::from_reader(LineCounter::new(reader));
+    let mut buf = Vec::new();
+    let mut frames = Vec::new();
+    let mut in_frames = false;
+    let mut in_title = false;
+    let mut title = String::new();
+    let mut rect: Option<(u64, u64, f64)> = None;
+    let mut line = 0;
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let empty = matches!(event, Event::Empty(_));
+        match event {
+            Event::Eof => break,
+            Event::Start(e) if e.name().as_ref() == b"svg" => {
+                in_frames |= attribute(&e, "id")?.as_deref() == Some("frames");
+            }
+            Event::End(e) if e.name().as_ref() == b"svg" => in_frames = false,
+            _ if !in_frames => {}
+            // Frames are in a `<g>`, or an `<a>` if they have a link.
+            Event::Start(e) if matches!(e.name().as_ref(), b"g" | b"a") => {
+                title.clear();
+                rect = None;
+                line = reader.get_ref().line_number();
+            }
+            Event::Start(e) if e.name().as_ref() == b"title" => in_title = true,
+            Event::End(e) if e.name().as_ref() == b"title" => in_title = false,
+            Event::Text(e) if in_title => title.push_str(&e.decode().map_err(invalid_data)?),
+            Event::GeneralRef(e) if in_title => {
+                let entity = format!("&{};", e.decode().map_err(invalid_data)?);
+                title.push_str(&unescape(&entity).map_err(invalid_data)?);
+            }
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"rect" => {
+                let number = |name| -> io::Result<Option<String>> { attribute(&e, name) };
+                if let (Some(x), Some(width), Some(y)) =
+                    (number("fg:x")?, number("fg:w")?, number("y")?)
+                {
+                    match (x.parse(), width.parse(), y.parse()) {
+                        (Ok(x), Ok(width), Ok(y)) => rect = Some((x, width, y)),
+                        _ => {
+                            let slash = if empty { "/" } else { "" };
+                            let text = format!("<{}{}>", String::from_utf8_lossy(&e), slash);
+                            let line = reader.get_ref().line_number();
+                            return Err(Error::parse(line, &text, "Invalid frame position").into());
+                        }
+                    }
+                }
+            }
+            Event::End(e) if matches!(e.name().as_ref(), b"g" | b"a") => {
+                if let Some((x, width, y)) = rect.take() {
+                    frames.push(Frame {
+                        line,
+                        x,
+                        width,
+                        y,
+                        title: std::mem::take(&mut title),
+                    });
+                }
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(frames)
+}
+
+fn attribute(e: &BytesStart<'_>, name: &str) -> io::Result<Option<String>> {
+    match e.try_get_attribute(name).map_err(invalid_data)? {
+        // The attributes that are read are numbers and ids, which need no unescaping.
+        Some(attr) => Ok(Some(
+            String::from_utf8(attr.value.into_owned()).map_err(invalid_data)?,
+        )),
+        None => Ok(None),
+    }
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_titles() {
+        assert_eq!(
+            Title::parse("main (1,234 samples, 12.34%)"),
+            Some(Title {
+                function: "main",
+                samples: "1,234",
+                percent: "12.34%",
+                delta_percent: None,
+            })
+        );
+        assert_eq!(
+            Title::parse("f (g) (5 ms, 1.00%; -0.25%)"),
+            Some(Title {
+                function: "f (g)",
+                samples: "5",
+                percent: "1.00%",
+                delta_percent: Some(-0.25),
+            })
+        );
+        assert_eq!(Title::parse("custom title"), None);
+    }
+
+    #[test]
+    fn reports_the_line_of_an_invalid_frame_position() {
+        let svg = r#"<svg id="frames">
+<g><title>all (1 samples, 100%)</title>
+<rect y="20" fg:x="0" fg:w="1"/></g>
+<g><title>main (1 samples, 100%)</title>
+<rect y="5" fg:x="0" fg:w="one"/></g>
+</svg>"#;
+        match Error::from_io(read_frames(svg.as_bytes()).unwrap_err()) {
+            Error::Parse { line, text, .. } => {
+                assert_eq!(line, 5);
+                assert_eq!(text, r#"<rect y="5" fg:x="0" fg:w="one"/>"#);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
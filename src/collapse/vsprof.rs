@@ -1,16 +1,43 @@
-use std::{cmp::Ordering, io};
+use std::{borrow::Cow, cmp::Ordering, io};
 
 use log::warn;
 
-use crate::collapse::{common::Occurrences, Collapse};
+use crate::collapse::{common::Occurrences, Collapse, CollapseStats, Demangle};
+use crate::Error;
 
 static START_LINE: &str = "Level,Function Name,Number of Calls,Elapsed Inclusive Time %,Elapsed Exclusive Time %,Avg Elapsed Inclusive Time,Avg Elapsed Exclusive Time,Module Name,";
 
+/// `vsprof` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Demangle C++ and Rust symbols that Visual Studio left mangled.
+    ///
+    /// Default is `None`, which leaves symbols as they are.
+    pub demangle: Option<Demangle>,
+
+    /// Fail with [`Error::EmptyInput`] rather than log a warning if the input is empty.
+    ///
+    /// Default is `false`.
+    pub strict: bool,
+}
+
 /// A stack collapser for the output of the Visual Studio built in profiler.
+///
+/// To construct one, either use `vsprof::Folder::default()` or create an [`Options`] and use
+/// `vsprof::Folder::from(options)`.
 #[derive(Default)]
 pub struct Folder {
     /// Function entries on the stack in this entry thus far.
     stack: Vec<(String, u64)>,
+
+    /// The number of the line being processed.
+    line_number: usize,
+
+    /// What has been counted while collapsing.
+    stats: CollapseStats,
+
+    opt: Options,
 }
 
 impl Collapse for Folder {
@@ -21,17 +48,21 @@ impl Collapse for Folder {
     {
         // Skip the header
         let mut line = Vec::new();
+        self.stats = CollapseStats::default();
         if reader.read_until(b'\n', &mut line)? == 0 {
+            if self.opt.strict {
+                return Err(Error::EmptyInput.into());
+            }
             warn!("File ended before start of call graph");
             return Ok(());
         };
+        self.line_number = 1;
 
         let header = String::from_utf8_lossy(&line).to_string();
         if !line_matches_start_line(&header) {
-            return invalid_data_error!(
-                "Expected first line to be header line\n    {}\nbut instead got\n    {}",
-                START_LINE,
-                header
+            return self.parse_error(
+                &header,
+                format!("Expected first line to be header line\n    {}", START_LINE),
             );
         }
 
@@ -42,6 +73,7 @@ impl Collapse for Folder {
             if reader.read_until(b'\n', &mut line)? == 0 {
                 break;
             }
+            self.line_number += 1;
             let l = String::from_utf8_lossy(&line);
             let line = l.trim_end();
             if line.is_empty() {
@@ -54,6 +86,7 @@ impl Collapse for Folder {
         self.write_stack(&mut occurences);
 
         // Write the results
+        self.stats.count_occurrences(&occurences);
         occurences.write_and_clear(writer)?;
 
         // Reset the state
@@ -62,13 +95,24 @@ impl Collapse for Folder {
     }
 
     fn is_applicable(&mut self, input: &str) -> Option<bool> {
-        let line = input
-            .lines()
-            .next()
-            .expect("there is always at least one line (even if empty)");
+        // Empty input has no lines at all.
+        let line = input.lines().next()?;
 
         Some(line_matches_start_line(line))
     }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder {
+            opt,
+            ..Default::default()
+        }
+    }
 }
 
 impl Folder {
@@ -76,10 +120,13 @@ impl Folder {
     // Level,Function Name,Number of Calls,...
     // 6,"System.String.IsNullOrEmpty(string)",4,0.00,0.00,0.00,0.00,"mscorlib.dll",
     fn on_line(&mut self, line: &str, occurences: &mut Occurrences) -> io::Result<()> {
-        let (depth, remainder) = get_next_number(line)?;
+        let (depth, remainder) = match get_next_number(line) {
+            Ok(number) => number,
+            Err(message) => return self.parse_error(line, message),
+        };
 
         if remainder.is_empty() {
-            return invalid_data_error!("Missing function name in line:\n{}", line);
+            return self.parse_error(line, "Missing function name");
         }
 
         // Function names are always wrapped in quotes. By trimming the leading double quote, we
@@ -88,11 +135,18 @@ impl Folder {
         let split = if let Some(remainder) = remainder.strip_prefix('"') {
             remainder.split_once('"')
         } else {
-            return invalid_data_error!("Unable to parse function name from line:\n{}", line);
+            return self.parse_error(line, "Unable to parse function name");
         };
 
         if let Some((function_name, remainder)) = split {
-            let (number_of_calls, _) = get_next_number(remainder)?;
+            let function_name = match &self.opt.demangle {
+                Some(demangle) => demangle.demangle(function_name),
+                None => Cow::Borrowed(function_name),
+            };
+            let number_of_calls = match get_next_number(remainder) {
+                Ok((number_of_calls, _)) => number_of_calls,
+                Err(message) => return self.parse_error(line, message),
+            };
 
             let prev_depth = self.stack.len() as u64;
             // There are 3 separate cases to handle regarding the depth:
@@ -160,17 +214,22 @@ impl Folder {
                 }
             }
         } else {
-            return invalid_data_error!("Unable to parse function name from line:\n{}", line);
+            return self.parse_error(line, "Unable to parse function name");
         }
 
         Ok(())
     }
 
+    fn parse_error<T>(&self, line: &str, message: impl Into<String>) -> io::Result<T> {
+        Err(Error::parse(self.line_number, line, message).into())
+    }
+
     // Store the current stack in `occurences`
-    fn write_stack(&self, occurrences: &mut Occurrences) {
+    fn write_stack(&mut self, occurrences: &mut Occurrences) {
         if let Some(nsamples) = self.stack.last().map(|(_, n)| *n).filter(|n| *n > 0) {
             let functions: Vec<_> = self.stack.iter().map(|(f, _)| &f[..]).collect();
             occurrences.insert(functions.join(";"), nsamples);
+            self.stats.events += 1;
         }
     }
 }
@@ -183,7 +242,7 @@ impl Folder {
 /// ### Example inputs
 /// - Number <1000: `471,91.25,18.39,401.92,81.02,"Raytracer.exe",`
 /// - Number >1000: `"2,893,824",54.37,4.21,0.04,0.00,"Raytracer.exe",`
-fn get_next_number(line: &str) -> io::Result<(u64, &str)> {
+fn get_next_number(line: &str) -> Result<(u64, &str), String> {
     // Trim the leading comma, if any
     let line = line.strip_prefix(',').unwrap_or(line);
 
@@ -209,7 +268,7 @@ fn get_next_number(line: &str) -> io::Result<(u64, &str)> {
         for c in num.chars() {
             if c.is_ascii_digit() {
                 if current_group_count > 2 {
-                    return invalid_data_error!("Missing thousands separator in number '{}'", num);
+                    return Err(format!("Missing thousands separator in number '{}'", num));
                 }
 
                 n *= 10;
@@ -221,7 +280,7 @@ fn get_next_number(line: &str) -> io::Result<(u64, &str)> {
 
             if c == ',' || c == '.' || c == ' ' {
                 if !initial && current_group_count < 3 {
-                    return invalid_data_error!("Missing thousands separator in number '{}'", num);
+                    return Err(format!("Missing thousands separator in number '{}'", num));
                 }
 
                 match separator {
@@ -229,7 +288,7 @@ fn get_next_number(line: &str) -> io::Result<(u64, &str)> {
                         // Ensure that we always use the same separator, this takes care of
                         // handling floating point numbers (which aren't valid here), because those
                         // would use at least 2 different separators.
-                        return invalid_data_error!("Unable to parse integer from '{}'", num);
+                        return Err(format!("Unable to parse integer from '{}'", num));
                     }
                     Some(_) => {}
                     None => separator = Some(c),
@@ -240,7 +299,7 @@ fn get_next_number(line: &str) -> io::Result<(u64, &str)> {
                 continue;
             }
 
-            return invalid_data_error!("Unable to parse integer from '{}'", num);
+            return Err(format!("Unable to parse integer from '{}'", num));
         }
 
         if remove_leading_comma {
@@ -253,7 +312,7 @@ fn get_next_number(line: &str) -> io::Result<(u64, &str)> {
         return Ok((n, remainder));
     }
 
-    invalid_data_error!("Invalid number in line:\n{}", line)
+    Err(format!("Invalid number in '{}'", line))
 }
 
 /// Some files may start with the <U+FEFF> character (zero width no-break space). This
@@ -1,9 +1,11 @@
+use std::borrow::Cow;
 use std::io::{self, BufRead};
 
 use log::warn;
 
 use crate::collapse::common::Occurrences;
-use crate::collapse::Collapse;
+use crate::collapse::{Collapse, CollapseStats, Demangle};
+use crate::Error;
 
 // The call graph begins after this line.
 static HEADER: &str = "Function Stack,CPU Time:Self,Module";
@@ -16,6 +18,16 @@ pub struct Options {
     ///
     /// Default is `false`.
     pub no_modules: bool,
+
+    /// Demangle C++ and Rust symbols that VTune left mangled.
+    ///
+    /// Default is `None`, which leaves symbols as they are.
+    pub demangle: Option<Demangle>,
+
+    /// Fail with [`Error::EmptyInput`] rather than log a warning if the input has no header.
+    ///
+    /// Default is `false`.
+    pub strict: bool,
 }
 
 /// A stack collapser for CSV call graphs created with the VTune `amplxe-cl` tool.
@@ -27,6 +39,12 @@ pub struct Folder {
     /// Function on the stack in this entry thus far.
     stack: Vec<String>,
 
+    /// The number of the line being processed.
+    line_number: usize,
+
+    /// What has been counted while collapsing.
+    stats: CollapseStats,
+
     opt: Options,
 }
 
@@ -38,12 +56,18 @@ impl Collapse for Folder {
     {
         // Consume the header...
         let mut line = Vec::new();
+        self.line_number = 0;
+        self.stats = CollapseStats::default();
         loop {
             line.clear();
             if reader.read_until(0x0A, &mut line)? == 0 {
+                if self.opt.strict {
+                    return Err(Error::EmptyInput.into());
+                }
                 warn!("File ended before header");
                 return Ok(());
             };
+            self.line_number += 1;
             let l = String::from_utf8_lossy(&line);
             if l.starts_with(HEADER) {
                 break;
@@ -57,6 +81,7 @@ impl Collapse for Folder {
             if reader.read_until(0x0A, &mut line)? == 0 {
                 break;
             }
+            self.line_number += 1;
             let l = String::from_utf8_lossy(&line);
             let line = l.trim_end();
             if line.is_empty() {
@@ -67,6 +92,7 @@ impl Collapse for Folder {
         }
 
         // Write the results...
+        self.stats.count_occurrences(&occurrences);
         occurrences.write_and_clear(writer)?;
 
         // Reset the state...
@@ -94,6 +120,10 @@ impl Collapse for Folder {
         }
         None
     }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
 }
 
 impl From<Options> for Folder {
@@ -141,12 +171,16 @@ impl Folder {
                     self.stack.pop();
                 }
             } else if depth > prev_depth + 1 {
-                return invalid_data_error!("Skipped indentation level at line:\n{}", line);
+                return self.parse_error(line, "Skipped indentation level");
             }
 
             if let Some((func, time, module)) = self.line_parts(&line[spaces..]) {
                 if let Ok(time) = time.parse::<f64>() {
                     let time_ms = (time * 1000.0).round() as u64;
+                    let func = match &self.opt.demangle {
+                        Some(demangle) => demangle.demangle(func),
+                        None => Cow::Borrowed(func),
+                    };
                     if module.is_empty() {
                         self.stack.push(func.to_string());
                     } else {
@@ -156,17 +190,23 @@ impl Folder {
                         self.write_stack(occurrences, time_ms);
                     }
                 } else {
-                    return invalid_data_error!("Invalid `CPU Time:Self` field: {}", time);
+                    return self
+                        .parse_error(line, format!("Invalid `CPU Time:Self` field '{}'", time));
                 }
             } else {
-                return invalid_data_error!("Unable to parse stack line:\n{}", line);
+                return self.parse_error(line, "Unable to parse stack line");
             }
         }
 
         Ok(())
     }
 
-    fn write_stack(&self, occurrences: &mut Occurrences, time: u64) {
+    fn parse_error<T>(&self, line: &str, message: impl Into<String>) -> io::Result<T> {
+        Err(Error::parse(self.line_number, line, message).into())
+    }
+
+    fn write_stack(&mut self, occurrences: &mut Occurrences, time: u64) {
+        self.stats.events += 1;
         occurrences.insert(self.stack.join(";"), time);
     }
 }
@@ -9,10 +9,13 @@ use std::{
     io::{self, BufRead},
 };
 
+use log::warn;
+
 use super::{
-    common::{fix_partially_demangled_rust_symbol, Occurrences},
-    Collapse,
+    common::{fix_partially_demangled_rust_symbol, LineCounter, Occurrences},
+    Collapse, CollapseStats, Demangle,
 };
+use crate::Error;
 
 /* A simplified xctrace xml example:
 
@@ -176,6 +179,16 @@ struct FrameId(u64);
 struct BacktraceId(u64);
 
 impl CurrentTag {
+    fn name(&self) -> &'static [u8] {
+        match self {
+            Self::TraceQueryResult { .. } => TRACE_QUERY_RESULT,
+            Self::Node { .. } => NODE,
+            Self::Row { .. } => ROW,
+            Self::Backtrace { .. } => BACKTRACE,
+            Self::Frame { .. } => FRAME,
+        }
+    }
+
     fn matches(&self, name: &[u8]) -> bool {
         match name {
             TRACE_QUERY_RESULT => matches!(self, Self::TraceQueryResult { .. }),
@@ -225,7 +238,12 @@ impl BacktraceId {
                 .get(frame)
                 .expect("Frame id not registered in collapse context, this is a inferno bug.");
             let frame_name = String::from_utf8_lossy(&frame.name);
-            let frame_name = fix_partially_demangled_rust_symbol(&frame_name);
+            let mut frame_name = fix_partially_demangled_rust_symbol(&frame_name);
+            if let Some(demangle) = &context.opt.demangle {
+                if let Cow::Owned(demangled) = demangle.demangle(&frame_name) {
+                    frame_name = Cow::Owned(demangled);
+                }
+            }
             folded.push_str(&frame_name);
         }
         folded
@@ -233,7 +251,7 @@ impl BacktraceId {
 }
 
 /// Unescapes the text in xml exported from xctrace.
-fn unescape_xctrace_text(text: Cow<'_, [u8]>) -> io::Result<Box<[u8]>> {
+fn unescape_xctrace_text(text: Cow<'_, [u8]>) -> Result<Box<[u8]>, String> {
     // xctrace shouldn't give us invalid xml text here, therefore
     // we don't expect the error branch being hit:
     //
@@ -241,57 +259,88 @@ fn unescape_xctrace_text(text: Cow<'_, [u8]>) -> io::Result<Box<[u8]>> {
     // https://github.com/tafia/quick-xml/blob/0793d6a8d006cb5dabf66bf2a25ddbf198305b46/src/escape.rs#L253
     match quick_xml::escape::unescape(&String::from_utf8_lossy(&text)) {
         Ok(x) => Ok(x.into_owned().into_bytes().into_boxed_slice()),
-        Err(e) => invalid_data_error!(
+        Err(e) => Err(format!(
             "Invalid xml text from xctrace, which is not expected: {:?}",
             e
-        ),
+        )),
     }
 }
 
-fn get_u64_from_attributes(key: &'static [u8], attributes: &Attributes) -> io::Result<u64> {
+fn get_u64_from_attributes(key: &'static [u8], attributes: &Attributes) -> Result<u64, String> {
     let id = attributes
         .clone()
         .filter_map(|x| x.ok())
         .find_map(|x| (x.key.into_inner() == key).then_some(x.value));
     let Some(id) = id else {
-        return invalid_data_error!("No {} found in attributes", String::from_utf8_lossy(key));
+        return Err(format!(
+            "No {} found in attributes",
+            String::from_utf8_lossy(key)
+        ));
     };
     let id = String::from_utf8_lossy(&id);
     match id.parse() {
         Ok(x) => Ok(x),
-        Err(e) => invalid_data_error!(
+        Err(e) => Err(format!(
             "Unrecognized {}: {}: {:?}",
             String::from_utf8_lossy(key),
             id,
             e
-        ),
+        )),
     }
 }
 
-fn get_name_from_attributes(attributes: &Attributes) -> io::Result<Box<[u8]>> {
+fn get_name_from_attributes(attributes: &Attributes) -> Result<Box<[u8]>, String> {
     let name = attributes
         .clone()
         .filter_map(|x| x.ok())
         .find_map(|x| (x.key.into_inner() == NAME).then_some(x.value));
     match name {
         Some(x) => unescape_xctrace_text(x),
-        None => invalid_data_error!("No name(symbol) found in attributes"),
+        None => Err("No name(symbol) found in attributes".to_string()),
     }
 }
 
 /// Extract necessary info from attributes for constructing backtrace.
-fn attributes_to_backtrace(attributes: &Attributes) -> io::Result<BacktraceId> {
+fn attributes_to_backtrace(attributes: &Attributes) -> Result<BacktraceId, String> {
     get_u64_from_attributes(ID, attributes).map(BacktraceId)
 }
 
 /// Extract necessary info from attributes for constructing frame.
-fn attributes_to_frame(attributes: &Attributes) -> io::Result<(FrameId, Box<[u8]>)> {
+fn attributes_to_frame(attributes: &Attributes) -> Result<(FrameId, Box<[u8]>), String> {
     let id = get_u64_from_attributes(ID, attributes)?;
     let name = get_name_from_attributes(attributes)?;
     Ok((FrameId(id), name))
 }
 
+/// The text of a tag, for pointing at it in errors.
+fn tag_text(prefix: &str, tag: &[u8], suffix: &str) -> String {
+    format!("<{}{}{}>", prefix, String::from_utf8_lossy(tag), suffix)
+}
+
+fn parse_error<T>(line: usize, text: &str, message: impl Into<String>) -> io::Result<T> {
+    Err(Error::parse(line, text, message).into())
+}
+
+/// `xctrace` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Demangle C++ and Rust symbols that xctrace left mangled.
+    ///
+    /// Default is `None`, which leaves symbols as they are.
+    pub demangle: Option<Demangle>,
+
+    /// Fail with [`Error::EmptyInput`] rather than log a warning if the input has no samples.
+    /// Malformed input always fails with an [`Error::Parse`].
+    ///
+    /// Default is `false`.
+    pub strict: bool,
+}
+
 /// Context of collapsing a xctrace's `Time Profiler` xml
+///
+/// To construct one, either use `xctrace::Folder::default()` or create an [`Options`] and use
+/// `xctrace::Folder::from(options)`.
 #[derive(Default)]
 pub struct Folder {
     /// xml tag backtrace
@@ -301,6 +350,19 @@ pub struct Folder {
     backtraces: BTreeMap<BacktraceId, Backtrace>,
     /// backtrace_id <--> Frame
     frames: BTreeMap<FrameId, Frame>,
+    /// What has been counted while collapsing.
+    stats: CollapseStats,
+
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder {
+            opt,
+            ..Default::default()
+        }
+    }
 }
 
 impl Collapse for Folder {
@@ -309,7 +371,7 @@ impl Collapse for Folder {
         R: std::io::BufRead,
         W: std::io::Write,
     {
-        let mut reader = Reader::from_reader(reader);
+        let mut reader = Reader::from_reader(LineCounter::new(reader));
         self.collapse_inner(&mut reader, writer)
     }
 
@@ -340,19 +402,30 @@ impl Collapse for Folder {
         }
         None
     }
+
+    fn stats(&self) -> Option<&CollapseStats> {
+        Some(&self.stats)
+    }
 }
 
 impl Folder {
-    fn collapse_inner<R, W>(&mut self, reader: &mut Reader<R>, writer: W) -> io::Result<()>
+    fn collapse_inner<R, W>(
+        &mut self,
+        reader: &mut Reader<LineCounter<R>>,
+        writer: W,
+    ) -> io::Result<()>
     where
         R: std::io::BufRead,
         W: std::io::Write,
     {
+        self.stats = CollapseStats::default();
         let mut buf = Vec::new();
         let nodes = loop {
-            let event = match reader.read_event_into(&mut buf) {
+            let event = reader.read_event_into(&mut buf);
+            let line = reader.get_ref().line_number();
+            let event = match event {
                 Ok(x) => x,
-                Err(e) => return invalid_data_error!("Read xml event failed: {:?}", e),
+                Err(e) => return parse_error(line, &e.to_string(), "Invalid xml"),
             };
             match event {
                 Event::Start(start) => {
@@ -373,14 +446,20 @@ impl Folder {
                             Some(CurrentTag::Row { backtrace: None })
                         }
                         (Some(CurrentTag::Row { .. }), BACKTRACE) => {
-                            let id = attributes_to_backtrace(&attributes)?;
+                            let id = match attributes_to_backtrace(&attributes) {
+                                Ok(id) => id,
+                                Err(e) => return parse_error(line, &tag_text("", &start, ""), e),
+                            };
                             Some(CurrentTag::Backtrace {
                                 id,
                                 frames: Vec::new(),
                             })
                         }
                         (Some(CurrentTag::Backtrace { .. }), FRAME) => {
-                            let (id, name) = attributes_to_frame(&attributes)?;
+                            let (id, name) = match attributes_to_frame(&attributes) {
+                                Ok(x) => x,
+                                Err(e) => return parse_error(line, &tag_text("", &start, ""), e),
+                            };
                             Some(CurrentTag::Frame { id, name })
                         }
                         (Some(_), _) => {
@@ -401,11 +480,9 @@ impl Folder {
                     if !is_interested_tag(name) {
                         continue;
                     }
+                    let text = || tag_text("/", name, "");
                     let Some(state) = self.state_backtrace.pop_with_name(name) else {
-                        return invalid_data_error!(
-                            "Unpaired tag: {}",
-                            String::from_utf8_lossy(name)
-                        );
+                        return parse_error(line, &text(), "Unpaired tag");
                     };
                     // Retrieve information when a tag span ends.
                     match (self.state_backtrace.top_mut(), state) {
@@ -431,9 +508,10 @@ impl Folder {
                             let new_backtrace = Backtrace { id, frames };
                             let ret = self.backtraces.insert(new_backtrace.id, new_backtrace);
                             if ret.is_some() {
-                                return invalid_data_error!(
-                                    "Repeated backtrace id in xctrace output: {:?}",
-                                    id
+                                return parse_error(
+                                    line,
+                                    &text(),
+                                    format!("Repeated backtrace id in xctrace output: {}", id.0),
                                 );
                             }
                             *backtrace = Some(id);
@@ -445,9 +523,10 @@ impl Folder {
                             let frame = Frame { id, name };
                             let ret = self.frames.insert(frame.id, frame);
                             if ret.is_some() {
-                                return invalid_data_error!(
-                                    "Repeated frame id in xctrace output: {:?}",
-                                    id
+                                return parse_error(
+                                    line,
+                                    &text(),
+                                    format!("Repeated frame id in xctrace output: {}", id.0),
                                 );
                             }
                             frames.push(id);
@@ -458,14 +537,16 @@ impl Folder {
                 Event::Empty(empty) => {
                     let attributes = empty.attributes();
                     let name = empty.name().into_inner();
+                    let text = || tag_text("", &empty, "/");
                     match (self.state_backtrace.top_mut(), name) {
                         (Some(CurrentTag::Row { backtrace }), BACKTRACE) => {
                             let new_backtrace =
                                 if let Ok(ref_id) = get_u64_from_attributes(REF, &attributes) {
                                     if !self.backtraces.contains_key(&BacktraceId(ref_id)) {
-                                        return invalid_data_error!(
-                                            "Invalid backtrace ref id: {}",
-                                            ref_id
+                                        return parse_error(
+                                            line,
+                                            &text(),
+                                            format!("Invalid backtrace ref id: {}", ref_id),
                                         );
                                     }
                                     BacktraceId(ref_id)
@@ -476,15 +557,21 @@ impl Folder {
                                     };
                                     let ret = self.backtraces.insert(id, backtrace);
                                     if ret.is_some() {
-                                        return invalid_data_error!(
-                                            "Repeated backtrace id in xctrace output: {:?}",
-                                            id
+                                        return parse_error(
+                                            line,
+                                            &text(),
+                                            format!(
+                                                "Repeated backtrace id in xctrace output: {}",
+                                                id.0
+                                            ),
                                         );
                                     }
                                     id
                                 } else {
-                                    return invalid_data_error!(
-                                        "Get ref_id or attributes of backtrace failed."
+                                    return parse_error(
+                                        line,
+                                        &text(),
+                                        "Get ref_id or attributes of backtrace failed",
                                     );
                                 };
                             *backtrace = Some(new_backtrace);
@@ -494,22 +581,29 @@ impl Folder {
                                 get_u64_from_attributes(REF, &attributes)
                             {
                                 if !self.frames.contains_key(&FrameId(ref_id)) {
-                                    return invalid_data_error!("Invalid frame ref id: {}", ref_id);
+                                    return parse_error(
+                                        line,
+                                        &text(),
+                                        format!("Invalid frame ref id: {}", ref_id),
+                                    );
                                 }
                                 FrameId(ref_id)
                             } else if let Ok((id, name)) = attributes_to_frame(&attributes) {
                                 let frame = Frame { id, name };
                                 let ret = self.frames.insert(id, frame);
                                 if ret.is_some() {
-                                    return invalid_data_error!(
-                                        "Repeated frame id in xctrace output: {:?}",
-                                        id
+                                    return parse_error(
+                                        line,
+                                        &text(),
+                                        format!("Repeated frame id in xctrace output: {}", id.0),
                                     );
                                 }
                                 id
                             } else {
-                                return invalid_data_error!(
-                                    "Get ref_id or attributes of frame failed."
+                                return parse_error(
+                                    line,
+                                    &text(),
+                                    "Get ref_id or attributes of frame failed",
                                 );
                             };
                             frames.push(frame);
@@ -524,7 +618,13 @@ impl Folder {
                 | Event::PI(_)
                 | Event::DocType(_)
                 | Event::GeneralRef(_) => {}
-                Event::Eof => return invalid_data_error!("Unexpected EOF"),
+                Event::Eof => match self.state_backtrace.top_mut() {
+                    Some(tag) => {
+                        let text = tag_text("/", tag.name(), "");
+                        return parse_error(line, &text, "File ended before closing tag");
+                    }
+                    None => break Vec::new(),
+                },
             }
         };
 
@@ -541,6 +641,7 @@ impl Folder {
                 .entry(backtrace)
                 .or_insert_with(|| BacktraceOccurrences { num: 0, backtrace });
             frame.num += 1;
+            self.stats.events += 1;
         }
 
         let mut occurrences = Occurrences::new(1);
@@ -548,6 +649,13 @@ impl Folder {
         for BacktraceOccurrences { num, backtrace } in backtrace_occurrences.into_values() {
             occurrences.insert_or_add(backtrace.resolve(self), num);
         }
+        if self.stats.events == 0 {
+            if self.opt.strict {
+                return Err(Error::EmptyInput.into());
+            }
+            warn!("No samples found in input");
+        }
+        self.stats.count_occurrences(&occurrences);
         occurrences.write_and_clear(writer)
     }
 }
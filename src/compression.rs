@@ -0,0 +1,129 @@
+#[cfg(any(feature = "gzip", feature = "zstd", feature = "xz"))]
+use std::io::BufReader;
+use std::io::{self, BufRead};
+
+use log::debug;
+
+#[cfg(any(feature = "gzip", feature = "zstd", feature = "xz"))]
+use crate::collapse::common::CAPACITY_READER;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Format {
+    fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Format::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Format::Zstd)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Format::Xz)
+        } else {
+            None
+        }
+    }
+
+    /// The name of the format, which is also the name of the cargo feature that decodes it.
+    fn name(self) -> &'static str {
+        match self {
+            Format::Gzip => "gzip",
+            Format::Zstd => "zstd",
+            Format::Xz => "xz",
+        }
+    }
+}
+
+/// Decompresses `reader` if it starts with the magic bytes of gzip, zstd, or xz data, and returns
+/// it as is otherwise.
+///
+/// Each format can only be decompressed if the cargo feature of the same name is enabled;
+/// compressed input in any other format is an error.
+pub(crate) fn decompress<'a, R>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>>
+where
+    R: BufRead + 'a,
+{
+    let format = match Format::detect(reader.fill_buf()?) {
+        Some(format) => format,
+        None => return Ok(Box::new(reader)),
+    };
+    debug!("Decompressing {} input", format.name());
+    match format {
+        #[cfg(feature = "gzip")]
+        Format::Gzip => {
+            let decoder = flate2::bufread::MultiGzDecoder::new(reader);
+            Ok(Box::new(BufReader::with_capacity(CAPACITY_READER, decoder)))
+        }
+        #[cfg(feature = "zstd")]
+        Format::Zstd => {
+            let decoder = zstd::stream::read::Decoder::with_buffer(reader)?;
+            Ok(Box::new(BufReader::with_capacity(CAPACITY_READER, decoder)))
+        }
+        #[cfg(feature = "xz")]
+        Format::Xz => {
+            let decoder = liblzma::bufread::XzDecoder::new_multi_decoder(reader);
+            Ok(Box::new(BufReader::with_capacity(CAPACITY_READER, decoder)))
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Input is {0}-compressed, but inferno was built without the `{0}` feature",
+                format.name()
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn read_all(input: &[u8]) -> io::Result<String> {
+        let mut out = String::new();
+        decompress(input)?.read_to_string(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn passes_through_plain_input() {
+        assert_eq!(read_all(b"main;foo 1\n").unwrap(), "main;foo 1\n");
+        assert_eq!(read_all(b"").unwrap(), "");
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(Format::detect(&[0x1f, 0x8b, 0x08]), Some(Format::Gzip));
+        assert_eq!(
+            Format::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Some(Format::Zstd)
+        );
+        assert_eq!(Format::detect(b"\xfd7zXZ\x00\x00"), Some(Format::Xz));
+        assert_eq!(Format::detect(b"\x1f"), None);
+        assert_eq!(Format::detect(b"perf 1234"), None);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn decompresses_gzip() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(b"main;foo 1\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(read_all(&compressed).unwrap(), "main;foo 1\n");
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn rejects_formats_without_feature() {
+        let err = read_all(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("`zstd` feature"));
+    }
+}
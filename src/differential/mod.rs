@@ -5,6 +5,9 @@ use std::path::Path;
 use ahash::AHashMap;
 use log::warn;
 
+use crate::compression;
+use crate::Error;
+
 const READER_CAPACITY: usize = 128 * 1024;
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -28,6 +31,9 @@ pub struct Options {
 
     /// Strip hex numbers (addresses) of the form "0x45ef2173" and replace with "0x...".
     pub strip_hex: bool,
+
+    /// Fail with an [`Error::Parse`] rather than log a warning when a line can't be parsed.
+    pub strict: bool,
 }
 
 /// Produce an output that can be used to generate a differential flame graph.
@@ -49,18 +55,17 @@ where
     let mut stack_counts = AHashMap::default();
     let total1 = parse_stack_counts(opt, &mut stack_counts, before, true)?;
     let total2 = parse_stack_counts(opt, &mut stack_counts, after, false)?;
-    if opt.normalize && total1 != total2 {
-        for counts in stack_counts.values_mut() {
-            counts.first = (counts.first as f64 * total2 as f64 / total1 as f64) as usize;
-        }
-    }
-    write_stacks(&stack_counts, writer)
+    write_diff(opt, stack_counts, total1, total2, writer)
 }
 
 /// Produce an output that can be used to generate a differential flame graph from
 /// a before and an after profile.
 ///
-/// See [`from_readers`] for the input and output formats.
+/// See [`from_readers`] for the input and output formats. Files compressed with gzip, zstd, or xz
+/// are decompressed if the cargo feature of the same name is enabled.
+///
+/// If a line cannot be parsed with [`Options::strict`] set, the [`Error::Parse`] that is returned
+/// includes the path of its file.
 pub fn from_files<P1, P2, W>(
     opt: Options,
     file_before: P1,
@@ -72,11 +77,27 @@ where
     P2: AsRef<Path>,
     W: Write,
 {
-    let file1 = File::open(file_before)?;
+    let file1 = File::open(&file_before)?;
     let reader1 = io::BufReader::with_capacity(READER_CAPACITY, file1);
-    let file2 = File::open(file_after)?;
+    let file2 = File::open(&file_after)?;
     let reader2 = io::BufReader::with_capacity(READER_CAPACITY, file2);
-    from_readers(opt, reader1, reader2, writer)
+
+    let mut stack_counts = AHashMap::default();
+    let total1 = parse_stack_counts(
+        opt,
+        &mut stack_counts,
+        compression::decompress(reader1)?,
+        true,
+    )
+    .map_err(|e| Error::with_path(e, file_before.as_ref()))?;
+    let total2 = parse_stack_counts(
+        opt,
+        &mut stack_counts,
+        compression::decompress(reader2)?,
+        false,
+    )
+    .map_err(|e| Error::with_path(e, file_after.as_ref()))?;
+    write_diff(opt, stack_counts, total1, total2, writer)
 }
 
 // Populate stack_counts based on lines from the reader and returns the sum of the sample counts.
@@ -91,6 +112,7 @@ where
 {
     let mut total = 0;
     let mut line = Vec::new();
+    let mut line_number = 0;
     let mut stripped_fractional_samples = false;
     loop {
         line.clear();
@@ -98,6 +120,7 @@ where
         if reader.read_until(0x0A, &mut line)? == 0 {
             break;
         }
+        line_number += 1;
 
         let l = String::from_utf8_lossy(&line);
         if let Some((stack, count)) =
@@ -110,6 +133,8 @@ where
                 counts.second += count;
             }
             total += count;
+        } else if opt.strict {
+            return Err(Error::parse(line_number, &l, "Unable to parse line").into());
         } else {
             warn!("Unable to parse line: {}", l);
         }
@@ -118,6 +143,25 @@ where
     Ok(total)
 }
 
+// Normalize the counts if asked to, and write them.
+fn write_diff<W>(
+    opt: Options,
+    mut stack_counts: AHashMap<String, Counts>,
+    total1: usize,
+    total2: usize,
+    writer: W,
+) -> io::Result<()>
+where
+    W: Write,
+{
+    if opt.normalize && total1 != total2 {
+        for counts in stack_counts.values_mut() {
+            counts.first = (counts.first as f64 * total2 as f64 / total1 as f64) as usize;
+        }
+    }
+    write_stacks(&stack_counts, writer)
+}
+
 // Write three-column lines with the folded stack trace and two value columns,
 // one for each profile.
 fn write_stacks<W>(stack_counts: &AHashMap<String, Counts>, mut writer: W) -> io::Result<()>
@@ -0,0 +1,171 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The errors that collapsing stacks or plotting a flame graph can fail with.
+///
+/// The public functions of inferno return [`io::Result`], so that they fit in with the readers and
+/// writers they work on. Every error other than [`Error::Io`] is wrapped in an [`io::Error`] of
+/// kind [`io::ErrorKind::InvalidData`], and can be recovered with [`Error::from_io`]:
+///
+/// ```
+/// use inferno::collapse::{sample, Collapse};
+///
+/// let input = "Analysis of sampling pid 1 every 1 millisecond\n\
+///              Call graph:\n    \
+///              1 Thread_1\n          \
+///              1 start\n";
+/// let error = sample::Folder::default()
+///     .collapse(input.as_bytes(), std::io::sink())
+///     .unwrap_err();
+/// match inferno::Error::from_io(error) {
+///     inferno::Error::Parse { line, .. } => assert_eq!(line, 4),
+///     e => panic!("unexpected error: {}", e),
+/// }
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A line of the input could not be parsed.
+    Parse {
+        /// The file the line was read from, if the input was read from a file.
+        path: Option<PathBuf>,
+        /// The 1-based number of the line within the input.
+        line: usize,
+        /// The offending line.
+        text: String,
+        /// What was wrong with the line.
+        message: String,
+    },
+    /// The input did not match any of the supported formats.
+    UnknownFormat,
+    /// The input did not contain any stacks.
+    EmptyInput,
+    /// Reading the input or writing the output failed.
+    Io(io::Error),
+}
+
+impl Error {
+    pub(crate) fn parse(line: usize, text: &str, message: impl Into<String>) -> Self {
+        Error::Parse {
+            path: None,
+            line,
+            text: text.trim_end().to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// Recovers the `Error` that `error` was created from, or wraps `error` in [`Error::Io`] if it
+    /// did not come from one.
+    pub fn from_io(error: io::Error) -> Self {
+        if error.get_ref().is_some_and(|e| e.is::<Error>()) {
+            *error
+                .into_inner()
+                .expect("checked above")
+                .downcast::<Error>()
+                .expect("checked above")
+        } else {
+            Error::Io(error)
+        }
+    }
+
+    /// Sets the path of a [`Error::Parse`] wrapped in `error`, if it does not have one already.
+    pub(crate) fn with_path(error: io::Error, path: &Path) -> io::Error {
+        match Error::from_io(error) {
+            Error::Parse {
+                path: None,
+                line,
+                text,
+                message,
+            } => Error::Parse {
+                path: Some(path.to_path_buf()),
+                line,
+                text,
+                message,
+            }
+            .into(),
+            e => e.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse {
+                path,
+                line,
+                text,
+                message,
+            } => {
+                write!(f, "{} on line {}", message, line)?;
+                if let Some(path) = path {
+                    write!(f, " of {}", path.display())?;
+                }
+                write!(f, ":\n{}", text)
+            }
+            Error::UnknownFormat => {
+                write!(f, "No applicable collapse implementation found for input")
+            }
+            Error::EmptyInput => write!(f, "No stacks found in input"),
+            Error::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::from_io(error)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_io_error() {
+        let error: io::Error = Error::parse(3, "foo bar\n", "Bad line").into();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Bad line on line 3:\nfoo bar");
+
+        let error = Error::with_path(error, Path::new("in.txt"));
+        assert_eq!(error.to_string(), "Bad line on line 3 of in.txt:\nfoo bar");
+        match Error::from_io(error) {
+            Error::Parse { path, line, .. } => {
+                assert_eq!(path.as_deref(), Some(Path::new("in.txt")));
+                assert_eq!(line, 3);
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn wraps_other_io_errors() {
+        let error = io::Error::new(io::ErrorKind::NotFound, "gone");
+        assert!(
+            matches!(Error::from_io(error), Error::Io(e) if e.kind() == io::ErrorKind::NotFound)
+        );
+        let error: io::Error = Error::EmptyInput.into();
+        assert!(matches!(Error::from_io(error), Error::EmptyInput));
+    }
+}
@@ -0,0 +1,245 @@
+use std::env;
+use std::io::{self, Write};
+
+use log::error;
+
+use super::color::Color;
+use super::merge::TimedFrame;
+use super::{deannotate, frame_color, rand, Direction, Layout, Options};
+use crate::Error;
+
+/// The width used when neither [`Options::terminal_width`] nor `$COLUMNS` is set.
+const DEFAULT_COLUMNS: usize = 80;
+
+const RESET: &str = "\x1b[0m";
+
+/// The character of a cell that the wide character in the cell before it spills into.
+const COVERED: char = '\0';
+
+/// Writes the flame graph as lines of text colored with ANSI escape codes.
+///
+/// Every frame is a row of cells with the background color it would have in the SVG, labeled
+/// with as much of its function name as fits. The plot is [`Options::terminal_width`] columns
+/// wide, and frames that are narrower than a column are left out. Colors use 24-bit escape
+/// codes, which most terminal emulators support.
+pub(super) fn write<W: Write>(
+    opt: &mut Options<'_>,
+    mut frames: Vec<TimedFrame<'_>>,
+    time: u64,
+    delta_max: usize,
+    mut writer: W,
+) -> io::Result<()> {
+    if time == 0 {
+        if opt.strict {
+            return Err(Error::EmptyInput.into());
+        }
+        error!("No stack counts found");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No stack counts found",
+        ));
+    }
+
+    let columns = opt
+        .terminal_width
+        .or_else(|| env::var("COLUMNS").ok()?.parse().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(DEFAULT_COLUMNS);
+
+    let layout = Layout::new(opt, &mut frames, time);
+    let depthmax = frames.iter().map(|f| f.location.depth).max().unwrap_or(0);
+    let mut rows = vec![vec![Cell::default(); columns]; depthmax + 1];
+
+    let column = |pct: f64| ((pct / 100.0 * columns as f64).round() as usize).min(columns);
+    let mut thread_rng = rand::thread_rng();
+    for frame in &frames {
+        let rect = layout.rect(opt, frame);
+        let (start, end) = (column(rect.x1_pct), column(rect.x2_pct));
+        if start == end {
+            continue;
+        }
+
+        let color = frame_color(opt, frame, &rect, delta_max, &mut thread_rng);
+        let function = if frame.location.depth == 0 && frame.location.function.is_empty() {
+            "all"
+        } else {
+            deannotate(frame.location.function)
+        };
+        let label = label(function, end - start);
+        let mut chars = label.chars().chain(std::iter::repeat(' '));
+        let mut cells = rows[frame.location.depth][start..end].iter_mut();
+        while let (Some(cell), Some(c)) = (cells.next(), chars.next()) {
+            *cell = Cell {
+                color: Some(color),
+                c,
+            };
+            if cell_width(c) == 2 {
+                if let Some(cell) = cells.next() {
+                    *cell = Cell {
+                        color: Some(color),
+                        c: COVERED,
+                    };
+                }
+            }
+        }
+    }
+
+    writeln!(writer, "{}", center(&opt.title, columns))?;
+    if let Some(subtitle) = &opt.subtitle {
+        writeln!(writer, "{}", center(subtitle, columns))?;
+    }
+    match opt.direction {
+        Direction::Straight => {
+            for row in rows.iter().rev() {
+                write_row(&mut writer, row)?;
+            }
+        }
+        Direction::Inverted => {
+            for row in &rows {
+                write_row(&mut writer, row)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    color: Option<Color>,
+    c: char,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            color: None,
+            c: ' ',
+        }
+    }
+}
+
+/// Writes a row of cells, switching colors only where they change.
+fn write_row<W: Write>(writer: &mut W, row: &[Cell]) -> io::Result<()> {
+    // Blank cells at the end of the row would only be trailing whitespace.
+    let end = row
+        .iter()
+        .rposition(|cell| cell.color.is_some())
+        .map_or(0, |i| i + 1);
+    let row = &row[..end];
+    let mut current = None;
+    let mut buf = [0; 4];
+    for cell in row {
+        if cell.color != current {
+            match cell.color {
+                Some(Color { r, g, b }) => write!(writer, "\x1b[48;2;{};{};{};30m", r, g, b)?,
+                None => writer.write_all(RESET.as_bytes())?,
+            }
+            current = cell.color;
+        }
+        if cell.c != COVERED {
+            writer.write_all(cell.c.encode_utf8(&mut buf).as_bytes())?;
+        }
+    }
+    if current.is_some() {
+        writer.write_all(RESET.as_bytes())?;
+    }
+    writeln!(writer)
+}
+
+/// The part of `function` that fits in `width` cells, with `..` appended if it was cut short.
+///
+/// Labels start with a blank cell, so that neighbouring frames can be told apart. Control
+/// characters are replaced with `?`, so that function names can't inject escape sequences.
+fn label(function: &str, width: usize) -> String {
+    let function: String = function
+        .chars()
+        .map(|c| if c.is_control() { '?' } else { c })
+        .collect();
+    let fitcells = width.saturating_sub(1);
+    let len: usize = function.chars().map(cell_width).sum();
+    if len <= fitcells {
+        format!(" {}", function)
+    } else if fitcells >= 3 {
+        let mut used = 0;
+        let f: String = function
+            .chars()
+            .take_while(|&c| {
+                used += cell_width(c);
+                used <= fitcells - 2
+            })
+            .collect();
+        format!(" {}..", f)
+    } else {
+        String::new()
+    }
+}
+
+/// The number of cells that `c` takes up in a terminal: 2 for the wide characters of East Asian
+/// scripts and for emoji, and 1 for any other character.
+fn cell_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+fn center(text: &str, columns: usize) -> String {
+    let len = text.chars().count();
+    format!("{:>1$}", text, len + columns.saturating_sub(len) / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_are_truncated() {
+        assert_eq!(label("main", 10), " main");
+        assert_eq!(label("main", 5), " main");
+        assert_eq!(label("main", 4), " m..");
+        assert_eq!(label("main", 3), "");
+    }
+
+    #[test]
+    fn labels_have_no_control_characters() {
+        assert_eq!(label("evil\x1b[2Jframe\n", 20), " evil?[2Jframe?");
+    }
+
+    #[test]
+    fn wide_characters_take_two_cells() {
+        assert_eq!(label("\u{5b57}\u{5b57}", 5), " \u{5b57}\u{5b57}");
+        assert_eq!(label("\u{5b57}\u{5b57}\u{5b57}", 6), " \u{5b57}..");
+
+        let mut out = Vec::new();
+        let cell = |c| Cell {
+            color: Some(Color { r: 0, g: 0, b: 0 }),
+            c,
+        };
+        write_row(
+            &mut out,
+            &[cell(' '), cell('\u{5b57}'), cell(COVERED), cell('a')],
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(" \u{5b57}a"), "{:?}", out);
+    }
+
+    #[test]
+    fn titles_are_centered() {
+        assert_eq!(center("ab", 6), "  ab");
+        assert_eq!(center("abcdefgh", 6), "abcdefgh");
+    }
+}
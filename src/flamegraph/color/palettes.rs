@@ -49,6 +49,9 @@ pub(super) mod perl {
     pub(in super::super) fn resolve(name: &str) -> BasicPalette {
         if name.ends_with("_[k]") {
             BasicPalette::Orange
+        } else if name.ends_with("_[i]") {
+            // inline annotation
+            BasicPalette::Aqua
         } else if name.contains("Perl") || name.contains(".pl") {
             BasicPalette::Green
         } else if name.contains("::") {
@@ -93,6 +96,9 @@ pub(super) mod js {
             return BasicPalette::Green;
         } else if name.ends_with("_[k]") {
             return BasicPalette::Orange;
+        } else if name.ends_with("_[i]") {
+            // inline annotation
+            return BasicPalette::Blue;
         } else if name.ends_with("_[j]") {
             if name.contains('/') {
                 return BasicPalette::Green;
@@ -128,7 +134,10 @@ pub(super) mod rust {
 
     pub(in super::super) fn resolve(name: &str) -> BasicPalette {
         let name = name.split_once('`').map(|(_, after)| after).unwrap_or(name);
-        if name.starts_with("core::")
+        if name.ends_with("_[i]") {
+            // inlined functions
+            BasicPalette::Purple
+        } else if name.starts_with("core::")
             || name.starts_with("std::")
             || name.starts_with("alloc::")
             || (name.starts_with("<core::")
@@ -456,6 +465,10 @@ mod tests {
                 input: String::from("somethingPerl"),
                 output: BasicPalette::Green,
             },
+            TestData {
+                input: String::from("some::thing_[i]"),
+                output: BasicPalette::Aqua,
+            },
         ];
 
         for item in test_names.iter() {
@@ -530,6 +543,10 @@ mod tests {
                 input: String::from("something_[j]"),
                 output: BasicPalette::Aqua,
             },
+            TestData {
+                input: String::from("something_[i]"),
+                output: BasicPalette::Blue,
+            },
             TestData {
                 input: String::from("some::thing"),
                 output: BasicPalette::Yellow,
@@ -608,6 +625,10 @@ mod tests {
                 input: String::from("my-app`foobar::extent::Extent::write"),
                 output: BasicPalette::Aqua,
             },
+            TestData {
+                input: String::from("core::ptr::drop_in_place_[i]"),
+                output: BasicPalette::Purple,
+            },
         ];
         for elem in test_names.iter() {
             let result = rust::resolve(&elem.input);
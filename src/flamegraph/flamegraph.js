@@ -1,5 +1,5 @@
 "use strict";
-var details, searchbtn, unzoombtn, matchedtxt, svg, searching, frames, known_font_width;
+var details, searchbtn, unzoombtn, matchedtxt, svg, searching, frames, panes, known_font_width;
 function init(evt) {
     details = document.getElementById("details").firstChild;
     searchbtn = document.getElementById("search");
@@ -7,6 +7,8 @@ function init(evt) {
     matchedtxt = document.getElementById("matched");
     svg = document.getElementsByTagName("svg")[0];
     frames = document.getElementById("frames");
+    // Sandwich and combined graphs have two panes of frames, that are zoomed separately.
+    panes = document.querySelectorAll("#frames, svg.frames");
     known_font_width = get_monospace_width(frames);
     total_samples = parseInt(frames.attributes.total_samples.value);
     searching = 0;
@@ -33,11 +35,13 @@ function init(evt) {
                 svg.attributes.viewBox.value = "0 0 " + svg.width.baseVal.value + " " + svg.height.baseVal.value;
             }
 
-            // Keep consistent padding on left and right of frames container.
-            frames.attributes.width.value = svg.width.baseVal.value - xpad * 2;
+            for (var i = 0; i < panes.length; i++) {
+                // Keep consistent padding on left and right of frames container.
+                panes[i].attributes.width.value = svg.width.baseVal.value - xpad * 2;
 
-            // Text truncation needs to be adjusted for the current width.
-            update_text_for_elements(frames.children);
+                // Text truncation needs to be adjusted for the current width.
+                update_text_for_elements(panes[i].children);
+            }
 
             // Keep search elements at a fixed distance from right edge.
             var svgWidth = svg.width.baseVal.value;
@@ -67,6 +71,7 @@ window.addEventListener("click", function(e) {
         }
         if (target.classList.contains("parent")) unzoom();
         zoom(target);
+        outline_in_other_panes(target);
 
         // set parameters for zoom state
         var el = target.querySelector("rect");
@@ -79,6 +84,7 @@ window.addEventListener("click", function(e) {
     }
     else if (e.target.id == "unzoom") {
         unzoom();
+        clear_outlines();
 
         // remove zoom state
         var params = get_params();
@@ -136,9 +142,45 @@ function find_child(node, selector) {
 function find_group(node) {
     var parent = node.parentElement;
     if (!parent) return;
-    if (parent.id == "frames") return node;
+    if (parent.id == "frames" || parent.classList.contains("frames")) return node;
     return find_group(parent);
 }
+function is_inverted(pane) {
+    var attr = pane.attributes["fg:inverted"];
+    if (attr == undefined) return inverted;
+    return attr.value == "true";
+}
+function g_to_name(e) {
+    var text = g_to_text(e);
+    return text.substring(0, text.lastIndexOf(" ("));
+}
+// In an SVG with more than one pane of frames, outline the frames of the same function as `node`
+// in the other panes.
+function outline_in_other_panes(node) {
+    clear_outlines();
+    if (panes.length < 2) return;
+    var name = g_to_name(node);
+    for (var p = 0; p < panes.length; p++) {
+        if (panes[p] === node.parentElement) continue;
+        var el = panes[p].children;
+        for (var i = 0; i < el.length; i++) {
+            if (g_to_name(el[i]) != name) continue;
+            var rect = find_child(el[i], "rect");
+            rect.style.stroke = searchcolor;
+            rect.style.strokeWidth = "2px";
+            el[i].classList.add("outlined");
+        }
+    }
+}
+function clear_outlines() {
+    var el = document.querySelectorAll(".outlined");
+    for (var i = 0; i < el.length; i++) {
+        var rect = find_child(el[i], "rect");
+        rect.style.stroke = "";
+        rect.style.strokeWidth = "";
+        el[i].classList.remove("outlined");
+    }
+}
 function orig_save(e, attr, val) {
     if (e.attributes["fg:orig_" + attr] != undefined) return;
     if (e.attributes[attr] == undefined) return;
@@ -271,14 +313,14 @@ function update_text(e) {
     t.textContent = "";
 }
 // zoom
-function zoom_reset(e) {
+function zoom_reset(e, total_samples) {
     if (e.tagName == "rect") {
         e.attributes.x.value = format_percent(100 * parseInt(e.attributes["fg:x"].value) / total_samples);
         e.attributes.width.value = format_percent(100 * parseInt(e.attributes["fg:w"].value) / total_samples);
     }
     if (e.childNodes == undefined) return;
     for(var i = 0, c = e.childNodes; i < c.length; i++) {
-        zoom_reset(c[i]);
+        zoom_reset(c[i], total_samples);
     }
 }
 function zoom_child(e, x, zoomed_width_samples) {
@@ -315,7 +357,9 @@ function zoom(node) {
     var xmax = xmin + width;
     var ymin = parseFloat(attr.y.value);
     unzoombtn.classList.remove("hide");
-    var el = frames.children;
+    var pane = node.parentElement;
+    var pane_inverted = is_inverted(pane);
+    var el = pane.children;
     var to_update_text = [];
     for (var i = 0; i < el.length; i++) {
         var e = el[i];
@@ -323,7 +367,7 @@ function zoom(node) {
         var ex = parseInt(a["fg:x"].value);
         var ew = parseInt(a["fg:w"].value);
         // Is it an ancestor
-        if (!inverted) {
+        if (!pane_inverted) {
             var upstack = parseFloat(a.y.value) > ymin;
         } else {
             var upstack = parseFloat(a.y.value) < ymin;
@@ -355,17 +399,20 @@ function zoom(node) {
 }
 function unzoom() {
     unzoombtn.classList.add("hide");
-    var el = frames.children;
-    for(var i = 0; i < el.length; i++) {
-        el[i].classList.remove("parent");
-        el[i].classList.remove("hide");
-        zoom_reset(el[i]);
+    for (var p = 0; p < panes.length; p++) {
+        var el = panes[p].children;
+        var total_samples = parseInt(panes[p].attributes.total_samples.value);
+        for(var i = 0; i < el.length; i++) {
+            el[i].classList.remove("parent");
+            el[i].classList.remove("hide");
+            zoom_reset(el[i], total_samples);
+        }
+        update_text_for_elements(el);
     }
-    update_text_for_elements(el);
 }
 // search
 function reset_search() {
-    var el = document.querySelectorAll("#frames rect");
+    var el = document.querySelectorAll("#frames rect, svg.frames rect");
     for (var i = 0; i < el.length; i++) {
         orig_load(el[i], "fill")
     }
@@ -391,7 +438,28 @@ function search_prompt() {
 }
 function search(term) {
     var re = new RegExp(term);
-    var el = frames.children;
+    // The panes show the same samples, so report the one where the matches cover the most.
+    var pct = 0;
+    for (var p = 0; p < panes.length; p++) {
+        var pane_pct = search_pane(panes[p], re);
+        if (pane_pct > pct)
+            pct = pane_pct;
+    }
+    if (!searching)
+        return;
+    var params = get_params();
+    params.s = term;
+    history.replaceState(null, null, parse_params(params));
+
+    searchbtn.classList.add("show");
+    searchbtn.firstChild.nodeValue = "Reset Search";
+    // display matched percent
+    matchedtxt.classList.remove("hide");
+    if (pct != 100) pct = pct.toFixed(1);
+    matchedtxt.firstChild.nodeValue = "Matched: " + pct + "%";
+}
+function search_pane(pane, re) {
+    var el = pane.children;
     var matches = new Object();
     var maxwidth = 0;
     for (var i = 0; i < el.length; i++) {
@@ -425,14 +493,6 @@ function search(term) {
             searching = 1;
         }
     }
-    if (!searching)
-        return;
-    var params = get_params();
-    params.s = term;
-    history.replaceState(null, null, parse_params(params));
-
-    searchbtn.classList.add("show");
-    searchbtn.firstChild.nodeValue = "Reset Search";
     // calculate percent matched, excluding vertical overlap
     var count = 0;
     var lastx = -1;
@@ -459,11 +519,9 @@ function search(term) {
             lastw = w;
         }
     }
-    // display matched percent
-    matchedtxt.classList.remove("hide");
-    var pct = 100 * count / maxwidth;
-    if (pct != 100) pct = pct.toFixed(1);
-    matchedtxt.firstChild.nodeValue = "Matched: " + pct + "%";
+    if (maxwidth == 0)
+        return 0;
+    return 100 * count / maxwidth;
 }
 function format_percent(n) {
     return n.toFixed(4) + "%";
@@ -0,0 +1,224 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use quick_xml::escape::escape;
+
+use super::merge::TimedFrame;
+use super::{deannotate, json, walk_frames, write_svg, Options};
+
+/// Writes an HTML page with the SVG flame graph and a report on the functions in it.
+///
+/// The report has a table of the self and total counts of every function, a search box that
+/// takes regular expressions, and a pane with the callers and callees of the function selected
+/// in the table or the flame graph. Everything is inlined, so the page works offline.
+pub(super) fn write<W: Write>(
+    opt: &mut Options<'_>,
+    frames: Vec<TimedFrame<'_>>,
+    time: u64,
+    delta_max: usize,
+    mut writer: W,
+) -> io::Result<()> {
+    let report = Report::new(&frames);
+
+    let mut svg = Vec::new();
+    write_svg(opt, frames, time, delta_max, &mut svg)?;
+    // The XML declaration and doctype of the SVG have no place in an HTML document.
+    let start = svg.windows(4).position(|w| w == b"<svg").unwrap_or(0);
+
+    let mut data = Vec::new();
+    report.write_json(opt, time, &mut data)?;
+
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html lang=\"en\">")?;
+    writeln!(writer, "<head>")?;
+    writeln!(writer, "<meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>{}</title>", escape(&opt.title))?;
+    writeln!(writer, "<style>\n{}</style>", include_str!("report.css"))?;
+    writeln!(writer, "</head>")?;
+    writeln!(writer, "<body>")?;
+    writeln!(writer, "<div id=\"flamegraph\">")?;
+    writer.write_all(&svg[start..])?;
+    writeln!(writer, "\n</div>")?;
+    writer.write_all(REPORT_HTML.as_bytes())?;
+    write!(
+        writer,
+        "<script type=\"application/json\" id=\"report-data\">"
+    )?;
+    writer.write_all(&data)?;
+    writeln!(writer, "</script>")?;
+    writeln!(writer, "<script>\n{}</script>", include_str!("report.js"))?;
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")?;
+    writer.flush()
+}
+
+const REPORT_HTML: &str = r#"<div id="report">
+<div id="functions-pane">
+<input id="report-search" type="search" placeholder="Search functions (regexp allowed, eg: ^ext4_)" spellcheck="false">
+<table id="functions">
+<thead><tr><th data-key="name">Function</th><th data-key="self">Self</th><th data-key="total">Total</th></tr></thead>
+<tbody></tbody>
+</table>
+</div>
+<div id="sandwich-pane">
+<h2 id="selected">Select a function in the table or the flame graph</h2>
+<h3>Callers</h3>
+<table id="callers"><tbody></tbody></table>
+<h3>Callees</h3>
+<table id="callees"><tbody></tbody></table>
+</div>
+</div>
+"#;
+
+/// The self and total counts of every function, and how much of them are spent in calls from and
+/// to other functions.
+#[derive(Debug, Default)]
+struct Report<'a> {
+    functions: Vec<Function<'a>>,
+}
+
+#[derive(Debug)]
+struct Function<'a> {
+    name: &'a str,
+    self_count: u64,
+    total_count: u64,
+    callers: BTreeMap<usize, u64>,
+    callees: BTreeMap<usize, u64>,
+}
+
+impl<'a> Report<'a> {
+    fn new(frames: &[TimedFrame<'a>]) -> Self {
+        let mut report = Report::default();
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+        // The function of every frame.
+        let mut frame_ids: Vec<usize> = vec![0; frames.len()];
+        let position = |f: &TimedFrame<'_>| (f.start_time, f.location.depth);
+        walk_frames(frames, position, |i, ancestors| {
+            let frame = &frames[i];
+            let name = deannotate(frame.location.function);
+            let id = *ids.entry(name).or_insert_with(|| {
+                report.functions.push(Function {
+                    name,
+                    self_count: 0,
+                    total_count: 0,
+                    callers: BTreeMap::new(),
+                    callees: BTreeMap::new(),
+                });
+                report.functions.len() - 1
+            });
+            frame_ids[i] = id;
+
+            let count = frame.end_time - frame.start_time;
+            report.functions[id].self_count += count;
+            // Recursive calls are already counted in the total of the outermost call.
+            if !ancestors.iter().any(|&a| frame_ids[a] == id) {
+                report.functions[id].total_count += count;
+            }
+            if let Some(&parent) = ancestors.last() {
+                let parent = frame_ids[parent];
+                report.functions[parent].self_count -= count;
+                *report.functions[parent].callees.entry(id).or_default() += count;
+                *report.functions[id].callers.entry(parent).or_default() += count;
+            }
+        });
+        report
+    }
+
+    fn write_json<W: Write>(&self, opt: &Options<'_>, time: u64, writer: &mut W) -> io::Result<()> {
+        let mut out = Vec::new();
+        out.write_all(b"{\"title\":")?;
+        json::write_str(&mut out, &opt.title)?;
+        out.write_all(b",\"countName\":")?;
+        json::write_str(&mut out, &opt.count_name)?;
+        write!(
+            out,
+            ",\"factor\":{},\"total\":{},\"functions\":[",
+            opt.factor, time
+        )?;
+        for (i, function) in self.functions.iter().enumerate() {
+            if i != 0 {
+                out.write_all(b",")?;
+            }
+            out.write_all(b"\n{\"name\":")?;
+            json::write_str(&mut out, function.name)?;
+            write!(
+                out,
+                ",\"self\":{},\"total\":{},\"callers\":",
+                function.self_count, function.total_count
+            )?;
+            write_edges(&mut out, &function.callers)?;
+            out.write_all(b",\"callees\":")?;
+            write_edges(&mut out, &function.callees)?;
+            out.write_all(b"}")?;
+        }
+        out.write_all(b"]}")?;
+
+        // `</` can't appear in a script element, but `<\/` means the same in a JSON string.
+        let mut start = 0;
+        for i in 0..out.len().saturating_sub(1) {
+            if &out[i..i + 2] == b"</" {
+                writer.write_all(&out[start..=i])?;
+                writer.write_all(b"\\")?;
+                start = i + 1;
+            }
+        }
+        writer.write_all(&out[start..])
+    }
+}
+
+fn write_edges<W: Write>(writer: &mut W, edges: &BTreeMap<usize, u64>) -> io::Result<()> {
+    writer.write_all(b"[")?;
+    for (i, (id, count)) in edges.iter().enumerate() {
+        if i != 0 {
+            writer.write_all(b",")?;
+        }
+        write!(writer, "[{},{}]", id, count)?;
+    }
+    writer.write_all(b"]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flamegraph::merge;
+
+    fn report(lines: &[&'static str]) -> Vec<(&'static str, u64, u64)> {
+        let (frames, _, _, _) = merge::frames(lines.iter().copied(), false).unwrap();
+        let mut report = Report::new(&frames);
+        report.functions.sort_by_key(|f| f.name);
+        report
+            .functions
+            .iter()
+            .map(|f| (f.name, f.self_count, f.total_count))
+            .collect()
+    }
+
+    #[test]
+    fn counts_self_and_total() {
+        assert_eq!(
+            report(&["main;a 2", "main;a;b 3", "main;b 1"]),
+            [("a", 2, 5), ("b", 4, 4), ("main", 0, 6)]
+        );
+    }
+
+    #[test]
+    fn counts_recursion_once() {
+        assert_eq!(
+            report(&["main;f 1", "main;f;f;f 2"]),
+            [("f", 3, 3), ("main", 0, 3)]
+        );
+    }
+
+    #[test]
+    fn escapes_closing_tags() {
+        let frames = Vec::new();
+        let opt = Options {
+            title: "</script>".to_owned(),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        Report::new(&frames).write_json(&opt, 0, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(r#"{"title":"<\/script>""#), "{}", out);
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::{self, Write};
+
+/// Writes `s` as a JSON string, with quotes.
+pub(crate) fn write_str<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        let escaped = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            c if c < ' ' => {
+                writer.write_all(&s.as_bytes()[start..i])?;
+                write!(writer, "\\u{:04x}", c as u32)?;
+                start = i + c.len_utf8();
+                continue;
+            }
+            _ => continue,
+        };
+        writer.write_all(&s.as_bytes()[start..i])?;
+        writer.write_all(escaped.as_bytes())?;
+        start = i + c.len_utf8();
+    }
+    writer.write_all(&s.as_bytes()[start..])?;
+    writer.write_all(b"\"")
+}
+
+/// Writes `count` multiplied by `factor` as a JSON number.
+///
+/// The result is rounded to nine decimal places, so that a count of `386001` with a factor of
+/// `0.001` is written as `386.001` rather than `386.00100000000003`.
+pub(super) fn write_scaled<W: Write>(writer: &mut W, count: f64, factor: f64) -> io::Result<()> {
+    let value = count * factor;
+    if factor.fract() == 0.0 {
+        write!(writer, "{}", value)
+    } else {
+        write!(writer, "{}", (value * 1e9).round() / 1e9)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        let mut out = Vec::new();
+        write_str(&mut out, "a\"b\\c\nd\u{1}é").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#""a\"b\\c\nd\u0001é""#);
+    }
+
+    #[test]
+    fn scales_numbers() {
+        let mut out = Vec::new();
+        write_scaled(&mut out, 386001.0, 0.001).unwrap();
+        out.push(b' ');
+        write_scaled(&mut out, -3.0, 2.0).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "386.001 -6");
+    }
+}
@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::io;
 use std::iter;
@@ -204,6 +205,76 @@ where
     Ok((frames, time, ignored, delta_max))
 }
 
+/// Like [`frames`], but for stacks that are already split into frames and merged, as they are in a
+/// [`FoldedStacks`](crate::collapse::FoldedStacks).
+///
+/// The stacks must be sorted with [`cmp_stacks`] (or be in flame chart order).
+pub(super) fn stack_frames<'a>(stacks: &[(Vec<&'a str>, u64)]) -> (Vec<TimedFrame<'a>>, u64) {
+    let mut time = 0;
+    let mut tmp = Default::default();
+    let mut frames = Default::default();
+    let mut last: &[&'a str] = &[];
+    for (stack, nsamples) in stacks {
+        if stack.is_empty() {
+            continue;
+        }
+
+        // inject empty first-level stack frame to capture "all"
+        let this = iter::once("").chain(stack.iter().copied());
+        if last.is_empty() {
+            flow(&mut tmp, &mut frames, None, this, time, None);
+        } else {
+            flow(
+                &mut tmp,
+                &mut frames,
+                iter::once("").chain(last.iter().copied()),
+                this,
+                time,
+                None,
+            );
+        }
+
+        last = stack;
+        time += nsamples;
+    }
+
+    if !last.is_empty() {
+        flow(
+            &mut tmp,
+            &mut frames,
+            iter::once("").chain(last.iter().copied()),
+            None,
+            time,
+            None,
+        );
+    }
+
+    (frames, time)
+}
+
+/// Orders stacks the same way as sorting their folded text lines would.
+pub(super) fn cmp_stacks(a: &[&str], b: &[&str]) -> Ordering {
+    fn bytes<'a>(stack: &'a [&str]) -> impl Iterator<Item = u8> + 'a {
+        stack.iter().enumerate().flat_map(|(i, frame)| {
+            let separator: &[u8] = if i == 0 { b"" } else { b";" };
+            separator.iter().chain(frame.as_bytes()).copied()
+        })
+    }
+    bytes(a).cmp(bytes(b))
+}
+
+// Whether `frames` would count `line`, rather than ignore it.
+pub(super) fn is_valid_line(line: &str) -> bool {
+    let mut line = line.trim();
+    // Only `frames` should warn about fractional sample counts.
+    let mut stripped_fractional_samples = true;
+    if parse_nsamples(&mut line, &mut stripped_fractional_samples).is_none() {
+        return false;
+    }
+    parse_nsamples(&mut line, &mut stripped_fractional_samples);
+    !line.is_empty()
+}
+
 // Parse and remove the number of samples from the end of a line.
 fn parse_nsamples(line: &mut &str, stripped_fractional_samples: &mut bool) -> Option<u64> {
     if let Some((samplesi, doti)) = rfind_samples(line) {
@@ -237,31 +308,6 @@ fn parse_nsamples(line: &mut &str, stripped_fractional_samples: &mut bool) -> Op
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn delta_does_not_leak_across_non_differential_lines() {
-        // A differential line (two sample columns) followed by a
-        // non-differential line (one column). The non-differential
-        // frame should have delta = None, not the stale value from
-        // the previous line.
-        let lines = vec!["a;b 10 5", "a;c 3"];
-        let (frames, _time, _ignored, _delta_max) = frames(lines, true).unwrap();
-
-        let c_frame = frames
-            .iter()
-            .find(|f| f.location.function == "c")
-            .expect("should have a frame for 'c'");
-        assert_eq!(
-            c_frame.delta, None,
-            "non-differential line's frame should have delta = None, \
-             but stale delta leaked from the previous differential line"
-        );
-    }
-}
-
 // Tries to find a sample count at the end of a line.
 //
 // On success, the first value of the returned tuple will be the index to the sample count.
@@ -290,3 +336,28 @@ pub(super) fn rfind_samples(line: &str) -> Option<(usize, usize)> {
         Some((samplesi, line.len() - samplesi))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_does_not_leak_across_non_differential_lines() {
+        // A differential line (two sample columns) followed by a
+        // non-differential line (one column). The non-differential
+        // frame should have delta = None, not the stale value from
+        // the previous line.
+        let lines = vec!["a;b 10 5", "a;c 3"];
+        let (frames, _time, _ignored, _delta_max) = frames(lines, true).unwrap();
+
+        let c_frame = frames
+            .iter()
+            .find(|f| f.location.function == "c")
+            .expect("should have a frame for 'c'");
+        assert_eq!(
+            c_frame.delta, None,
+            "non-differential line's frame should have delta = None, \
+             but stale delta leaked from the previous differential line"
+        );
+    }
+}
@@ -7,10 +7,18 @@ macro_rules! args {
 #[cfg(feature = "nameattr")]
 mod attrs;
 
+mod ansi;
 pub mod color;
+mod html;
+pub(crate) mod json;
 mod merge;
+mod panes;
 mod rand;
+#[cfg(feature = "png")]
+mod raster;
+mod speedscope;
 mod svg;
+mod tree;
 
 use std::fs::File;
 use std::io::prelude::*;
@@ -33,7 +41,11 @@ pub use self::attrs::FuncFrameAttrsMap;
 
 pub use self::color::Palette;
 use self::color::{Color, SearchColor, StrokeColor};
+use self::panes::Pane;
 use self::svg::{Dimension, StyleOptions};
+use crate::collapse::FoldedStacks;
+use crate::compression;
+use crate::Error;
 
 const XPAD: usize = 10; // pad left and right
 const FRAMEPAD: usize = 1; // vertical padding for frames
@@ -44,6 +56,9 @@ const FRAMEPAD: usize = 1; // vertical padding for frames
 // the embedded JavaScript.
 const DEFAULT_IMAGE_WIDTH: usize = 1200;
 
+/// The largest [`Options::png_scale`].
+pub const MAX_PNG_SCALE: f64 = 64.0;
+
 /// Default values for [`Options`].
 pub mod defaults {
     macro_rules! doc {
@@ -87,7 +102,8 @@ pub mod defaults {
         FONT_WIDTH: f64 = 0.59,
         COUNT_NAME: &str = "samples",
         NAME_TYPE: &str = "Function:",
-        FACTOR: f64 = 1.0
+        FACTOR: f64 = 1.0,
+        PNG_SCALE: f64 = 1.0
     }
 }
 
@@ -261,6 +277,57 @@ pub struct Options<'a> {
 
     /// Base symbols
     pub base: Vec<String>,
+
+    /// Draw a sandwich graph of this function instead of a flame graph.
+    ///
+    /// A sandwich graph shows the callers of the function as a reversed flame graph (see
+    /// [`Options::reverse_stack_order`]) on top of its callees as an icicle graph rooted at the
+    /// function (like [`Options::base`]), with the function itself in the middle. Where the
+    /// function appears more than once in a stack, like when it is recursive, the stack is
+    /// split at the innermost call, so that every sample is counted once in each half and the
+    /// two halves are just as wide.
+    ///
+    /// Only [`Format::Svg`] supports sandwich graphs. [`Options::direction`],
+    /// [`Options::reverse_stack_order`], [`Options::base`] and [`Options::flame_chart`] are
+    /// ignored when drawing one.
+    pub sandwich: Option<String>,
+
+    /// Draw the flame graph together with its reversed flame graph (see
+    /// [`Options::reverse_stack_order`]) in the same SVG.
+    ///
+    /// The flame graph grows up from the middle of the image, and the reversed one, which starts
+    /// at the leaves, hangs down from it like an icicle graph. They share the search box, zoom
+    /// separately, and clicking a frame in one outlines the frames of the same function in the
+    /// other.
+    ///
+    /// Only [`Format::Svg`] supports combined graphs. [`Options::direction`],
+    /// [`Options::reverse_stack_order`], [`Options::base`] and [`Options::flame_chart`] are
+    /// ignored when drawing one, and so is this option when [`Options::sandwich`] is set.
+    pub combined: bool,
+
+    /// Fail with an [`Error::Parse`] rather than log a warning when a line can't be parsed, and
+    /// with [`Error::EmptyInput`] rather than plot an error message when there are no stack
+    /// counts.
+    pub strict: bool,
+
+    /// The format to write the flame graph in.
+    ///
+    /// Defaults to an SVG image.
+    pub format: Format,
+
+    /// The number of pixels in a [`Format::Png`] image for every pixel of the SVG. It must be a
+    /// positive number no larger than [`MAX_PNG_SCALE`], and the image can have at most 2^28
+    /// pixels.
+    ///
+    /// [Default value](defaults::PNG_SCALE).
+    pub png_scale: f64,
+
+    /// The number of columns of a [`Format::Ansi`] flame graph.
+    ///
+    /// Defaults to None, which means the width is taken from the `COLUMNS` environment variable,
+    /// or is 80 columns if that isn't set. `inferno-flamegraph` sets it to the width of the
+    /// terminal when writing to one.
+    pub terminal_width: Option<usize>,
 }
 
 impl Options<'_> {
@@ -326,6 +393,12 @@ impl Default for Options<'_> {
             color_diffusion: Default::default(),
             flame_chart: Default::default(),
             base: Default::default(),
+            sandwich: Default::default(),
+            combined: Default::default(),
+            strict: Default::default(),
+            format: Default::default(),
+            png_scale: defaults::PNG_SCALE,
+            terminal_width: Default::default(),
 
             #[cfg(feature = "nameattr")]
             func_frameattrs: Default::default(),
@@ -348,6 +421,74 @@ pub enum Direction {
     Inverted,
 }
 
+/// The format a flame graph is written in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[non_exhaustive]
+pub enum Format {
+    /// An interactive SVG image.
+    #[default]
+    Svg,
+
+    /// A [speedscope](https://www.speedscope.app/) JSON file with a single evented profile, which
+    /// can be opened in speedscope's time order, left heavy, and sandwich views.
+    ///
+    /// [`Options::title`] names the profile, [`Options::count_name`] picks its unit, and
+    /// [`Options::factor`] scales its weights. Options that only affect how the SVG looks are
+    /// ignored.
+    Speedscope,
+
+    /// A PNG image of the flame graph, for places that only accept images.
+    ///
+    /// The image is rasterized in pure Rust from the same layout as the SVG, without its
+    /// interactive parts, at [`Options::png_scale`]. This requires the `png` cargo feature.
+    Png,
+
+    /// Text colored with ANSI escape codes, for looking at a flame graph in a terminal.
+    ///
+    /// Every frame is a row of colored cells labeled with its function name, in the same colors
+    /// and [`Options::direction`] as the SVG, and [`Options::terminal_width`] columns wide.
+    Ansi,
+
+    /// A self-contained HTML page with the interactive SVG and a report on the functions in it.
+    ///
+    /// Next to the flame graph, the page has a sortable table of the self and total counts of
+    /// every function, a search box that takes regular expressions, and a pane with the callers
+    /// and callees of the function selected in the table or the flame graph. All the scripts and
+    /// styles are inlined, so the page works offline.
+    Html,
+
+    /// A JSON tree of the merged frames, in the format of
+    /// [d3-flame-graph](https://github.com/spiermar/d3-flame-graph), for processing profiles
+    /// without parsing the SVG.
+    ///
+    /// Every node is an object with the `name` of its function, its `value` and `self` counts, a
+    /// `delta` for differential input, and its `children`. [`Options::min_width`] and
+    /// [`Options::factor`] apply, and options that only affect how the SVG looks are ignored.
+    Json,
+}
+
+impl Format {
+    /// The valid set of formats (via `FromStr`).
+    pub const VARIANTS: &'static [&'static str] =
+        &["svg", "speedscope", "png", "ansi", "html", "json"];
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "svg" => Ok(Format::Svg),
+            "speedscope" => Ok(Format::Speedscope),
+            "png" => Ok(Format::Png),
+            "ansi" => Ok(Format::Ansi),
+            "html" => Ok(Format::Html),
+            "json" => Ok(Format::Json),
+            unknown => Err(format!("unknown output format: {}", unknown)),
+        }
+    }
+}
+
 /// The direction text is truncated when it's too long.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
 pub enum TextTruncateDirection {
@@ -389,7 +530,11 @@ impl Rectangle {
 /// flame graph uses the difference between the two sample counts to show how the sample counts for
 /// each stack has changed between the first and second profiling.
 ///
-/// The resulting flame graph will be written out to `writer` in SVG format.
+/// The resulting flame graph will be written out to `writer` in the [`Options::format`], which
+/// is SVG by default.
+///
+/// Lines that do not match this format are ignored, unless [`Options::strict`] is set, in which
+/// case the first of them is returned as an [`Error::Parse`] that counts `lines` from 1.
 ///
 /// [differential flame graph]: http://www.brendangregg.com/blog/2014-11-09/differential-flame-graphs.html
 #[allow(clippy::cognitive_complexity)]
@@ -399,12 +544,99 @@ where
     W: Write,
 {
     let mut reversed = StrStack::new();
+    let strict = opt.strict;
+    let mut invalid = None;
     let lines = lines
         .into_iter()
-        .map(|line| line.trim())
-        .filter(|line| !(line.is_empty() || line.starts_with("# ")));
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_, line)| !(line.is_empty() || line.starts_with("# ")))
+        .inspect(|&(i, line)| {
+            if strict && invalid.is_none() && !merge::is_valid_line(line) {
+                invalid = Some(Error::parse(i + 1, line, "Unable to parse folded line"));
+            }
+        })
+        .map(|(_, line)| line);
+
+    if let Some(function) = &opt.sandwich {
+        // The callers of the function are reversed like with `reverse_stack_order`, and its
+        // callees are rooted at it like with `base`.
+        let mut callers = StrStack::new();
+        let mut callees: Vec<&str> = Vec::new();
+        let mut stack = String::new();
+        for line in lines {
+            let samples_idx = samples_start(line);
+            let Some(cursor) = innermost_frame(line[..samples_idx].trim_end(), |f| f == function)
+            else {
+                continue;
+            };
+            callees.push(&line[cursor..]);
+            reverse_stack(
+                &line[..cursor + function.len()],
+                &line[samples_idx..],
+                &mut stack,
+            );
+            callers.push(stack.trim());
+        }
+        let mut callers: Vec<&str> = callers.iter().collect();
+        callers.sort_unstable();
+        callees.sort_unstable();
+        let (callers, time, ignored, callers_delta_max) = merge::frames(callers, false)?;
+        let (callees, _, _, callees_delta_max) = merge::frames(callees, false)?;
+
+        if let Some(error) = invalid {
+            return Err(error.into());
+        }
+        if ignored != 0 {
+            warn!("Ignored {} lines with invalid format", ignored);
+        }
+
+        let delta_max = std::cmp::max(callers_delta_max, callees_delta_max);
+        return panes::write(
+            opt,
+            Pane::rooted_at_function("frames", callers),
+            Pane::rooted_at_function("callees", callees),
+            time,
+            delta_max,
+            writer,
+        );
+    }
+
+    if opt.combined {
+        let mut lines: Vec<&str> = lines.collect();
+        let mut stack = String::new();
+        for line in &lines {
+            let samples_idx = samples_start(line);
+            reverse_stack(&line[..samples_idx], &line[samples_idx..], &mut stack);
+            reversed.push(stack.trim());
+        }
+        let mut reversed: Vec<&str> = reversed.iter().collect();
+        reversed.sort_unstable();
+        if !opt.no_sort {
+            lines.sort_unstable();
+        }
+        let (frames, time, ignored, frames_delta_max) = merge::frames(lines, false)?;
+        let (reversed, _, _, reversed_delta_max) = merge::frames(reversed, false)?;
 
-    let (mut frames, time, ignored, delta_max) = if opt.reverse_stack_order {
+        if let Some(error) = invalid {
+            return Err(error.into());
+        }
+        if ignored != 0 {
+            warn!("Ignored {} lines with invalid format", ignored);
+        }
+
+        let delta_max = std::cmp::max(frames_delta_max, reversed_delta_max);
+        return panes::write(
+            opt,
+            Pane::new("frames", frames),
+            Pane::new("reversed", reversed),
+            time,
+            delta_max,
+            writer,
+        );
+    }
+
+    let (frames, time, ignored, delta_max) = if opt.reverse_stack_order {
         if opt.no_sort {
             warn!(
                 "Input lines are always sorted when `reverse_stack_order` is `true`. \
@@ -414,21 +646,8 @@ where
         // Reverse order of stacks and sort.
         let mut stack = String::new();
         for line in lines {
-            stack.clear();
-            let samples_idx = merge::rfind_samples(line)
-                .map(|(i, _)| i)
-                .unwrap_or_else(|| line.len());
-            let samples_idx = merge::rfind_samples(&line[..samples_idx - 1])
-                .map(|(i, _)| i)
-                .unwrap_or(samples_idx);
-            for (i, func) in line[..samples_idx].trim().split(';').rev().enumerate() {
-                if i != 0 {
-                    stack.push(';');
-                }
-                stack.push_str(func);
-            }
-            stack.push(' ');
-            stack.push_str(&line[samples_idx..]);
+            let samples_idx = samples_start(line);
+            reverse_stack(&line[..samples_idx], &line[samples_idx..], &mut stack);
             // Trim to handle the case where functions names internally contain `;`.
             // This can happen, for example, with types like `[u8; 8]` in Rust.
             // See https://github.com/jonhoo/inferno/pull/338.
@@ -454,18 +673,11 @@ where
             lines
                 .into_iter()
                 .filter_map(|line| {
-                    let mut cursor = line.len();
-                    for symbol in line.rsplit(';') {
-                        cursor -= symbol.len();
-                        if opt.base.iter().any(|b| b == symbol) {
-                            break;
-                        }
-                        cursor = cursor.saturating_sub(1);
-                    }
-                    if cursor == 0 {
-                        None
-                    } else {
-                        Some(&line[cursor..])
+                    // The last "frame" still has the sample counts in it, so the leaf is never
+                    // the base.
+                    match innermost_frame(line, |symbol| opt.base.iter().any(|b| b == symbol)) {
+                        Some(cursor) if cursor != 0 => Some(&line[cursor..]),
+                        _ => None,
                     }
                 })
                 .collect()
@@ -474,10 +686,206 @@ where
         merge::frames(lines, false)?
     };
 
+    if let Some(error) = invalid {
+        return Err(error.into());
+    }
     if ignored != 0 {
         warn!("Ignored {} lines with invalid format", ignored);
     }
 
+    render(opt, frames, time, delta_max, writer)
+}
+
+/// The index in `line` where its sample counts start, or its length if it has none.
+fn samples_start(line: &str) -> usize {
+    let Some((samples_idx, _)) = merge::rfind_samples(line) else {
+        return line.len();
+    };
+    merge::rfind_samples(line[..samples_idx].trim_end())
+        .map(|(i, _)| i)
+        .unwrap_or(samples_idx)
+}
+
+/// Writes the frames of `stack` in reverse order, followed by `samples`, to `reversed`.
+fn reverse_stack(stack: &str, samples: &str, reversed: &mut String) {
+    reversed.clear();
+    for (i, func) in stack.trim().split(';').rev().enumerate() {
+        if i != 0 {
+            reversed.push(';');
+        }
+        reversed.push_str(func);
+    }
+    reversed.push(' ');
+    reversed.push_str(samples);
+}
+
+/// The index in `stack` where the innermost frame that `is_base` starts, if there is one.
+fn innermost_frame(stack: &str, is_base: impl Fn(&str) -> bool) -> Option<usize> {
+    let mut cursor = stack.len();
+    for symbol in stack.rsplit(';') {
+        cursor -= symbol.len();
+        if is_base(symbol) {
+            return Some(cursor);
+        }
+        cursor = cursor.saturating_sub(1);
+    }
+    None
+}
+
+/// Calls `visit` with the index of every frame in `frames` but the root, and the indices of the
+/// frames from depth 1 down to its parent.
+///
+/// `position` gives the start of a frame, in samples, and its depth. Frames are visited in order
+/// of their start and then their depth, so every frame is visited after its parent, and the
+/// stack of frames from the root down to the current one can be kept. This is how frames are
+/// drawn, so it also works for frames read back from a flame graph.
+pub(crate) fn walk_frames<T, K: Ord>(
+    frames: &[T],
+    position: impl Fn(&T) -> (K, usize),
+    mut visit: impl FnMut(usize, &[usize]),
+) {
+    let mut order: Vec<usize> = (0..frames.len()).collect();
+    order.sort_unstable_by_key(|&i| position(&frames[i]));
+
+    let mut stack: Vec<usize> = Vec::new();
+    for i in order {
+        let (_, depth) = position(&frames[i]);
+        if depth == 0 {
+            continue;
+        }
+        stack.truncate(depth - 1);
+        visit(i, &stack);
+        stack.push(i);
+    }
+}
+
+/// Produce a flame graph from in-memory [`FoldedStacks`].
+///
+/// This produces the same flame graph as passing the folded text of `stacks` to [`from_lines`],
+/// but without formatting and re-parsing that text. Since `stacks` cannot hold differential
+/// counts, the result is never a differential flame graph. The `no_sort` option is ignored, as
+/// the stacks are always sorted (except when producing a flame chart, which shows them in the
+/// order they were first added).
+///
+/// The resulting flame graph will be written out to `writer` in the [`Options::format`], which
+/// is SVG by default.
+pub fn from_stacks<W: Write>(
+    opt: &mut Options<'_>,
+    stacks: &FoldedStacks,
+    writer: W,
+) -> io::Result<()> {
+    if let Some(function) = &opt.sandwich {
+        let mut callers: Vec<(Vec<&str>, u64)> = Vec::new();
+        let mut callees: Vec<(Vec<&str>, u64)> = Vec::new();
+        for (ids, count) in stacks.stacks() {
+            let frames: Vec<&str> = ids.iter().map(|&id| stacks.frame(id)).collect();
+            if let Some(i) = frames.iter().rposition(|frame| frame == function) {
+                callers.push((frames[..=i].iter().rev().copied().collect(), count));
+                callees.push((frames[i..].to_vec(), count));
+            }
+        }
+        callers.sort_unstable_by(|(a, _), (b, _)| merge::cmp_stacks(a, b));
+        callees.sort_unstable_by(|(a, _), (b, _)| merge::cmp_stacks(a, b));
+        let (callers, time) = merge::stack_frames(&callers);
+        let (callees, _) = merge::stack_frames(&callees);
+        return panes::write(
+            opt,
+            Pane::rooted_at_function("frames", callers),
+            Pane::rooted_at_function("callees", callees),
+            time,
+            1,
+            writer,
+        );
+    }
+
+    if opt.combined {
+        let mut lines: Vec<(Vec<&str>, u64)> = stacks
+            .stacks()
+            .map(|(ids, count)| (ids.iter().map(|&id| stacks.frame(id)).collect(), count))
+            .collect();
+        let mut reversed: Vec<(Vec<&str>, u64)> = lines
+            .iter()
+            .map(|(frames, count)| (frames.iter().rev().copied().collect(), *count))
+            .collect();
+        lines.sort_unstable_by(|(a, _), (b, _)| merge::cmp_stacks(a, b));
+        reversed.sort_unstable_by(|(a, _), (b, _)| merge::cmp_stacks(a, b));
+        let (frames, time) = merge::stack_frames(&lines);
+        let (reversed, _) = merge::stack_frames(&reversed);
+        return panes::write(
+            opt,
+            Pane::new("frames", frames),
+            Pane::new("reversed", reversed),
+            time,
+            1,
+            writer,
+        );
+    }
+
+    let mut lines: Vec<(Vec<&str>, u64)> = stacks
+        .stacks()
+        .filter_map(|(ids, count)| {
+            let mut frames: Vec<&str> = ids.iter().map(|&id| stacks.frame(id)).collect();
+            if opt.reverse_stack_order {
+                frames.reverse();
+            } else if !opt.flame_chart && !opt.base.is_empty() {
+                // Like `from_lines`, start at the innermost base frame above the root and below
+                // the leaf, and drop stacks that do not have one.
+                let callers = &frames[..frames.len().saturating_sub(1)];
+                match callers
+                    .iter()
+                    .rposition(|frame| opt.base.iter().any(|b| b == frame))
+                {
+                    Some(i) if i > 0 => {
+                        frames.drain(..i);
+                    }
+                    _ => return None,
+                }
+            }
+            Some((frames, count))
+        })
+        .collect();
+
+    if opt.flame_chart {
+        lines.reverse();
+    } else {
+        lines.sort_unstable_by(|(a, _), (b, _)| merge::cmp_stacks(a, b));
+    }
+
+    let (frames, time) = merge::stack_frames(&lines);
+    render(opt, frames, time, 1, writer)
+}
+
+fn render<W: Write>(
+    opt: &mut Options<'_>,
+    frames: Vec<merge::TimedFrame<'_>>,
+    time: u64,
+    delta_max: usize,
+    writer: W,
+) -> io::Result<()> {
+    match opt.format {
+        Format::Svg => write_svg(opt, frames, time, delta_max, writer),
+        Format::Speedscope => speedscope::write(opt, &frames, time, writer),
+        #[cfg(feature = "png")]
+        Format::Png => raster::write(opt, frames, time, delta_max, writer),
+        #[cfg(not(feature = "png"))]
+        Format::Png => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "PNG output requires inferno to be built with the `png` feature",
+        )),
+        Format::Ansi => ansi::write(opt, frames, time, delta_max, writer),
+        Format::Html => html::write(opt, frames, time, delta_max, writer),
+        Format::Json => tree::write(opt, &frames, time, writer),
+    }
+}
+
+#[allow(clippy::cognitive_complexity)]
+fn write_svg<W: Write>(
+    opt: &mut Options<'_>,
+    mut frames: Vec<merge::TimedFrame<'_>>,
+    time: u64,
+    delta_max: usize,
+    writer: W,
+) -> io::Result<()> {
     let mut buffer = StrStack::new();
 
     // let's start writing the svg!
@@ -488,6 +896,9 @@ where
     };
 
     if time == 0 {
+        if opt.strict {
+            return Err(Error::EmptyInput.into());
+        }
         error!("No stack counts found");
         // emit an error message SVG, for tools automating flamegraph use
         let imageheight = opt.font_size * 5;
@@ -510,24 +921,12 @@ where
         ));
     }
 
-    let image_width = opt.image_width.unwrap_or(DEFAULT_IMAGE_WIDTH) as f64;
+    let layout = Layout::new(opt, &mut frames, time);
+    let image_width = layout.image_width;
     let timemax = time;
-    let widthpertime_pct = 100.0 / timemax as f64;
-    let minwidth_time = opt.min_width / widthpertime_pct;
-
-    // prune blocks that are too narrow
-    let mut depthmax = 0;
-    frames.retain(|frame| {
-        if ((frame.end_time - frame.start_time) as f64) < minwidth_time {
-            false
-        } else {
-            depthmax = std::cmp::max(depthmax, frame.location.depth);
-            true
-        }
-    });
+    let imageheight = layout.imageheight;
 
     // draw canvas, and embed interactive JavaScript program
-    let imageheight = ((depthmax + 1) * opt.frame_height) + opt.ypad1() + opt.ypad2();
     svg::write_header(&mut svg, imageheight, opt)?;
 
     let (bgcolor1, bgcolor2) = color::bgcolor_for(opt.bgcolors, opt.colors);
@@ -546,6 +945,46 @@ where
 
     svg::write_prelude(&mut svg, &style_options, opt)?;
 
+    // create frames container
+    let container_x = format!("{}", XPAD);
+    let container_width = format!("{}", image_width as usize - XPAD - XPAD);
+    svg.write_event(Event::Start(BytesStart::new("svg").with_attributes(vec![
+        ("id", "frames"),
+        ("x", &container_x),
+        ("width", &container_width),
+        ("total_samples", &format!("{}", timemax)),
+    ])))?;
+
+    write_frames(
+        &mut svg,
+        opt,
+        &layout,
+        frames,
+        timemax,
+        delta_max,
+        &mut buffer,
+    )?;
+
+    svg.write_event(Event::End(BytesEnd::new("svg")))?;
+    svg.write_event(Event::End(BytesEnd::new("svg")))?;
+    svg.write_event(Event::Eof)?;
+
+    svg.into_inner().flush()?;
+    Ok(())
+}
+
+/// Draws `frames` as laid out by `layout`, with percentages of `timemax`.
+fn write_frames<W: Write>(
+    svg: &mut Writer<W>,
+    opt: &mut Options<'_>,
+    layout: &Layout,
+    frames: Vec<merge::TimedFrame<'_>>,
+    timemax: u64,
+    delta_max: usize,
+    buffer: &mut StrStack,
+) -> io::Result<()> {
+    let image_width = layout.image_width;
+
     // Used when picking color parameters at random, when no option determines how to pick these
     // parameters. We instantiate it here because it may be called once for each iteration in the
     // frames loop.
@@ -558,44 +997,10 @@ where
     let cache_g_end = Event::End(BytesEnd::new("g"));
     let cache_a_end = Event::End(BytesEnd::new("a"));
 
-    // create frames container
-    let container_x = format!("{}", XPAD);
-    let container_width = format!("{}", image_width as usize - XPAD - XPAD);
-    svg.write_event(Event::Start(BytesStart::new("svg").with_attributes(vec![
-        ("id", "frames"),
-        ("x", &container_x),
-        ("width", &container_width),
-        ("total_samples", &format!("{}", timemax)),
-    ])))?;
-
     // draw frames
     let mut samples_txt_buffer = num_format::Buffer::default();
     for frame in frames {
-        let x1_pct = frame.start_time as f64 * widthpertime_pct;
-        let x2_pct = frame.end_time as f64 * widthpertime_pct;
-
-        let (y1, y2) = match opt.direction {
-            Direction::Straight => {
-                let y1 = imageheight - opt.ypad2() - (frame.location.depth + 1) * opt.frame_height
-                    + FRAMEPAD;
-                let y2 = imageheight - opt.ypad2() - frame.location.depth * opt.frame_height;
-                (y1, y2)
-            }
-            Direction::Inverted => {
-                let y1 = opt.ypad1() + frame.location.depth * opt.frame_height;
-                let y2 = opt.ypad1() + (frame.location.depth + 1) * opt.frame_height - FRAMEPAD;
-                (y1, y2)
-            }
-        };
-
-        let rect = Rectangle {
-            x1_pct,
-            x1_samples: frame.start_time,
-            y1,
-            x2_pct,
-            x2_samples: frame.end_time,
-            y2,
-        };
+        let rect = layout.rect(opt, &frame);
 
         // The rounding here can differ from the Perl version when the fractional part is `0.5`.
         // The Perl version does `my $samples = sprintf "%.0f", ($etime - $stime) * $factor;`,
@@ -640,84 +1045,35 @@ where
             }
         };
 
-        let (has_href, title) = write_container_start(
-            opt,
-            &mut svg,
-            &mut cache_a,
-            &mut cache_g,
-            &frame,
-            &buffer[info],
-        )?;
+        let (has_href, title) =
+            write_container_start(opt, svg, &mut cache_a, &mut cache_g, &frame, &buffer[info])?;
 
         svg.write_event(Event::Start(BytesStart::new("title")))?;
         svg.write_event(Event::Text(BytesText::new(title)))?;
         svg.write_event(Event::End(BytesEnd::new("title")))?;
 
         // select the color of the rectangle
-        let color = if frame.location.function == "--" {
-            color::VDGREY
-        } else if frame.location.function == "-" {
-            color::DGREY
-        } else if opt.color_diffusion {
-            // We want to visually highlight high priority regions for
-            // optimization: wider frames are redder. Typically when optimizing,
-            // a frame that is 50% of width is high priority, so it seems wrong
-            // to give it half the saturation of 100%. So we use sqrt to make
-            // the red dropoff less linear.
-            color::color_scale((((x2_pct - x1_pct) / 100.0).sqrt() * 2000.0) as isize, 2000)
-        } else if let Some(mut delta) = frame.delta {
-            if opt.negate_differentials {
-                delta = -delta;
-            }
-            color::color_scale(delta, delta_max)
-        } else if let Some(ref mut palette_map) = opt.palette_map {
-            let colors = opt.colors;
-            let hash = opt.hash;
-            let deterministic = opt.deterministic;
-            palette_map.find_color_for(frame.location.function, |name| {
-                color::color(colors, hash, deterministic, name, &mut thread_rng)
-            })
-        } else {
-            color::color(
-                opt.colors,
-                opt.hash,
-                opt.deterministic,
-                frame.location.function,
-                &mut thread_rng,
-            )
-        };
-        filled_rectangle(&mut svg, &mut buffer, &rect, color, &mut cache_rect)?;
+        let color = frame_color(opt, &frame, &rect, delta_max, &mut thread_rng);
+        filled_rectangle(svg, buffer, &rect, color, &mut cache_rect)?;
 
-        let fitchars = (rect.width_pct()
-            / (100.0 * opt.font_size as f64 * opt.font_width / image_width))
-            .trunc() as usize;
-        let text: svg::TextArgument<'_> = if fitchars >= 3 {
-            // room for one char plus two dots
-            let f = deannotate(frame.location.function);
-
-            // TODO: use Unicode grapheme clusters instead
-            if f.len() < fitchars {
-                // no need to truncate
-                f.into()
-            } else {
+        let text: svg::TextArgument<'_> = match layout.label(opt, &rect, frame.location.function) {
+            Some((f, false)) => f.into(),
+            Some((f, true)) => {
                 // need to truncate :'(
                 use std::fmt::Write;
                 let mut w = buffer.writer();
-                for c in f.chars().take(fitchars - 2) {
-                    w.write_char(c).expect("writing to buffer shouldn't fail");
-                }
+                w.write_str(f).expect("writing to buffer shouldn't fail");
                 w.write_str("..").expect("writing to buffer shouldn't fail");
                 w.finish().into()
             }
-        } else {
             // don't show the function name
-            "".into()
+            None => "".into(),
         };
 
         // write the text
         svg::write_str(
-            &mut svg,
-            &mut buffer,
+            svg,
+            buffer,
             svg::TextItem {
                 x: Dimension::Percent(rect.x1_pct + 100.0 * 3.0 / image_width),
                 y: 3.0 + (rect.y1 + rect.y2) as f64 / 2.0,
@@ -734,14 +1090,152 @@ where
         }
     }
 
-    svg.write_event(Event::End(BytesEnd::new("svg")))?;
-    svg.write_event(Event::End(BytesEnd::new("svg")))?;
-    svg.write_event(Event::Eof)?;
-
-    svg.into_inner().flush()?;
     Ok(())
 }
 
+/// The size and position of everything in the plot, which the SVG and PNG writers share.
+struct Layout {
+    image_width: f64,
+    imageheight: usize,
+    widthpertime_pct: f64,
+    /// The way the frames grow from the edge of the root frame at `origin`.
+    direction: Direction,
+    origin: usize,
+}
+
+impl Layout {
+    /// Lays out the plot of `frames`, after removing the frames that are too narrow to show.
+    fn new(opt: &Options<'_>, frames: &mut Vec<merge::TimedFrame<'_>>, time: u64) -> Self {
+        let depthmax = prune(opt, frames, time);
+        let imageheight = ((depthmax + 1) * opt.frame_height) + opt.ypad1() + opt.ypad2();
+        let origin = match opt.direction {
+            Direction::Straight => imageheight - opt.ypad2(),
+            Direction::Inverted => opt.ypad1(),
+        };
+        Layout {
+            image_width: opt.image_width.unwrap_or(DEFAULT_IMAGE_WIDTH) as f64,
+            imageheight,
+            widthpertime_pct: 100.0 / time as f64,
+            direction: opt.direction,
+            origin,
+        }
+    }
+
+    /// The rectangle of `frame`, with its horizontal position as a percentage of the width of the
+    /// frames area.
+    fn rect(&self, opt: &Options<'_>, frame: &merge::TimedFrame<'_>) -> Rectangle {
+        let (y1, y2) = match self.direction {
+            Direction::Straight => {
+                let y1 = self.origin - (frame.location.depth + 1) * opt.frame_height + FRAMEPAD;
+                let y2 = self.origin - frame.location.depth * opt.frame_height;
+                (y1, y2)
+            }
+            Direction::Inverted => {
+                let y1 = self.origin + frame.location.depth * opt.frame_height;
+                let y2 = self.origin + (frame.location.depth + 1) * opt.frame_height - FRAMEPAD;
+                (y1, y2)
+            }
+        };
+
+        Rectangle {
+            x1_pct: frame.start_time as f64 * self.widthpertime_pct,
+            x1_samples: frame.start_time,
+            y1,
+            x2_pct: frame.end_time as f64 * self.widthpertime_pct,
+            x2_samples: frame.end_time,
+            y2,
+        }
+    }
+
+    /// The part of `function` that fits in `rect`, and whether it was cut short and needs `..`
+    /// appended, or `None` if there is no room to show the function name.
+    fn label<'f>(
+        &self,
+        opt: &Options<'_>,
+        rect: &Rectangle,
+        function: &'f str,
+    ) -> Option<(&'f str, bool)> {
+        let fitchars = (rect.width_pct()
+            / (100.0 * opt.font_size as f64 * opt.font_width / self.image_width))
+            .trunc() as usize;
+        if fitchars < 3 {
+            // no room for one char plus two dots
+            return None;
+        }
+
+        let f = deannotate(function);
+        // TODO: use Unicode grapheme clusters instead
+        if f.len() < fitchars {
+            Some((f, false))
+        } else {
+            let end = f
+                .char_indices()
+                .nth(fitchars - 2)
+                .map_or(f.len(), |(i, _)| i);
+            Some((&f[..end], true))
+        }
+    }
+}
+
+/// Removes the frames that are too narrow to show, and returns the depth of the deepest frame
+/// that is left.
+fn prune(opt: &Options<'_>, frames: &mut Vec<merge::TimedFrame<'_>>, time: u64) -> usize {
+    let widthpertime_pct = 100.0 / time as f64;
+    let minwidth_time = opt.min_width / widthpertime_pct;
+    let mut depthmax = 0;
+    frames.retain(|frame| {
+        if ((frame.end_time - frame.start_time) as f64) < minwidth_time {
+            false
+        } else {
+            depthmax = std::cmp::max(depthmax, frame.location.depth);
+            true
+        }
+    });
+    depthmax
+}
+
+/// Selects the color of the rectangle of `frame`.
+fn frame_color(
+    opt: &mut Options<'_>,
+    frame: &merge::TimedFrame<'_>,
+    rect: &Rectangle,
+    delta_max: usize,
+    mut thread_rng: impl FnMut() -> f32,
+) -> Color {
+    if frame.location.function == "--" {
+        color::VDGREY
+    } else if frame.location.function == "-" {
+        color::DGREY
+    } else if opt.color_diffusion {
+        // We want to visually highlight high priority regions for
+        // optimization: wider frames are redder. Typically when optimizing,
+        // a frame that is 50% of width is high priority, so it seems wrong
+        // to give it half the saturation of 100%. So we use sqrt to make
+        // the red dropoff less linear.
+        color::color_scale(((rect.width_pct() / 100.0).sqrt() * 2000.0) as isize, 2000)
+    } else if let Some(mut delta) = frame.delta {
+        if opt.negate_differentials {
+            delta = -delta;
+        }
+        color::color_scale(delta, delta_max)
+    } else if let Some(ref mut palette_map) = opt.palette_map {
+        let colors = opt.colors;
+        let hash = opt.hash;
+        let deterministic = opt.deterministic;
+        palette_map.find_color_for(frame.location.function, |name| {
+            color::color(colors, hash, deterministic, name, &mut thread_rng)
+        })
+    } else {
+        color::color(
+            opt.colors,
+            opt.hash,
+            opt.deterministic,
+            frame.location.function,
+            &mut thread_rng,
+        )
+    }
+}
+
 #[cfg(feature = "nameattr")]
 fn write_container_start<'a, W: Write>(
     opt: &'a Options<'a>,
@@ -813,7 +1307,8 @@ fn write_container_attributes(event: &mut Event<'_>, frame_attributes: &FrameAtt
 ///
 /// See [`from_lines`] for the expected format of each line.
 ///
-/// The resulting flame graph will be written out to `writer` in SVG format.
+/// The resulting flame graph will be written out to `writer` in the [`Options::format`], which
+/// is SVG by default.
 pub fn from_reader<R, W>(opt: &mut Options<'_>, reader: R, writer: W) -> io::Result<()>
 where
     R: Read,
@@ -826,7 +1321,8 @@ where
 ///
 /// See [`from_lines`] for the expected format of each line.
 ///
-/// The resulting flame graph will be written out to `writer` in SVG format.
+/// The resulting flame graph will be written out to `writer` in the [`Options::format`], which
+/// is SVG by default.
 pub fn from_readers<R, W>(opt: &mut Options<'_>, readers: R, writer: W) -> io::Result<()>
 where
     R: IntoIterator,
@@ -844,14 +1340,19 @@ where
 /// and write the result to provided `writer`.
 ///
 /// If files is empty, STDIN will be used as input.
+///
+/// Input compressed with gzip, zstd, or xz is decompressed if the cargo feature of the same name is
+/// enabled. If a line of a single input file cannot be parsed, the [`Error::Parse`] that is
+/// returned includes the file's path.
 pub fn from_files<W: Write>(opt: &mut Options<'_>, files: &[PathBuf], writer: W) -> io::Result<()> {
     if files.is_empty() || files.len() == 1 && files[0].to_str() == Some("-") {
         let stdin = io::stdin();
         let r = BufReader::with_capacity(128 * 1024, stdin.lock());
-        from_reader(opt, r, writer)
+        from_reader(opt, compression::decompress(r)?, writer)
     } else if files.len() == 1 {
-        let r = File::open(&files[0])?;
-        from_reader(opt, r, writer)
+        let r = BufReader::with_capacity(128 * 1024, File::open(&files[0])?);
+        from_reader(opt, compression::decompress(r)?, writer)
+            .map_err(|e| Error::with_path(e, &files[0]))
     } else {
         let stdin = io::stdin();
         let mut stdin_added = false;
@@ -860,12 +1361,12 @@ pub fn from_files<W: Write>(opt: &mut Options<'_>, files: &[PathBuf], writer: W)
             if infile.to_str() == Some("-") {
                 if !stdin_added {
                     let r = BufReader::with_capacity(128 * 1024, stdin.lock());
-                    readers.push(Box::new(r));
+                    readers.push(Box::new(compression::decompress(r)?));
                     stdin_added = true;
                 }
             } else {
-                let r = File::open(infile)?;
-                readers.push(Box::new(r));
+                let r = BufReader::with_capacity(128 * 1024, File::open(infile)?);
+                readers.push(Box::new(compression::decompress(r)?));
             }
         }
 
@@ -0,0 +1,153 @@
+use std::io::{self, Write};
+
+use quick_xml::events::{BytesEnd, BytesStart, Event};
+use quick_xml::Writer;
+use str_stack::StrStack;
+
+use super::color::{self, StrokeColor};
+use super::merge::TimedFrame;
+use super::svg::{self, StyleOptions};
+use super::{
+    prune, write_frames, write_svg, Direction, Format, Layout, Options, DEFAULT_IMAGE_WIDTH,
+    FRAMEPAD, XPAD,
+};
+
+/// The frames of one of the flame graphs drawn in an SVG with two of them.
+pub(super) struct Pane<'a> {
+    /// The id of the `<svg>` the frames are drawn in.
+    id: &'static str,
+    frames: Vec<TimedFrame<'a>>,
+}
+
+impl<'a> Pane<'a> {
+    pub(super) fn new(id: &'static str, frames: Vec<TimedFrame<'a>>) -> Self {
+        Pane { id, frames }
+    }
+
+    /// A pane of `frames` without their empty root frame, so that the function above it is at
+    /// the root.
+    pub(super) fn rooted_at_function(id: &'static str, mut frames: Vec<TimedFrame<'a>>) -> Self {
+        frames.retain(|frame| frame.location.depth != 0);
+        for frame in frames.iter_mut() {
+            frame.location.depth -= 1;
+        }
+        Pane { id, frames }
+    }
+}
+
+/// Writes two flame graphs in one SVG, for a sandwich graph or a combined graph.
+///
+/// The frames of `upper` are drawn growing up from the middle of the image, and those of `lower`
+/// growing down from it, each in their own `<svg>` so that the embedded JavaScript can zoom them
+/// separately. The first one should have the id `frames`, so that tools that look for the frames
+/// container find a flame graph. Both panes should be `time` wide.
+pub(super) fn write<W: Write>(
+    opt: &mut Options<'_>,
+    upper: Pane<'_>,
+    lower: Pane<'_>,
+    time: u64,
+    delta_max: usize,
+    writer: W,
+) -> io::Result<()> {
+    if opt.format != Format::Svg {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Sandwich and combined graphs can only be written as SVG",
+        ));
+    }
+    if time == 0 {
+        return write_svg(opt, upper.frames, time, delta_max, writer);
+    }
+
+    // Both panes are laid out like a normal flame graph, so that the title, the details and the
+    // buttons are where they usually are.
+    let direction = std::mem::replace(&mut opt.direction, Direction::Straight);
+    let result = write_panes(opt, upper, lower, time, delta_max, writer);
+    opt.direction = direction;
+    result
+}
+
+fn write_panes<W: Write>(
+    opt: &mut Options<'_>,
+    mut upper: Pane<'_>,
+    mut lower: Pane<'_>,
+    time: u64,
+    delta_max: usize,
+    writer: W,
+) -> io::Result<()> {
+    let upper_depth = prune(opt, &mut upper.frames, time);
+    let lower_depth = prune(opt, &mut lower.frames, time);
+
+    // The roots of the panes are next to each other, with a little gap in between.
+    let middle = opt.ypad1() + (upper_depth + 1) * opt.frame_height;
+    let imageheight = middle + FRAMEPAD + (lower_depth + 1) * opt.frame_height + opt.ypad2();
+    let image_width = opt.image_width.unwrap_or(DEFAULT_IMAGE_WIDTH) as f64;
+    let layout = |direction, origin| Layout {
+        image_width,
+        imageheight,
+        widthpertime_pct: 100.0 / time as f64,
+        direction,
+        origin,
+    };
+
+    let mut svg = if opt.pretty_xml {
+        Writer::new_with_indent(writer, b' ', 4)
+    } else {
+        Writer::new(writer)
+    };
+    svg::write_header(&mut svg, imageheight, opt)?;
+
+    let (bgcolor1, bgcolor2) = color::bgcolor_for(opt.bgcolors, opt.colors);
+    let strokecolor = match opt.stroke_color {
+        StrokeColor::Color(c) => Some(c.to_string()),
+        StrokeColor::None => None,
+    };
+    let style_options = StyleOptions {
+        imageheight,
+        bgcolor1,
+        bgcolor2,
+        uicolor: opt.uicolor.to_string(),
+        strokecolor,
+    };
+    svg::write_prelude(&mut svg, &style_options, opt)?;
+
+    // Both containers are marked as panes of frames for the embedded JavaScript.
+    let container_x = format!("{}", XPAD);
+    let container_width = format!("{}", image_width as usize - XPAD - XPAD);
+    let total_samples = format!("{}", time);
+    let mut buffer = StrStack::new();
+    let panes = [
+        (upper, "false", layout(Direction::Straight, middle)),
+        (
+            lower,
+            "true",
+            layout(Direction::Inverted, middle + FRAMEPAD),
+        ),
+    ];
+    for (pane, inverted, layout) in panes {
+        svg.write_event(Event::Start(BytesStart::new("svg").with_attributes(vec![
+            ("id", pane.id),
+            ("class", "frames"),
+            ("x", &container_x),
+            ("width", &container_width),
+            ("total_samples", &total_samples),
+            ("fg:inverted", inverted),
+        ])))?;
+        write_frames(
+            &mut svg,
+            opt,
+            &layout,
+            pane.frames,
+            time,
+            delta_max,
+            &mut buffer,
+        )?;
+        svg.write_event(Event::End(BytesEnd::new("svg")))?;
+    }
+
+    svg.write_event(Event::End(BytesEnd::new("svg")))?;
+    svg.write_event(Event::Eof)?;
+
+    svg.into_inner().flush()?;
+    Ok(())
+}
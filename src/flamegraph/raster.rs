@@ -0,0 +1,253 @@
+use std::io::{self, Write};
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use log::error;
+
+use super::color::{self, Color, StrokeColor};
+use super::merge::TimedFrame;
+use super::{frame_color, rand, Layout, Options, Rectangle, MAX_PNG_SCALE, XPAD};
+use crate::Error;
+
+const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+
+// The most pixels an image can have, which at 3 bytes a pixel is 768 MiB. This also keeps the
+// width and height well within the 2^31 - 1 that PNG allows.
+const MAX_PIXELS: f64 = (1u64 << 28) as f64;
+
+/// Writes the flame graph as a PNG image.
+///
+/// The image has the same layout as the SVG: the same rectangles with the same colors, and the
+/// same truncated labels, with [`Options::png_scale`] pixels for every pixel of the SVG. The
+/// interactive parts of the SVG, like the search button, are left out. Text is drawn with the
+/// embedded [Hack](https://github.com/source-foundry/Hack) monospace font, whatever
+/// [`Options::font_type`] is.
+pub(super) fn write<W: Write>(
+    opt: &mut Options<'_>,
+    mut frames: Vec<TimedFrame<'_>>,
+    time: u64,
+    delta_max: usize,
+    writer: W,
+) -> io::Result<()> {
+    if !(opt.png_scale > 0.0 && opt.png_scale <= MAX_PNG_SCALE) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "png_scale must be a positive number no larger than {}, not {}",
+                MAX_PNG_SCALE, opt.png_scale
+            ),
+        ));
+    }
+    if time == 0 {
+        if opt.strict {
+            return Err(Error::EmptyInput.into());
+        }
+        error!("No stack counts found");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No stack counts found",
+        ));
+    }
+
+    let layout = Layout::new(opt, &mut frames, time);
+    let mut canvas = Canvas::new(
+        layout.image_width * opt.png_scale,
+        layout.imageheight as f64 * opt.png_scale,
+        opt.png_scale,
+    )?;
+
+    let (bgcolor1, bgcolor2) = color::bgcolor_for(opt.bgcolors, opt.colors);
+    let bgcolor1 = color::parse_hex_color(&bgcolor1).unwrap_or(BLACK);
+    let bgcolor2 = color::parse_hex_color(&bgcolor2).unwrap_or(BLACK);
+    canvas.fill_gradient(bgcolor1, bgcolor2);
+
+    let title_size = (opt.font_size + 5) as f64;
+    let center = layout.image_width / 2.0;
+    let y = (opt.font_size * 2) as f64;
+    canvas.draw_text_centered(&opt.title, center, y, title_size, opt.uicolor);
+    if let Some(subtitle) = &opt.subtitle {
+        let y = (opt.font_size * 4) as f64;
+        canvas.draw_text_centered(subtitle, center, y, opt.font_size as f64, BLACK);
+    }
+
+    // Frames are positioned relative to the frames area, which has padding on either side.
+    let frames_width = layout.image_width - 2.0 * XPAD as f64;
+    let x = |pct: f64| XPAD as f64 + frames_width * pct / 100.0;
+    let stroke = match opt.stroke_color {
+        StrokeColor::Color(c) => Some(c),
+        StrokeColor::None => None,
+    };
+    let mut thread_rng = rand::thread_rng();
+    let mut label = String::new();
+    for frame in &frames {
+        let rect = layout.rect(opt, frame);
+        let color = frame_color(opt, frame, &rect, delta_max, &mut thread_rng);
+        canvas.fill_rect(&rect, x(rect.x1_pct), x(rect.x2_pct), color, stroke);
+
+        if let Some((f, truncated)) = layout.label(opt, &rect, frame.location.function) {
+            label.clear();
+            label.push_str(f);
+            if truncated {
+                label.push_str("..");
+            }
+            let text_x = x(rect.x1_pct) + 3.0 * frames_width / layout.image_width;
+            let text_y = 3.0 + (rect.y1 + rect.y2) as f64 / 2.0;
+            canvas.draw_text(&label, text_x, text_y, opt.font_size as f64, BLACK);
+        }
+    }
+
+    canvas.write_png(writer)
+}
+
+/// An RGB image, drawn on in the coordinates of the SVG.
+struct Canvas {
+    width: usize,
+    height: usize,
+    scale: f64,
+    pixels: Vec<u8>,
+    font: FontRef<'static>,
+}
+
+impl Canvas {
+    fn new(width: f64, height: f64, scale: f64) -> io::Result<Self> {
+        let width = width.round().max(1.0);
+        let height = height.round().max(1.0);
+        if width * height > MAX_PIXELS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "A {}x{} PNG image has more than the {} pixels allowed",
+                    width, height, MAX_PIXELS
+                ),
+            ));
+        }
+        let (width, height) = (width as usize, height as usize);
+        let font = FontRef::try_from_slice(epaint_default_fonts::HACK_REGULAR)
+            .expect("the embedded font is valid");
+        Ok(Canvas {
+            width,
+            height,
+            scale,
+            pixels: vec![0; width * height * 3],
+            font,
+        })
+    }
+
+    /// Fills the image with a vertical gradient from `top` to `bottom`, which like the SVG
+    /// background starts at 5% and ends at 95% of the height.
+    fn fill_gradient(&mut self, top: Color, bottom: Color) {
+        let lerp = |a: u8, b: u8, t: f64| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+        for y in 0..self.height {
+            let t = ((y as f64 / self.height as f64 - 0.05) / 0.9).clamp(0.0, 1.0);
+            let color = Color {
+                r: lerp(top.r, bottom.r, t),
+                g: lerp(top.g, bottom.g, t),
+                b: lerp(top.b, bottom.b, t),
+            };
+            for x in 0..self.width {
+                self.blend(x, y, color, 1.0);
+            }
+        }
+    }
+
+    /// Fills the rectangle between `x1` and `x2` horizontally and `rect` vertically, with an
+    /// outline if `stroke` is set.
+    fn fill_rect(
+        &mut self,
+        rect: &Rectangle,
+        x1: f64,
+        x2: f64,
+        color: Color,
+        stroke: Option<Color>,
+    ) {
+        let px1 = self.to_px(x1, self.width);
+        let px2 = self.to_px(x2, self.width).max(px1 + 1).min(self.width);
+        let py1 = self.to_px(rect.y1 as f64, self.height);
+        let py2 = self.to_px(rect.y2 as f64, self.height);
+        for y in py1..py2 {
+            for x in px1..px2 {
+                let edge = x == px1 || x + 1 == px2 || y == py1 || y + 1 == py2;
+                match stroke {
+                    Some(stroke) if edge => self.blend(x, y, stroke, 1.0),
+                    _ => self.blend(x, y, color, 1.0),
+                }
+            }
+        }
+    }
+
+    /// Draws `text` centered on `x`, with its baseline at `y`.
+    fn draw_text_centered(&mut self, text: &str, x: f64, y: f64, size: f64, color: Color) {
+        let width = self.text_width(text, size);
+        self.draw_text(text, x - width / 2.0, y, size, color);
+    }
+
+    /// Draws `text` starting at `x`, with its baseline at `y`, with `size` pixels per em.
+    fn draw_text(&mut self, text: &str, x: f64, y: f64, size: f64, color: Color) {
+        let scale = self.px_scale(size);
+        let font = self.font.clone();
+        let scaled = font.as_scaled(scale);
+        let mut caret = (x * self.scale) as f32;
+        let baseline = (y * self.scale) as f32;
+        for c in text.chars() {
+            let glyph = scaled.scaled_glyph(c);
+            let advance = scaled.h_advance(glyph.id);
+            let glyph = glyph
+                .id
+                .with_scale_and_position(scale, point(caret, baseline));
+            caret += advance;
+            if let Some(outline) = font.outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, coverage| {
+                    let px = bounds.min.x as i64 + gx as i64;
+                    let py = bounds.min.y as i64 + gy as i64;
+                    if px >= 0 && py >= 0 {
+                        self.blend(px as usize, py as usize, color, coverage);
+                    }
+                });
+            }
+        }
+    }
+
+    fn text_width(&self, text: &str, size: f64) -> f64 {
+        let scaled = self.font.as_scaled(self.px_scale(size));
+        let width: f32 = text
+            .chars()
+            .map(|c| scaled.h_advance(scaled.glyph_id(c)))
+            .sum();
+        width as f64 / self.scale
+    }
+
+    // `PxScale` is the height of the font rather than the size of its em square.
+    fn px_scale(&self, size: f64) -> PxScale {
+        let units_per_em = self.font.units_per_em().unwrap_or(1.0);
+        let px_per_em = (size * self.scale) as f32;
+        PxScale::from(px_per_em * self.font.height_unscaled() / units_per_em)
+    }
+
+    fn to_px(&self, v: f64, max: usize) -> usize {
+        ((v * self.scale).round().max(0.0) as usize).min(max)
+    }
+
+    fn blend(&mut self, x: usize, y: usize, color: Color, coverage: f32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let coverage = coverage.clamp(0.0, 1.0);
+        let i = (y * self.width + x) * 3;
+        for (channel, value) in self.pixels[i..i + 3]
+            .iter_mut()
+            .zip([color.r, color.g, color.b])
+        {
+            *channel = (*channel as f32 * (1.0 - coverage) + value as f32 * coverage).round() as u8;
+        }
+    }
+
+    fn write_png<W: Write>(self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+"use strict";
+(function() {
+    var report = JSON.parse(document.getElementById("report-data").textContent);
+    var functions = report.functions;
+    var ids = {};
+    for (var i = 0; i < functions.length; i++) {
+        ids[functions[i].name] = i;
+    }
+
+    var table = document.getElementById("functions");
+    var headers = table.querySelectorAll("th");
+    var searchbox = document.getElementById("report-search");
+    var sortkey = "self";
+    var ascending = false;
+    var filter = null;
+    var selected = null;
+
+    function format_count(n) {
+        return (n * report.factor).toLocaleString() + " " + report.countName;
+    }
+    function format_percent(n) {
+        return report.total ? (100 * n / report.total).toFixed(2) + "%" : "";
+    }
+    function cell(row, text, title) {
+        var td = document.createElement("td");
+        td.textContent = text;
+        if (title) td.title = title;
+        row.appendChild(td);
+    }
+    function function_row(id, count, extra) {
+        var f = functions[id];
+        var row = document.createElement("tr");
+        cell(row, f.name);
+        cell(row, format_percent(count), format_count(count));
+        if (extra !== undefined) cell(row, format_percent(extra), format_count(extra));
+        if (id === selected) row.classList.add("selected");
+        row.addEventListener("click", function() { select(id); });
+        return row;
+    }
+
+    // The table of functions, filtered by the search box.
+    function render_functions() {
+        var order = [];
+        for (var i = 0; i < functions.length; i++) {
+            if (!filter || filter.test(functions[i].name)) order.push(i);
+        }
+        order.sort(function(a, b) {
+            var x = functions[a][sortkey], y = functions[b][sortkey];
+            var c = x < y ? -1 : x > y ? 1 : a - b;
+            return ascending ? c : -c;
+        });
+        var tbody = document.createElement("tbody");
+        for (var i = 0; i < order.length; i++) {
+            var f = functions[order[i]];
+            tbody.appendChild(function_row(order[i], f.self, f.total));
+        }
+        table.replaceChild(tbody, table.tBodies[0]);
+        for (var i = 0; i < headers.length; i++) {
+            var sorted = headers[i].dataset.key === sortkey;
+            headers[i].classList.toggle("sorted", sorted);
+            headers[i].classList.toggle("ascending", sorted && ascending);
+        }
+    }
+
+    // The callers and callees of the selected function, the "sandwich" around it.
+    function render_sandwich() {
+        if (selected === null) return;
+        var f = functions[selected];
+        document.getElementById("selected").textContent = f.name + " (self " +
+            format_count(f.self) + ", total " + format_count(f.total) + ")";
+        var panes = { callers: f.callers, callees: f.callees };
+        for (var key in panes) {
+            var edges = panes[key].slice().sort(function(a, b) { return b[1] - a[1]; });
+            var tbody = document.createElement("tbody");
+            for (var i = 0; i < edges.length; i++) {
+                tbody.appendChild(function_row(edges[i][0], edges[i][1]));
+            }
+            var pane = document.getElementById(key);
+            pane.replaceChild(tbody, pane.tBodies[0]);
+        }
+    }
+
+    function select(id) {
+        selected = id;
+        render_functions();
+        render_sandwich();
+    }
+
+    for (var i = 0; i < headers.length; i++) {
+        headers[i].addEventListener("click", function(e) {
+            var key = e.target.dataset.key;
+            if (key === sortkey) {
+                ascending = !ascending;
+            } else {
+                sortkey = key;
+                ascending = key === "name";
+            }
+            render_functions();
+        });
+    }
+
+    searchbox.addEventListener("input", function() {
+        var term = searchbox.value;
+        try {
+            filter = term ? new RegExp(term) : null;
+        } catch (e) {
+            searchbox.classList.add("invalid");
+            return;
+        }
+        searchbox.classList.remove("invalid");
+        render_functions();
+        // Highlight the matches in the flame graph too, if it has its script.
+        if (typeof search === "function") {
+            if (searching) search_prompt();
+            if (term) search(term);
+        }
+    });
+
+    // Clicking a frame selects its function, as well as zooming in on it.
+    var frames = document.getElementById("frames");
+    if (frames) {
+        frames.addEventListener("click", function(e) {
+            var node = e.target;
+            while (node && node.parentNode !== frames) node = node.parentNode;
+            var title = node && node.querySelector("title");
+            if (!title) return;
+            var text = title.textContent;
+            var name = text.substring(0, text.lastIndexOf(" ("));
+            if (ids.hasOwnProperty(name)) select(ids[name]);
+        });
+    }
+
+    render_functions();
+})();
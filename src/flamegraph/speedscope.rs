@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use log::error;
+
+use super::merge::TimedFrame;
+use super::{deannotate, json, Options};
+use crate::Error;
+
+const SCHEMA: &str = "https://www.speedscope.app/file-format-schema.json";
+
+/// Writes `frames` as a [speedscope](https://www.speedscope.app/) file with a single evented
+/// profile.
+///
+/// Every frame is opened at its start time and closed at its end time, so the time order view of
+/// speedscope shows the same layout as the SVG, whether that is a flame graph or a flame chart.
+/// Differential counts are not supported by the format and are left out.
+pub(super) fn write<W: Write>(
+    opt: &Options<'_>,
+    frames: &[TimedFrame<'_>],
+    time: u64,
+    mut writer: W,
+) -> io::Result<()> {
+    if time == 0 {
+        if opt.strict {
+            return Err(Error::EmptyInput.into());
+        }
+        error!("No stack counts found");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No stack counts found",
+        ));
+    }
+
+    // Frames are closed before others are opened at the same time, innermost first, and opened
+    // outermost first, so that every close matches the innermost open frame.
+    let mut events: Vec<(u64, bool, isize, &str)> = Vec::with_capacity(frames.len() * 2);
+    for frame in frames {
+        // The root frame is speedscope's own.
+        if frame.location.depth == 0 {
+            continue;
+        }
+        let depth = frame.location.depth as isize;
+        let function = deannotate(frame.location.function);
+        events.push((frame.start_time, true, depth, function));
+        events.push((frame.end_time, false, -depth, function));
+    }
+    events.sort_unstable_by_key(|&(at, open, depth, _)| (at, open, depth));
+
+    let mut frame_ids: HashMap<&str, usize> = HashMap::new();
+    let mut names = Vec::new();
+    for &(_, _, _, function) in &events {
+        frame_ids.entry(function).or_insert_with(|| {
+            names.push(function);
+            names.len() - 1
+        });
+    }
+
+    write!(
+        writer,
+        "{{\"$schema\":\"{}\",\"shared\":{{\"frames\":[",
+        SCHEMA
+    )?;
+    for (i, name) in names.iter().enumerate() {
+        if i != 0 {
+            writer.write_all(b",")?;
+        }
+        writer.write_all(b"{\"name\":")?;
+        json::write_str(&mut writer, name)?;
+        writer.write_all(b"}")?;
+    }
+    writer.write_all(b"]},\"profiles\":[{\"type\":\"evented\",\"name\":")?;
+    json::write_str(&mut writer, &format!("{} ({})", opt.title, opt.count_name))?;
+    write!(
+        writer,
+        ",\"unit\":\"{}\",\"startValue\":0,\"endValue\":",
+        unit(&opt.count_name),
+    )?;
+    json::write_scaled(&mut writer, time as f64, opt.factor)?;
+    writer.write_all(b",\"events\":[")?;
+    for (i, &(at, open, _, function)) in events.iter().enumerate() {
+        if i != 0 {
+            writer.write_all(b",")?;
+        }
+        write!(
+            writer,
+            "{{\"type\":\"{}\",\"frame\":{},\"at\":",
+            if open { "O" } else { "C" },
+            frame_ids[function],
+        )?;
+        json::write_scaled(&mut writer, at as f64, opt.factor)?;
+        writer.write_all(b"}")?;
+    }
+    writer.write_all(b"]}],\"name\":")?;
+    json::write_str(&mut writer, &opt.title)?;
+    writeln!(
+        writer,
+        ",\"activeProfileIndex\":0,\"exporter\":\"inferno\"}}"
+    )?;
+    writer.flush()
+}
+
+// Picks the speedscope unit that matches the count name, so that speedscope can format the
+// weights, or "none" to show them as plain numbers.
+fn unit(count_name: &str) -> &'static str {
+    match count_name.to_ascii_lowercase().as_str() {
+        "ns" | "nanoseconds" => "nanoseconds",
+        "us" | "µs" | "microseconds" => "microseconds",
+        "ms" | "milliseconds" => "milliseconds",
+        "s" | "seconds" => "seconds",
+        "b" | "bytes" => "bytes",
+        _ => "none",
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::{self, Write};
+
+use log::error;
+
+use super::merge::TimedFrame;
+use super::{deannotate, json, Options};
+use crate::Error;
+
+/// Writes `frames` as a tree of nested JSON objects, in the format of
+/// [d3-flame-graph](https://github.com/spiermar/d3-flame-graph).
+///
+/// Every node has the `name` of its function, its `value` and `self` counts, a `delta` if the
+/// input was differential, and its `children` from left to right. The root node is named `all`.
+/// Counts are multiplied by [`Options::factor`], and nodes narrower than [`Options::min_width`]
+/// are left out, but still count towards the `self` of their parent.
+pub(super) fn write<W: Write>(
+    opt: &Options<'_>,
+    frames: &[TimedFrame<'_>],
+    time: u64,
+    mut writer: W,
+) -> io::Result<()> {
+    if time == 0 {
+        if opt.strict {
+            return Err(Error::EmptyInput.into());
+        }
+        error!("No stack counts found");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No stack counts found",
+        ));
+    }
+
+    let nodes = nodes(frames);
+    let minwidth_time = opt.min_width * time as f64 / 100.0;
+    write_node(&mut writer, opt, &nodes, 0, minwidth_time)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+#[derive(Debug)]
+struct Node<'a> {
+    name: &'a str,
+    value: u64,
+    self_value: u64,
+    delta: Option<isize>,
+    children: Vec<usize>,
+}
+
+/// Builds the tree of `frames`, with the root frame as the first node.
+fn nodes<'a>(frames: &[TimedFrame<'a>]) -> Vec<Node<'a>> {
+    // Every frame is visited after its parent, and its siblings from left to right, so that the
+    // stack of frames from the root down to the current one can be kept.
+    let mut order: Vec<&TimedFrame<'_>> = frames.iter().collect();
+    order.sort_unstable_by_key(|f| (f.start_time, f.location.depth));
+
+    let mut nodes: Vec<Node<'_>> = Vec::with_capacity(frames.len());
+    let mut stack: Vec<usize> = Vec::new();
+    for frame in order {
+        let depth = frame.location.depth;
+        let value = frame.end_time - frame.start_time;
+        let name = if depth == 0 && frame.location.function.is_empty() {
+            "all"
+        } else {
+            deannotate(frame.location.function)
+        };
+
+        let id = nodes.len();
+        nodes.push(Node {
+            name,
+            value,
+            self_value: value,
+            delta: frame.delta,
+            children: Vec::new(),
+        });
+        stack.truncate(depth);
+        if let Some(&parent) = stack.last() {
+            nodes[parent].self_value -= value;
+            nodes[parent].children.push(id);
+        }
+        stack.push(id);
+    }
+    nodes
+}
+
+fn write_node<W: Write>(
+    writer: &mut W,
+    opt: &Options<'_>,
+    nodes: &[Node<'_>],
+    id: usize,
+    minwidth_time: f64,
+) -> io::Result<()> {
+    let node = &nodes[id];
+    writer.write_all(b"{\"name\":")?;
+    json::write_str(writer, node.name)?;
+    writer.write_all(b",\"value\":")?;
+    json::write_scaled(writer, node.value as f64, opt.factor)?;
+    writer.write_all(b",\"self\":")?;
+    json::write_scaled(writer, node.self_value as f64, opt.factor)?;
+    if let Some(delta) = node.delta {
+        writer.write_all(b",\"delta\":")?;
+        json::write_scaled(writer, delta as f64, opt.factor)?;
+    }
+    writer.write_all(b",\"children\":[")?;
+    let mut first = true;
+    for &child in &node.children {
+        if (nodes[child].value as f64) < minwidth_time {
+            continue;
+        }
+        if !first {
+            writer.write_all(b",")?;
+        }
+        first = false;
+        write_node(writer, opt, nodes, child, minwidth_time)?;
+    }
+    writer.write_all(b"]}")
+}
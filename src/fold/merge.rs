@@ -0,0 +1,256 @@
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+use ahash::AHashMap;
+
+use super::read_stacks;
+use crate::collapse::FoldedStacks;
+use crate::folded;
+
+/// Configure how profiles are merged.
+///
+/// All options default to off.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Scale every profile so that its counts add up to the same total before it is weighted.
+    ///
+    /// This is useful when profiles were recorded at different frequencies or for different
+    /// durations, and each should contribute equally to the merged profile. The total is
+    /// [`Options::total`] if set, and the largest total of the profiles otherwise.
+    pub normalize: bool,
+
+    /// The total to normalize every profile to when [`Options::normalize`] is set.
+    pub total: Option<u64>,
+
+    /// Fail rather than log a warning when a line can't be parsed. See
+    /// [strict mode](crate::folded#strict-mode).
+    pub strict: bool,
+}
+
+/// Configure how a single profile is added to the merged profile.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Input {
+    /// Multiply every count of the profile by this factor, which must be a positive number.
+    ///
+    /// The default is 1.
+    pub weight: f64,
+
+    /// Add a frame with this name to the root of every stack of the profile, so that the
+    /// profiles can be told apart in the merged profile.
+    ///
+    /// The name should not contain `;`, since that separates frames.
+    pub tag: Option<String>,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self {
+            weight: 1.0,
+            tag: None,
+        }
+    }
+}
+
+/// Sums the counts of the given profiles.
+///
+/// Every profile is normalized if [`Options::normalize`] is set, multiplied by its
+/// [`Input::weight`], and tagged with its [`Input::tag`]. The weighted counts are summed before
+/// they are rounded to the nearest integer, and stacks whose count rounds to 0 are dropped.
+///
+/// # Panics
+///
+/// Panics if the [`Input::weight`] of a profile is not a positive number.
+pub fn merge(opt: &Options, profiles: &[(FoldedStacks, Input)]) -> FoldedStacks {
+    for (_, input) in profiles {
+        if let Err(e) = check_weight(input) {
+            panic!("{}", e);
+        }
+    }
+    let target = if opt.normalize {
+        opt.total.unwrap_or_else(|| {
+            profiles
+                .iter()
+                .map(|(stacks, _)| stacks.total_count())
+                .max()
+                .unwrap_or(0)
+        })
+    } else {
+        0
+    };
+
+    let mut counts: AHashMap<String, f64> = AHashMap::default();
+    for (stacks, input) in profiles {
+        let total = stacks.total_count();
+        let factor = if opt.normalize && total != 0 {
+            input.weight * target as f64 / total as f64
+        } else {
+            input.weight
+        };
+
+        for (ids, count) in stacks.stacks() {
+            let stack = match &input.tag {
+                Some(tag) => format!("{};{}", tag, stacks.join(ids)),
+                None => stacks.join(ids),
+            };
+            *counts.entry(stack).or_default() += count as f64 * factor;
+        }
+    }
+
+    let mut result = FoldedStacks::new();
+    for (stack, count) in counts {
+        let count = count.round();
+        if count >= 1.0 {
+            result.add(&stack, count as u64);
+        }
+    }
+    result
+}
+
+/// Reads folded stack lines from every reader, merges the profiles as [`merge`] does, and writes
+/// the result to `writer`, sorted by stack.
+///
+/// The input is in the [folded format](crate::folded), without second counts.
+pub fn from_readers<I, R, W>(opt: &Options, readers: I, writer: W) -> io::Result<()>
+where
+    I: IntoIterator<Item = (R, Input)>,
+    R: BufRead,
+    W: Write,
+{
+    let mut profiles = Vec::new();
+    for (reader, input) in readers {
+        check_weight(&input)?;
+        profiles.push((read_profile(opt, reader)?, input));
+    }
+    merge(opt, &profiles).write_to(writer)
+}
+
+/// Reads folded stack lines from the given files and merges them as [`from_readers`] does.
+///
+/// See [reading files](crate::folded#reading-files) for how the files are read.
+pub fn from_files<P, W>(opt: &Options, files: &[(P, Input)], writer: W) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    for (_, input) in files {
+        check_weight(input)?;
+    }
+    let mut profiles = Vec::new();
+    for (path, input) in files {
+        let stacks = folded::read_file(path.as_ref(), |reader| read_profile(opt, reader))?;
+        profiles.push((stacks, input.clone()));
+    }
+    merge(opt, &profiles).write_to(writer)
+}
+
+fn check_weight(input: &Input) -> io::Result<()> {
+    if input.weight.is_finite() && input.weight > 0.0 {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("weight must be a positive number, not {}", input.weight),
+        ))
+    }
+}
+
+fn read_profile<R: BufRead>(opt: &Options, reader: R) -> io::Result<FoldedStacks> {
+    let read_opt = super::Options {
+        strict: opt.strict,
+        ..Default::default()
+    };
+    let mut stacks = FoldedStacks::new();
+    read_stacks(&read_opt, reader, &mut stacks)?;
+    Ok(stacks)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::Error;
+
+    fn merge_str(opt: &Options, inputs: &[(&str, Input)]) -> String {
+        let readers = inputs
+            .iter()
+            .map(|(text, input)| (text.as_bytes(), input.clone()));
+        let mut output = Vec::new();
+        from_readers(opt, readers, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn weights_and_tags_profiles() {
+        let a = Input {
+            tag: Some("host-a".to_string()),
+            ..Default::default()
+        };
+        let b = Input {
+            weight: 0.5,
+            tag: Some("host-b".to_string()),
+        };
+        assert_eq!(
+            merge_str(
+                &Options::default(),
+                &[("main;foo 3\nmain 1\n", a), ("main;foo 5\n", b)]
+            ),
+            "host-a;main 1\nhost-a;main;foo 3\nhost-b;main;foo 3\n"
+        );
+    }
+
+    #[test]
+    fn rejects_differential_lines() {
+        let readers = [("main;foo 3 5\n".as_bytes(), Input::default())];
+        let error = from_readers(&Options::default(), readers, io::sink()).unwrap_err();
+        assert!(matches!(
+            Error::from_io(error),
+            Error::Parse { line: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_weights() {
+        for weight in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let input = Input {
+                weight,
+                ..Default::default()
+            };
+            let readers = [("main;foo 3\n".as_bytes(), input)];
+            let error = from_readers(&Options::default(), readers, io::sink()).unwrap_err();
+            assert_eq!(
+                error.kind(),
+                io::ErrorKind::InvalidInput,
+                "weight {}",
+                weight
+            );
+        }
+    }
+
+    #[test]
+    fn normalizes_before_rounding() {
+        let mut opt = Options {
+            normalize: true,
+            ..Default::default()
+        };
+        // The second profile is scaled up by 10/4, and its two `main;foo` lines only round once.
+        assert_eq!(
+            merge_str(
+                &opt,
+                &[
+                    ("main;foo 6\nmain;bar 4\n", Input::default()),
+                    ("main;foo 1\nmain;baz 2\nmain;foo 1\n", Input::default()),
+                ]
+            ),
+            "main;bar 4\nmain;baz 5\nmain;foo 11\n"
+        );
+
+        opt.total = Some(2);
+        assert_eq!(
+            merge_str(&opt, &[("a 1\nb 3\n", Input::default())]),
+            "a 1\nb 2\n"
+        );
+    }
+}
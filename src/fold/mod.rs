@@ -0,0 +1,310 @@
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::collapse::FoldedStacks;
+use crate::folded;
+use crate::Error;
+
+/// Merging of several profiles, with per-profile weights and tags.
+pub mod merge;
+
+/// Configure what is done to the stacks.
+///
+/// The operations are applied to every stack in the order the fields are listed in: stacks are
+/// kept or dropped first, based on all of their frames, then frames are dropped, the stack is
+/// re-rooted and trimmed, and finally the counts are scaled and the top stacks picked. Stacks
+/// that end up the same are merged.
+///
+/// All options default to off.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Only keep stacks with a frame that matches this pattern.
+    pub keep: Option<Regex>,
+
+    /// Drop stacks with a frame that matches this pattern.
+    pub drop: Option<Regex>,
+
+    /// Remove the frames that match this pattern from every stack. Stacks that are left with no
+    /// frames are dropped.
+    pub drop_frames: Option<Regex>,
+
+    /// Start every stack at its innermost frame that matches this pattern, and drop stacks that
+    /// have no such frame.
+    ///
+    /// This is like [`crate::flamegraph::Options::base`], except that the root frame can match
+    /// too.
+    pub reroot: Option<Regex>,
+
+    /// Cut every stack off after this many frames from the root. Stacks whose inner frames are cut
+    /// off are merged into their callers.
+    pub max_depth: Option<usize>,
+
+    /// Multiply every count by this factor, rounding to the nearest integer. Stacks whose count
+    /// rounds to 0 are dropped. The factor must be a positive number.
+    pub scale: Option<f64>,
+
+    /// Only keep this many stacks, those with the highest counts.
+    pub top: Option<usize>,
+
+    /// Fail rather than log a warning when a line can't be parsed. See
+    /// [strict mode](crate::folded#strict-mode).
+    pub strict: bool,
+}
+
+/// Reads folded stack lines from `reader`, applies the configured operations to them, and writes
+/// the result to `writer`.
+///
+/// The input is in the [folded format](crate::folded), and so is the output, sorted by stack.
+/// Differential input, with two counts per line, is not supported, and fails with an
+/// [`Error::Parse`] whether or not [`Options::strict`] is set.
+pub fn from_reader<R, W>(opt: &Options, reader: R, writer: W) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    check_scale(opt)?;
+    let mut stacks = FoldedStacks::new();
+    read_stacks(opt, reader, &mut stacks)?;
+    apply(opt, &stacks).write_to(writer)
+}
+
+/// Reads folded stack lines from the given files, or from STDIN if `files` is empty, and
+/// transforms them as [`from_reader`] does.
+///
+/// The stacks of all the files are merged. See [reading files](crate::folded#reading-files) for
+/// how the files are read.
+pub fn from_files<P, W>(opt: &Options, files: &[P], writer: W) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    check_scale(opt)?;
+    let mut stacks = FoldedStacks::new();
+    folded::read_files(files, |reader| read_stacks(opt, reader, &mut stacks))?;
+    apply(opt, &stacks).write_to(writer)
+}
+
+/// Applies the configured operations to `stacks`.
+///
+/// # Panics
+///
+/// Panics if [`Options::scale`] is not a positive number.
+pub fn apply(opt: &Options, stacks: &FoldedStacks) -> FoldedStacks {
+    if let Err(e) = check_scale(opt) {
+        panic!("{}", e);
+    }
+    let mut result = FoldedStacks::new();
+    let mut frames = Vec::new();
+    for (ids, count) in stacks.stacks() {
+        frames.clear();
+        frames.extend(ids.iter().map(|&id| stacks.frame(id)));
+        if transform(opt, &mut frames) {
+            result.add_frames(frames.iter().copied(), count);
+        }
+    }
+
+    if let Some(factor) = opt.scale {
+        result = scale(&result, factor);
+    }
+    match opt.top {
+        Some(n) if n < result.len() => top(&result, n),
+        _ => result,
+    }
+}
+
+// Applies the operations on frames to `frames`, and returns whether the stack is kept.
+fn transform(opt: &Options, frames: &mut Vec<&str>) -> bool {
+    let matches = |pattern: &Regex, frames: &[&str]| frames.iter().any(|f| pattern.is_match(f));
+    if let Some(keep) = &opt.keep {
+        if !matches(keep, frames) {
+            return false;
+        }
+    }
+    if let Some(drop) = &opt.drop {
+        if matches(drop, frames) {
+            return false;
+        }
+    }
+    if let Some(drop_frames) = &opt.drop_frames {
+        frames.retain(|f| !drop_frames.is_match(f));
+    }
+    if let Some(reroot) = &opt.reroot {
+        match frames.iter().rposition(|f| reroot.is_match(f)) {
+            Some(root) => {
+                frames.drain(..root);
+            }
+            None => return false,
+        }
+    }
+    if let Some(max_depth) = opt.max_depth {
+        frames.truncate(max_depth);
+    }
+    !frames.is_empty()
+}
+
+fn check_scale(opt: &Options) -> io::Result<()> {
+    match opt.scale {
+        Some(factor) if !(factor.is_finite() && factor > 0.0) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("scale must be a positive number, not {}", factor),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn scale(stacks: &FoldedStacks, factor: f64) -> FoldedStacks {
+    let mut result = FoldedStacks::new();
+    for (ids, count) in stacks.stacks() {
+        let count = (count as f64 * factor).round() as u64;
+        if count != 0 {
+            result.add_frames(ids.iter().map(|&id| stacks.frame(id)), count);
+        }
+    }
+    result
+}
+
+// Keeps the `n` stacks with the highest counts. Ties are broken by stack, so that the same input
+// always keeps the same stacks.
+fn top(stacks: &FoldedStacks, n: usize) -> FoldedStacks {
+    let mut sorted: Vec<_> = stacks
+        .stacks()
+        .map(|(ids, count)| (count, stacks.join(ids)))
+        .collect();
+    sorted.sort_by(|(a_count, a), (b_count, b)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+
+    let mut result = FoldedStacks::new();
+    for (count, stack) in sorted.into_iter().take(n) {
+        result.add(&stack, count);
+    }
+    result
+}
+
+// Adds the stacks in `reader` to `stacks`, skipping the lines that can't be parsed unless
+// `opt.strict` is set.
+fn read_stacks<R>(opt: &Options, reader: R, stacks: &mut FoldedStacks) -> io::Result<()>
+where
+    R: BufRead,
+{
+    folded::read_lines(reader, opt.strict, |line| {
+        if line.second.is_some() {
+            return Err(Error::parse(
+                line.number,
+                line.text,
+                "Differential lines with two counts can't be folded",
+            )
+            .into());
+        }
+        stacks.add(line.stack, line.count);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn fold(opt: &Options, input: &str) -> String {
+        let mut output = Vec::new();
+        from_reader(opt, input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    const INPUT: &str = "\
+main;parse;lex 3
+main;parse;eval;lex 2
+main;eval 5
+start;main;eval;gc 1
+";
+
+    #[test]
+    fn keeps_and_drops_stacks() {
+        let opt = Options {
+            keep: Some(Regex::new("^lex$").unwrap()),
+            drop: Some(Regex::new("eval").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(fold(&opt, INPUT), "main;parse;lex 3\n");
+    }
+
+    #[test]
+    fn drops_frames_and_merges() {
+        let opt = Options {
+            drop_frames: Some(Regex::new("^(parse|start)$").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            fold(&opt, INPUT),
+            "main;eval 5\nmain;eval;gc 1\nmain;eval;lex 2\nmain;lex 3\n"
+        );
+    }
+
+    #[test]
+    fn reroots_at_innermost_match() {
+        let opt = Options {
+            reroot: Some(Regex::new("^(main|eval)$").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            fold(&opt, INPUT),
+            "eval 5\neval;gc 1\neval;lex 2\nmain;parse;lex 3\n"
+        );
+    }
+
+    #[test]
+    fn trims_scales_and_picks_top() {
+        let opt = Options {
+            max_depth: Some(2),
+            scale: Some(0.5),
+            top: Some(2),
+            ..Default::default()
+        };
+        // The stacks are merged before they are scaled, so `main;parse` is 2.5 rather than 1.5
+        // and 1, and `start;main` is 0.5, which rounds up but loses the tie.
+        assert_eq!(fold(&opt, INPUT), "main;eval 3\nmain;parse 3\n");
+    }
+
+    #[test]
+    fn skips_invalid_lines_unless_strict() {
+        let input = "# snapshot 1\nmain;foo 1.0\nnot a count\n\nmain;foo 2\n";
+        assert_eq!(fold(&Options::default(), input), "main;foo 3\n");
+
+        let opt = Options {
+            strict: true,
+            ..Default::default()
+        };
+        let error = from_reader(&opt, input.as_bytes(), io::sink()).unwrap_err();
+        match Error::from_io(error) {
+            Error::Parse { line, .. } => assert_eq!(line, 3),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn rejects_differential_lines() {
+        let opt = Options {
+            top: Some(1),
+            ..Default::default()
+        };
+        let error = from_reader(
+            &opt,
+            "main 1
+main;foo 3 5
+"
+            .as_bytes(),
+            io::sink(),
+        )
+        .unwrap_err();
+        match Error::from_io(error) {
+            Error::Parse { line, text, .. } => {
+                assert_eq!(line, 2);
+                assert_eq!(text, "main;foo 3 5");
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
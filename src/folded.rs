@@ -0,0 +1,205 @@
+//! Every line of folded stacks is a semicolon-separated list of frame names, from the root of the
+//! stack to its leaf, followed by a space and the number of samples of that stack:
+//!
+//! ```text
+//! main;foo;bar 3
+//! main;baz 1
+//! ```
+//!
+//! A line can have a second count, like `main;foo;bar 3 5`, for differential input like that
+//! written by [`crate::differential`]. Empty lines and lines that start with `# ` are skipped.
+//! This is the format that [`crate::flamegraph::from_lines`] reads.
+//!
+//! # Reading files
+//!
+//! The `from_files` functions that read folded stacks, like [`crate::report::from_files`], read
+//! STDIN in place of a file named `-`. Files compressed with gzip, zstd, or xz are decompressed if
+//! the cargo feature of the same name is enabled.
+//!
+//! # Strict mode
+//!
+//! A line that can't be parsed is skipped with a warning, unless the `strict` option of the tool
+//! reading it is set, in which case it is an [`Error::Parse`]. When it was read from a file, the
+//! error includes the path of the file.
+
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use log::warn;
+
+use crate::collapse::common::CAPACITY_READER;
+use crate::compression;
+use crate::Error;
+
+/// A line of folded stacks, like `main;foo;bar 3`, or `main;foo;bar 3 5` for differential input.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Line<'a> {
+    /// The 1-based number of the line within its input.
+    pub(crate) number: usize,
+    /// The line, without surrounding whitespace.
+    pub(crate) text: &'a str,
+    /// The `;`-separated frames.
+    pub(crate) stack: &'a str,
+    /// The count, or the count before the change for differential input.
+    pub(crate) count: u64,
+    /// The count after the change, for differential input.
+    pub(crate) second: Option<u64>,
+}
+
+/// Calls `on_line` with every line of folded stacks in `reader`.
+///
+/// Empty lines and lines that start with `# ` are skipped, and so are lines that can't be parsed,
+/// with a warning, unless `strict` is set, in which case they are an [`Error::Parse`].
+pub(crate) fn read_lines<R, F>(mut reader: R, strict: bool, mut on_line: F) -> io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(Line<'_>) -> io::Result<()>,
+{
+    let mut line = Vec::new();
+    let mut number = 0;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        number += 1;
+
+        let l = String::from_utf8_lossy(&line);
+        let text = l.trim();
+        if text.is_empty() || text.starts_with("# ") {
+            continue;
+        }
+        match parse_line(text) {
+            Some((stack, count, second)) => on_line(Line {
+                number,
+                text,
+                stack,
+                count,
+                second,
+            })?,
+            None if strict => {
+                return Err(Error::parse(number, text, "Unable to parse folded line").into());
+            }
+            None => warn!("Unable to parse folded line: {}", text),
+        }
+    }
+}
+
+/// Calls `read` with a reader of every file in `files`, or of STDIN if `files` is empty. A file
+/// named `-` is STDIN too.
+///
+/// Files compressed with gzip, zstd, or xz are decompressed if the cargo feature of the same name
+/// is enabled. An [`Error::Parse`] that `read` returns is given the path of its file.
+pub(crate) fn read_files<P, F>(files: &[P], mut read: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(&mut dyn BufRead) -> io::Result<()>,
+{
+    if files.is_empty() {
+        return read(&mut open(Path::new("-"))?);
+    }
+    for path in files {
+        let path = path.as_ref();
+        read_file(path, &mut read)?;
+    }
+    Ok(())
+}
+
+/// Calls `read` with a reader of the file at `path`, where `-` is STDIN, as
+/// [`read_files`] does.
+pub(crate) fn read_file<F, T>(path: &Path, read: F) -> io::Result<T>
+where
+    F: FnOnce(&mut dyn BufRead) -> io::Result<T>,
+{
+    let result = read(&mut open(path)?);
+    if path == Path::new("-") {
+        result
+    } else {
+        result.map_err(|e| Error::with_path(e, path))
+    }
+}
+
+fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    if path == Path::new("-") {
+        let reader = io::BufReader::with_capacity(CAPACITY_READER, io::stdin().lock());
+        compression::decompress(reader)
+    } else {
+        let reader = io::BufReader::with_capacity(CAPACITY_READER, File::open(path)?);
+        compression::decompress(reader)
+    }
+}
+
+// Splits a line into its stack and its one or two counts. Like `flamegraph`, this truncates
+// fractional counts, and takes the token before the last to be a count too if it is one.
+fn parse_line(line: &str) -> Option<(&str, u64, Option<u64>)> {
+    let (rest, last) = line.rsplit_once(' ')?;
+    let last = parse_count(last)?;
+    let rest = rest.trim_end();
+    let (stack, first, second) = match rest.rsplit_once(' ') {
+        Some((stack, first)) => match parse_count(first) {
+            Some(first) => (stack.trim_end(), first, Some(last)),
+            None => (rest, last, None),
+        },
+        None => (rest, last, None),
+    };
+    if stack.is_empty() {
+        return None;
+    }
+    Some((stack, first, second))
+}
+
+fn parse_count(count: &str) -> Option<u64> {
+    let count = match count.split_once('.') {
+        Some((whole, fraction)) if fraction.chars().all(|c| c.is_ascii_digit()) => whole,
+        Some(_) => return None,
+        None => count,
+    };
+    count.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_one_or_two_counts() {
+        assert_eq!(parse_line("main;foo 3"), Some(("main;foo", 3, None)));
+        assert_eq!(parse_line("main;foo 3 5"), Some(("main;foo", 3, Some(5))));
+        assert_eq!(
+            parse_line("main;[u8; 8] 1.5"),
+            Some(("main;[u8; 8]", 1, None))
+        );
+        assert_eq!(parse_line("main;foo"), None);
+        assert_eq!(parse_line("42"), None);
+    }
+
+    #[test]
+    fn skips_comments_and_invalid_lines_unless_strict() {
+        let input = "# snapshot 1\n\nmain;foo 1\nnot a count\n  main;bar 2 3  \n";
+        let mut lines = Vec::new();
+        read_lines(input.as_bytes(), false, |line| {
+            lines.push((line.number, line.text.to_string(), line.count, line.second));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            lines,
+            [
+                (3, "main;foo 1".to_string(), 1, None),
+                (5, "main;bar 2 3".to_string(), 2, Some(3)),
+            ]
+        );
+
+        let error = read_lines(input.as_bytes(), true, |_| Ok(())).unwrap_err();
+        match Error::from_io(error) {
+            Error::Parse { line, text, .. } => {
+                assert_eq!(line, 4);
+                assert_eq!(text, "not a count");
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
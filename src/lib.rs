@@ -138,11 +138,64 @@
 //! $ inferno-diff-folded folded2 folded1 | inferno-flamegraph --negate > diff1.svg
 //! ```
 //!
+//! ## Transforming folded stacks
+//!
+//! To look at part of a profile, you can filter and transform the folded stacks with
+//! `inferno-fold` (or the [`fold`] module) before plotting them. For example, to plot only the
+//! stacks that go through `parse`, starting at `parse`, at most 20 frames deep:
+//!
+//! ```console
+//! $ inferno-fold --keep '^parse$' --reroot '^parse$' --max-depth 20 stacks.folded | inferno-flamegraph > parse.svg
+//! ```
+//!
+//! See `inferno-fold --help` for the other operations.
+//!
+//! ## Converting to pprof
+//!
+//! Tools that ingest [pprof] profiles can't read folded stacks directly. `inferno-pprof` (or the
+//! [`pprof`] module) converts folded stacks, or the output of `inferno-diff-folded`, to a gzipped
+//! `profile.proto`:
+//!
+//! ```console
+//! $ inferno-pprof --sample-type cpu --unit nanoseconds stacks.folded > profile.pb.gz
+//! ```
+//!
+//! ## Ranking functions
+//!
+//! To see at a glance which functions take the most time, `inferno-report` (or the [`report`]
+//! module) ranks the functions in folded stacks by their self or total counts, as a table, CSV, or
+//! JSON. With `--baseline`, it also shows how much their share of the profile changed:
+//!
+//! ```console
+//! $ inferno-report --sort total --top 20 --baseline before.folded after.folded
+//! ```
+//!
+//! ## Recovering folded stacks from SVGs
+//!
+//! Every frame of a flame graph SVG made by inferno records its position and width in samples, so
+//! `inferno-svg-to-folded` (or [`collapse::svg`]) can rebuild the folded stacks it was drawn
+//! from, with both counts if it is a differential flame graph:
+//!
+//! ```console
+//! $ inferno-svg-to-folded old-flamegraph.svg | inferno-flamegraph --colors java > new.svg
+//! ```
+//!
 //! # Feature flags
 //! All features below are enabled by default
 //! - `cli`: Also builds the `inferno` command-line tools
 //! - `multithreaded`: Enables multithreaded stack-collapsing
 //! - `nameattr`: Allows for adding customizing and adding attributes to the svg of [`flamegraph`]. See the `--nameattr` option for the flamegraph cli
+//! - `gzip`: Transparently decompresses gzip input files and STDIN
+//! - `regex`: Enables [`collapse::rewrite`] and [`fold`], which match frames with regular
+//!   expressions. Required by `cli`
+//!
+//! These features are disabled by default, as they build C libraries
+//! - `zstd`: Transparently decompresses zstd input files and STDIN
+//! - `xz`: Transparently decompresses xz input files and STDIN
+//!
+//! This feature is disabled by default, as it embeds a font and pulls in a rasterizer
+//! - `png`: Allows writing flame graphs as PNG images with [`flamegraph::Format::Png`]. See the
+//!   `--format png` option for the flamegraph cli
 //!
 //! # Development
 //!
@@ -171,6 +224,7 @@
 //!   [sample]: https://gist.github.com/loderunner/36724cc9ee8db66db305#profiling-with-sample
 //!   [VTune]: https://software.intel.com/en-us/vtune-amplifier-help-command-line-interface
 //!   [gimli project]: https://github.com/gimli-rs/addr2line
+//!   [pprof]: https://github.com/google/pprof
 
 #![cfg_attr(doc, warn(rustdoc::all))]
 #![deny(missing_docs)]
@@ -184,6 +238,15 @@
 ///   [crate-level documentation]: ../index.html
 pub mod collapse;
 
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod cli;
+
+mod compression;
+
+/// The folded stack format that the collapsers write and the other tools read.
+pub mod folded;
+
 /// Tool for creating an output required to generate differential flame graphs.
 ///
 /// See the [crate-level documentation] for details.
@@ -191,6 +254,31 @@ pub mod collapse;
 ///   [crate-level documentation]: ../index.html
 pub mod differential;
 
+mod error;
+pub use error::Error;
+
+/// Tools for filtering and transforming folded stack traces.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../index.html
+#[cfg(feature = "regex")]
+pub mod fold;
+
+/// Tools for converting folded stack traces to pprof profiles.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../index.html
+pub mod pprof;
+
+/// Reports of the functions in folded stack traces, ranked by their self and total counts.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../index.html
+pub mod report;
+
 /// Tools for producing flame graphs from folded stack traces.
 ///
 /// See the [crate-level documentation] for details.
@@ -0,0 +1,334 @@
+mod proto;
+
+use std::collections::BTreeMap;
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+use ahash::AHashMap;
+
+use self::proto::Message;
+use crate::collapse::FoldedStacks;
+use crate::folded;
+use crate::Error;
+
+/// Default values for [`Options`].
+pub mod defaults {
+    /// "samples"
+    pub const SAMPLE_TYPE: &str = "samples";
+
+    /// "count"
+    pub const UNIT: &str = "count";
+}
+
+/// Configure the generated profile.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Options {
+    /// The type of the sample values, like `cpu` or `alloc_space`.
+    ///
+    /// When the input has two counts per stack, as written by [`crate::differential`], the
+    /// profile has two sample types, with `_before` and `_after` appended to this name.
+    ///
+    /// [Default value](defaults::SAMPLE_TYPE).
+    pub sample_type: String,
+
+    /// The unit of the sample values, like `nanoseconds` or `bytes`.
+    ///
+    /// [Default value](defaults::UNIT).
+    pub unit: String,
+
+    /// Fail rather than log a warning when a line can't be parsed, as described under
+    /// [strict mode](crate::folded#strict-mode), and with [`Error::EmptyInput`] rather than write
+    /// an empty profile when there are no stacks.
+    pub strict: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            sample_type: defaults::SAMPLE_TYPE.to_string(),
+            unit: defaults::UNIT.to_string(),
+            strict: false,
+        }
+    }
+}
+
+/// Converts folded stack lines from `reader` to a [pprof] profile, and writes it to `writer`.
+///
+/// The input is in the [folded format](crate::folded). Identical stacks are summed.
+///
+/// The output is a serialized `perftools.profiles.Profile` message, in which every distinct frame
+/// name is a single function and location, gzipped as pprof tools expect. Writing it fails if
+/// inferno was built without the `gzip` cargo feature.
+///
+/// [pprof]: https://github.com/google/pprof/blob/main/proto/profile.proto
+pub fn from_reader<R, W>(opt: &Options, reader: R, writer: W) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut stacks = Stacks::default();
+    read_stacks(opt, reader, &mut stacks)?;
+    write_profile(opt, &stacks, writer)
+}
+
+/// Converts folded stack lines from the given files, or from STDIN if `files` is empty, to a
+/// single profile as [`from_reader`] does.
+///
+/// See [reading files](crate::folded#reading-files) for how the files are read.
+pub fn from_files<P, W>(opt: &Options, files: &[P], writer: W) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let mut stacks = Stacks::default();
+    folded::read_files(files, |reader| read_stacks(opt, reader, &mut stacks))?;
+    write_profile(opt, &stacks, writer)
+}
+
+/// Converts in-memory [`FoldedStacks`] to a profile as [`from_reader`] does.
+pub fn from_stacks<W: Write>(opt: &Options, stacks: &FoldedStacks, writer: W) -> io::Result<()> {
+    let mut converted = Stacks::default();
+    for (ids, count) in stacks.stacks() {
+        converted.add(stacks.join(ids), [count, 0]);
+    }
+    write_profile(opt, &converted, writer)
+}
+
+// The summed counts of every stack, sorted by stack so that the output does not depend on the
+// order of the input.
+#[derive(Default)]
+struct Stacks {
+    counts: BTreeMap<String, [u64; 2]>,
+    differential: bool,
+}
+
+impl Stacks {
+    fn add(&mut self, stack: String, counts: [u64; 2]) {
+        let total = self.counts.entry(stack).or_default();
+        total[0] += counts[0];
+        total[1] += counts[1];
+    }
+}
+
+// Adds the stacks in `reader` to `stacks`, skipping the lines that can't be parsed unless
+// `opt.strict` is set.
+fn read_stacks<R>(opt: &Options, reader: R, stacks: &mut Stacks) -> io::Result<()>
+where
+    R: BufRead,
+{
+    folded::read_lines(reader, opt.strict, |line| {
+        stacks.differential |= line.second.is_some();
+        stacks.add(
+            line.stack.to_string(),
+            [line.count, line.second.unwrap_or(0)],
+        );
+        Ok(())
+    })
+}
+
+// Interns strings into the string table of the profile, where the empty string must come first.
+struct StringTable {
+    strings: Vec<String>,
+    indices: AHashMap<String, i64>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        let mut table = StringTable {
+            strings: Vec::new(),
+            indices: AHashMap::default(),
+        };
+        table.intern("");
+        table
+    }
+
+    fn intern(&mut self, s: &str) -> i64 {
+        if let Some(&index) = self.indices.get(s) {
+            return index;
+        }
+        let index = self.strings.len() as i64;
+        self.strings.push(s.to_string());
+        self.indices.insert(s.to_string(), index);
+        index
+    }
+}
+
+fn write_profile<W: Write>(opt: &Options, stacks: &Stacks, writer: W) -> io::Result<()> {
+    if opt.strict && stacks.counts.is_empty() {
+        return Err(Error::EmptyInput.into());
+    }
+    let profile = encode_profile(opt, stacks);
+
+    #[cfg(feature = "gzip")]
+    {
+        let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+        encoder.write_all(&profile)?;
+        encoder.finish()?.flush()
+    }
+
+    // pprof tools only read gzipped profiles, so a raw one would be of no use to anyone.
+    #[cfg(not(feature = "gzip"))]
+    {
+        let _ = (profile, writer);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "pprof profiles are gzip-compressed, but inferno was built without the `gzip` feature",
+        ))
+    }
+}
+
+// Field numbers of `perftools.profiles.Profile` and the messages in it.
+mod field {
+    pub(super) const PROFILE_SAMPLE_TYPE: u64 = 1;
+    pub(super) const PROFILE_SAMPLE: u64 = 2;
+    pub(super) const PROFILE_LOCATION: u64 = 4;
+    pub(super) const PROFILE_FUNCTION: u64 = 5;
+    pub(super) const PROFILE_STRING_TABLE: u64 = 6;
+    pub(super) const PROFILE_DEFAULT_SAMPLE_TYPE: u64 = 14;
+
+    pub(super) const VALUE_TYPE_TYPE: u64 = 1;
+    pub(super) const VALUE_TYPE_UNIT: u64 = 2;
+
+    pub(super) const SAMPLE_LOCATION_ID: u64 = 1;
+    pub(super) const SAMPLE_VALUE: u64 = 2;
+
+    pub(super) const LOCATION_ID: u64 = 1;
+    pub(super) const LOCATION_LINE: u64 = 4;
+
+    pub(super) const LINE_FUNCTION_ID: u64 = 1;
+
+    pub(super) const FUNCTION_ID: u64 = 1;
+    pub(super) const FUNCTION_NAME: u64 = 2;
+    pub(super) const FUNCTION_SYSTEM_NAME: u64 = 3;
+}
+
+fn encode_profile(opt: &Options, stacks: &Stacks) -> Vec<u8> {
+    let mut strings = StringTable::new();
+    let mut profile = Message::new();
+
+    let unit = strings.intern(&opt.unit);
+    let sample_types = if stacks.differential {
+        vec![
+            format!("{}_before", opt.sample_type),
+            format!("{}_after", opt.sample_type),
+        ]
+    } else {
+        vec![opt.sample_type.clone()]
+    };
+    for sample_type in &sample_types {
+        let mut value_type = Message::new();
+        value_type
+            .int(field::VALUE_TYPE_TYPE, strings.intern(sample_type))
+            .int(field::VALUE_TYPE_UNIT, unit);
+        profile.message(field::PROFILE_SAMPLE_TYPE, &value_type);
+    }
+
+    // Every function has a single location with the same id. Ids start at 1, since 0 is not a
+    // valid id.
+    let mut function_ids: AHashMap<&str, u64> = AHashMap::default();
+    let mut functions = Vec::new();
+    for (stack, counts) in &stacks.counts {
+        let location_ids: Vec<u64> = stack
+            .split(';')
+            .rev()
+            .map(|frame| {
+                *function_ids.entry(frame).or_insert_with(|| {
+                    functions.push(frame);
+                    functions.len() as u64
+                })
+            })
+            .collect();
+        let values = &counts[..sample_types.len()];
+
+        let mut sample = Message::new();
+        sample
+            .packed(field::SAMPLE_LOCATION_ID, location_ids)
+            .packed(field::SAMPLE_VALUE, values.iter().copied());
+        profile.message(field::PROFILE_SAMPLE, &sample);
+    }
+
+    for id in 1..=functions.len() as u64 {
+        let mut line = Message::new();
+        line.uint(field::LINE_FUNCTION_ID, id);
+        let mut location = Message::new();
+        location
+            .uint(field::LOCATION_ID, id)
+            .message(field::LOCATION_LINE, &line);
+        profile.message(field::PROFILE_LOCATION, &location);
+    }
+
+    for (i, name) in functions.iter().enumerate() {
+        let name = strings.intern(name);
+        let mut function = Message::new();
+        function
+            .uint(field::FUNCTION_ID, i as u64 + 1)
+            .int(field::FUNCTION_NAME, name)
+            .int(field::FUNCTION_SYSTEM_NAME, name);
+        profile.message(field::PROFILE_FUNCTION, &function);
+    }
+
+    // The last sample type, which is the profile after the change for differential input, is shown
+    // by default.
+    let default_sample_type = strings.intern(sample_types.last().unwrap());
+
+    for s in &strings.strings {
+        profile.bytes(field::PROFILE_STRING_TABLE, s.as_bytes());
+    }
+    profile.int(field::PROFILE_DEFAULT_SAMPLE_TYPE, default_sample_type);
+    profile.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn deduplicates_strings_and_functions() {
+        let mut stacks = Stacks::default();
+        let mut input = "main;foo;bar 2\nmain;bar 1\nmain;foo;bar 1\n".as_bytes();
+        read_stacks(&Options::default(), &mut input, &mut stacks).unwrap();
+        let profile = encode_profile(&Options::default(), &stacks);
+
+        let mut expected = Message::new();
+        let mut value_type = Message::new();
+        value_type.int(1, 2).int(2, 1);
+        expected.message(1, &value_type);
+        // main;bar, then main;foo;bar, leaf first.
+        for (locations, value) in [(&[1, 2][..], 1), (&[1, 3, 2][..], 3)] {
+            let mut sample = Message::new();
+            sample
+                .packed(1, locations.iter().copied())
+                .packed(2, [value]);
+            expected.message(2, &sample);
+        }
+        for id in 1..=3 {
+            let mut line = Message::new();
+            line.uint(1, id);
+            let mut location = Message::new();
+            location.uint(1, id).message(4, &line);
+            expected.message(4, &location);
+        }
+        for (id, name) in [(1, 3), (2, 4), (3, 5)] {
+            let mut function = Message::new();
+            function.uint(1, id).int(2, name).int(3, name);
+            expected.message(5, &function);
+        }
+        for s in ["", "count", "samples", "bar", "main", "foo"] {
+            expected.bytes(6, s.as_bytes());
+        }
+        expected.int(14, 2);
+        assert_eq!(profile, expected.into_bytes());
+    }
+
+    #[test]
+    #[cfg(not(feature = "gzip"))]
+    fn requires_gzip() {
+        let mut result = Vec::new();
+        let error = from_reader(&Options::default(), "main 1\n".as_bytes(), &mut result);
+        assert_eq!(error.unwrap_err().kind(), io::ErrorKind::Unsupported);
+        assert!(result.is_empty());
+    }
+}
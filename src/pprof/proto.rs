@@ -0,0 +1,96 @@
+//! Just enough of the protocol buffers wire format to write a `profile.proto`.
+//!
+//! Fields that are 0 are left out, like protobuf encoders do for `proto3` messages.
+
+const VARINT: u64 = 0;
+const LEN: u64 = 2;
+
+pub(super) struct Message(Vec<u8>);
+
+impl Message {
+    pub(super) fn new() -> Self {
+        Message(Vec::new())
+    }
+
+    pub(super) fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub(super) fn uint(&mut self, field: u64, value: u64) -> &mut Self {
+        if value != 0 {
+            self.key(field, VARINT);
+            self.varint(value);
+        }
+        self
+    }
+
+    pub(super) fn int(&mut self, field: u64, value: i64) -> &mut Self {
+        // `int64` values are encoded as their two's complement.
+        self.uint(field, value as u64)
+    }
+
+    pub(super) fn bytes(&mut self, field: u64, value: &[u8]) -> &mut Self {
+        self.key(field, LEN);
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    pub(super) fn message(&mut self, field: u64, message: &Message) -> &mut Self {
+        self.bytes(field, &message.0)
+    }
+
+    /// Writes a `repeated` integer field in the packed encoding.
+    pub(super) fn packed<I>(&mut self, field: u64, values: I) -> &mut Self
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        let mut packed = Message::new();
+        for value in values {
+            packed.varint(value);
+        }
+        if !packed.0.is_empty() {
+            self.bytes(field, &packed.0);
+        }
+        self
+    }
+
+    fn key(&mut self, field: u64, wire_type: u64) {
+        self.varint(field << 3 | wire_type);
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_fields() {
+        let mut inner = Message::new();
+        inner.uint(1, 150);
+        let mut message = Message::new();
+        message
+            .uint(1, 0)
+            .int(2, -1)
+            .bytes(3, b"ab")
+            .message(4, &inner)
+            .packed(5, [1, 300]);
+        assert_eq!(
+            message.into_bytes(),
+            [
+                0x10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, // 2: -1
+                0x1a, 0x02, b'a', b'b', // 3: "ab"
+                0x22, 0x03, 0x08, 0x96, 0x01, // 4: { 1: 150 }
+                0x2a, 0x03, 0x01, 0xac, 0x02, // 5: [1, 300]
+            ]
+        );
+    }
+}
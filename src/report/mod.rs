@@ -0,0 +1,472 @@
+use std::io::{self, prelude::*};
+use std::path::Path;
+use std::str::FromStr;
+
+use ahash::{AHashMap, AHashSet};
+use num_format::{Buffer, Locale};
+
+use crate::collapse::FoldedStacks;
+use crate::flamegraph::json;
+use crate::folded;
+
+/// The format a report is written in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[non_exhaustive]
+pub enum Format {
+    /// A table with aligned columns, for reading in a terminal.
+    ///
+    /// Counts have thousands separators, and the function names are in the last column so that
+    /// long names don't push the numbers out of line. When there is a baseline, the table has the
+    /// change of each percentage, in percentage points, instead of the baseline counts.
+    #[default]
+    Table,
+
+    /// Comma-separated values with a header row, for spreadsheets.
+    ///
+    /// The columns are `function`, `self`, `self_percent`, `total` and `total_percent`, followed
+    /// by the same four with a `baseline_` prefix when there is a baseline.
+    Csv,
+
+    /// A JSON object, for scripts.
+    ///
+    /// The object has the `total` count of the profile, the `baseline_total` when there is a
+    /// baseline, and the `functions` as objects with the same fields as the columns of
+    /// [`Format::Csv`], with the function in `name`.
+    Json,
+}
+
+impl Format {
+    /// The valid set of formats (via `FromStr`).
+    pub const VARIANTS: &'static [&'static str] = &["table", "csv", "json"];
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            unknown => Err(format!("unknown report format: {}", unknown)),
+        }
+    }
+}
+
+/// The count that functions are ranked by.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[non_exhaustive]
+pub enum SortBy {
+    /// The samples in which the function is the leaf, that is, the time spent in the function
+    /// itself.
+    #[default]
+    SelfCount,
+
+    /// The samples in which the function is anywhere on the stack, that is, the time spent in the
+    /// function and everything it calls.
+    Total,
+}
+
+impl SortBy {
+    /// The valid set of sort keys (via `FromStr`).
+    pub const VARIANTS: &'static [&'static str] = &["self", "total"];
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "self" => Ok(SortBy::SelfCount),
+            "total" => Ok(SortBy::Total),
+            unknown => Err(format!("unknown sort key: {}", unknown)),
+        }
+    }
+}
+
+/// Configure the report.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Options {
+    /// The format to write the report in.
+    ///
+    /// Defaults to a table.
+    pub format: Format,
+
+    /// The count to rank the functions by, from the highest down. Functions with the same count
+    /// are ordered by name.
+    ///
+    /// Defaults to the self count.
+    pub sort: SortBy,
+
+    /// Only report this many functions, those ranked highest.
+    pub top: Option<usize>,
+
+    /// Fail rather than log a warning when a line can't be parsed. See
+    /// [strict mode](crate::folded#strict-mode).
+    pub strict: bool,
+}
+
+/// Reads folded stack lines from `reader`, and writes a report of the self and total counts of
+/// every function in them to `writer`.
+///
+/// The input is in the [folded format](crate::folded). If lines have two counts, the first counts
+/// are the baseline that the second are compared against.
+///
+/// The self count of a function is the sum of the counts of the stacks it is the leaf of, and its
+/// total count is the sum of the counts of the stacks it is on. A function that is on a stack more
+/// than once, like a recursive one, counts that stack once towards its total, so that no total is
+/// ever more than the count of the whole profile. Percentages are of the count of the whole
+/// profile.
+pub fn from_reader<R, W>(opt: &Options, reader: R, writer: W) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut counts = Counts::default();
+    read_stacks(opt, reader, &mut counts, false)?;
+    counts.write(opt, writer)
+}
+
+/// Reads folded stack lines from the given files, or from STDIN if `files` is empty, and reports
+/// on them as [`from_reader`] does.
+///
+/// The stacks of all the files are added together. See [reading files](crate::folded#reading-files)
+/// for how the files are read.
+pub fn from_files<P, W>(opt: &Options, files: &[P], writer: W) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let mut counts = Counts::default();
+    read_files(opt, files, &mut counts, false)?;
+    counts.write(opt, writer)
+}
+
+/// Reports on the folded stacks in `files` as [`from_files`] does, compared against the folded
+/// stacks in the `baseline` files.
+///
+/// Lines with two counts count with their second, so that the output of [`crate::differential`]
+/// stands for the profile it was compared against.
+pub fn diff_files<P, W>(opt: &Options, baseline: &[P], files: &[P], writer: W) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let mut counts = Counts {
+        differential: true,
+        ..Default::default()
+    };
+    read_files(opt, baseline, &mut counts, true)?;
+    read_files(opt, files, &mut counts, false)?;
+    counts.write(opt, writer)
+}
+
+/// Reports on in-memory [`FoldedStacks`] as [`from_reader`] does.
+pub fn from_stacks<W: Write>(opt: &Options, stacks: &FoldedStacks, writer: W) -> io::Result<()> {
+    let mut counts = Counts::default();
+    for (ids, count) in stacks.stacks() {
+        counts.add(ids.iter().map(|&id| stacks.frame(id)), [0, count]);
+    }
+    counts.write(opt, writer)
+}
+
+/// The counts of the functions in a profile and, at index 0, in its baseline.
+#[derive(Default)]
+struct Counts {
+    functions: AHashMap<String, Function>,
+    total: [u64; 2],
+    differential: bool,
+}
+
+#[derive(Default)]
+struct Function {
+    self_count: [u64; 2],
+    total_count: [u64; 2],
+}
+
+impl Counts {
+    fn add<'a>(&mut self, frames: impl Iterator<Item = &'a str>, counts: [u64; 2]) {
+        let add = |sum: &mut [u64; 2]| {
+            sum[0] += counts[0];
+            sum[1] += counts[1];
+        };
+        add(&mut self.total);
+
+        let mut seen = AHashSet::new();
+        let mut leaf = None;
+        for frame in frames {
+            leaf = Some(frame);
+            // A function counts a stack once, however many times it is on it.
+            if seen.insert(frame) {
+                add(&mut self.function(frame).total_count);
+            }
+        }
+        if let Some(leaf) = leaf {
+            add(&mut self.function(leaf).self_count);
+        }
+    }
+
+    fn function(&mut self, name: &str) -> &mut Function {
+        if !self.functions.contains_key(name) {
+            self.functions.insert(name.to_string(), Function::default());
+        }
+        self.functions.get_mut(name).expect("was just inserted")
+    }
+
+    /// The functions in the order they are reported in.
+    fn ranked(&self, opt: &Options) -> Vec<(&str, &Function)> {
+        let mut functions: Vec<(&str, &Function)> = self
+            .functions
+            .iter()
+            .map(|(name, function)| (name.as_str(), function))
+            .collect();
+        let key = |function: &Function| match opt.sort {
+            SortBy::SelfCount => function.self_count[1],
+            SortBy::Total => function.total_count[1],
+        };
+        functions.sort_unstable_by(|(a_name, a), (b_name, b)| {
+            key(b).cmp(&key(a)).then_with(|| a_name.cmp(b_name))
+        });
+        if let Some(top) = opt.top {
+            functions.truncate(top);
+        }
+        functions
+    }
+
+    fn percent(&self, count: [u64; 2], i: usize) -> f64 {
+        if self.total[i] == 0 {
+            0.0
+        } else {
+            100.0 * count[i] as f64 / self.total[i] as f64
+        }
+    }
+
+    fn write<W: Write>(&self, opt: &Options, mut writer: W) -> io::Result<()> {
+        let functions = self.ranked(opt);
+        match opt.format {
+            Format::Table => self.write_table(&functions, &mut writer)?,
+            Format::Csv => self.write_csv(&functions, &mut writer)?,
+            Format::Json => self.write_json(&functions, &mut writer)?,
+        }
+        writer.flush()
+    }
+
+    fn write_table<W: Write>(
+        &self,
+        functions: &[(&str, &Function)],
+        writer: &mut W,
+    ) -> io::Result<()> {
+        let mut columns: Vec<(&str, Vec<String>)> = Vec::new();
+        let mut column = |header, cell: &dyn Fn(&Function) -> String| {
+            let cells = functions
+                .iter()
+                .map(|(_, function)| cell(function))
+                .collect();
+            columns.push((header, cells));
+        };
+        column("Self", &|f| thousands(f.self_count[1]));
+        column("Self%", &|f| {
+            format!("{:.2}%", self.percent(f.self_count, 1))
+        });
+        if self.differential {
+            column("ΔSelf%", &|f| {
+                let change = self.percent(f.self_count, 1) - self.percent(f.self_count, 0);
+                format!("{:+.2}", change)
+            });
+        }
+        column("Total", &|f| thousands(f.total_count[1]));
+        column("Total%", &|f| {
+            format!("{:.2}%", self.percent(f.total_count, 1))
+        });
+        if self.differential {
+            column("ΔTotal%", &|f| {
+                let change = self.percent(f.total_count, 1) - self.percent(f.total_count, 0);
+                format!("{:+.2}", change)
+            });
+        }
+
+        let widths: Vec<usize> = columns
+            .iter()
+            .map(|(header, cells)| {
+                cells
+                    .iter()
+                    .map(String::as_str)
+                    .chain(std::iter::once(*header))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        for (header, width) in columns.iter().map(|(header, _)| header).zip(&widths) {
+            write!(writer, "{:>width$}  ", header, width = width)?;
+        }
+        writeln!(writer, "Function")?;
+        for (row, (name, _)) in functions.iter().enumerate() {
+            for ((_, cells), width) in columns.iter().zip(&widths) {
+                write!(writer, "{:>width$}  ", cells[row], width = width)?;
+            }
+            writeln!(writer, "{}", name)?;
+        }
+        Ok(())
+    }
+
+    fn write_csv<W: Write>(
+        &self,
+        functions: &[(&str, &Function)],
+        writer: &mut W,
+    ) -> io::Result<()> {
+        write!(writer, "function,self,self_percent,total,total_percent")?;
+        if self.differential {
+            write!(
+                writer,
+                ",baseline_self,baseline_self_percent,baseline_total,baseline_total_percent"
+            )?;
+        }
+        writeln!(writer)?;
+        for (name, function) in functions {
+            write_csv_field(writer, name)?;
+            for &i in self.columns() {
+                write!(
+                    writer,
+                    ",{},{:.2},{},{:.2}",
+                    function.self_count[i],
+                    self.percent(function.self_count, i),
+                    function.total_count[i],
+                    self.percent(function.total_count, i),
+                )?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    fn write_json<W: Write>(
+        &self,
+        functions: &[(&str, &Function)],
+        writer: &mut W,
+    ) -> io::Result<()> {
+        write!(writer, "{{\"total\":{}", self.total[1])?;
+        if self.differential {
+            write!(writer, ",\"baseline_total\":{}", self.total[0])?;
+        }
+        writer.write_all(b",\"functions\":[")?;
+        for (n, (name, function)) in functions.iter().enumerate() {
+            if n != 0 {
+                writer.write_all(b",")?;
+            }
+            writer.write_all(b"{\"name\":")?;
+            json::write_str(writer, name)?;
+            for &i in self.columns() {
+                let prefix = if i == 0 { "baseline_" } else { "" };
+                write!(
+                    writer,
+                    ",\"{p}self\":{},\"{p}self_percent\":{:.2},\"{p}total\":{},\"{p}total_percent\":{:.2}",
+                    function.self_count[i],
+                    self.percent(function.self_count, i),
+                    function.total_count[i],
+                    self.percent(function.total_count, i),
+                    p = prefix,
+                )?;
+            }
+            writer.write_all(b"}")?;
+        }
+        writer.write_all(b"]}\n")
+    }
+
+    /// The indices of the counts to write, the profile's first and then the baseline's.
+    fn columns(&self) -> &'static [usize] {
+        if self.differential {
+            &[1, 0]
+        } else {
+            &[1]
+        }
+    }
+}
+
+fn thousands(count: u64) -> String {
+    let mut buffer = Buffer::default();
+    buffer.write_formatted(&count, &Locale::en);
+    buffer.as_str().to_string()
+}
+
+/// Writes `field`, quoted if it has characters that are special in CSV.
+fn write_csv_field<W: Write>(writer: &mut W, field: &str) -> io::Result<()> {
+    if field.contains([',', '"', '\n', '\r']) {
+        write!(writer, "\"{}\"", field.replace('"', "\"\""))
+    } else {
+        writer.write_all(field.as_bytes())
+    }
+}
+
+fn read_files<P: AsRef<Path>>(
+    opt: &Options,
+    files: &[P],
+    counts: &mut Counts,
+    baseline: bool,
+) -> io::Result<()> {
+    folded::read_files(files, |reader| read_stacks(opt, reader, counts, baseline))
+}
+
+// Adds the stacks in `reader` to `counts`, to those of the baseline if `baseline` is set, skipping
+// the lines that can't be parsed unless `opt.strict` is set.
+fn read_stacks<R>(opt: &Options, reader: R, counts: &mut Counts, baseline: bool) -> io::Result<()>
+where
+    R: BufRead,
+{
+    folded::read_lines(reader, opt.strict, |line| {
+        let line_counts = match line.second {
+            Some(second) if baseline => [second, 0],
+            None if baseline => [line.count, 0],
+            Some(second) => {
+                counts.differential = true;
+                [line.count, second]
+            }
+            None => [0, line.count],
+        };
+        counts.add(line.stack.split(';'), line_counts);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(opt: &Options, input: &str) -> String {
+        let mut result = Vec::new();
+        from_reader(opt, input.as_bytes(), &mut result).unwrap();
+        String::from_utf8(result).unwrap()
+    }
+
+    #[test]
+    fn counts_recursion_once() {
+        let opt = Options {
+            format: Format::Csv,
+            sort: SortBy::Total,
+            ..Default::default()
+        };
+        assert_eq!(
+            report(&opt, "main;f;g;f 3\nmain;f 1\n"),
+            "function,self,self_percent,total,total_percent\n\
+             f,4,100.00,4,100.00\n\
+             main,0,0.00,4,100.00\n\
+             g,0,0.00,3,75.00\n"
+        );
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        let opt = Options {
+            format: Format::Csv,
+            sort: SortBy::Total,
+            top: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            report(&opt, "f<a, \"b\"> 1\n"),
+            "function,self,self_percent,total,total_percent\n\
+             \"f<a, \"\"b\"\">\",1,100.00,1,100.00\n"
+        );
+    }
+}
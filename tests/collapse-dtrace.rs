@@ -4,7 +4,8 @@ use std::fs::File;
 use std::io::{self, BufReader, Cursor};
 use std::process::{Command, Stdio};
 
-use inferno::collapse::dtrace::{Folder, Options};
+use inferno::collapse::dtrace::{Folder, Options, TimeUnit};
+use inferno::collapse::Demangle;
 use log::Level;
 use pretty_assertions::assert_eq;
 use testing_logger::CapturedLog;
@@ -124,6 +125,145 @@ fn collapse_dtrace_rust_names() {
     test_collapse_dtrace(test_file, result_file, Options::default()).unwrap()
 }
 
+#[test]
+fn collapse_dtrace_mangled() {
+    let test_file = "./tests/data/collapse-dtrace/mangled.txt";
+    let result_file = "./tests/data/collapse-dtrace/results/mangled.txt";
+    test_collapse_dtrace(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_dtrace_mangled_demangle() {
+    let test_file = "./tests/data/collapse-dtrace/mangled.txt";
+    let result_file = "./tests/data/collapse-dtrace/results/mangled-demangle.txt";
+
+    let mut options = Options::default();
+    options.demangle = Some(Demangle::default());
+
+    test_collapse_dtrace(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_dtrace_mangled_demangle_with_offsets() {
+    let test_file = "./tests/data/collapse-dtrace/mangled.txt";
+    let result_file = "./tests/data/collapse-dtrace/results/mangled-demangle-offsets.txt";
+
+    let mut options = Options::default();
+    options.includeoffset = true;
+    options.demangle = Some(Demangle::default());
+
+    test_collapse_dtrace(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_dtrace_off_cpu() {
+    let test_file = "./tests/data/collapse-dtrace/off-cpu.txt";
+    let result_file = "./tests/data/collapse-dtrace/results/off-cpu.txt";
+    test_collapse_dtrace(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_dtrace_off_cpu_in_milliseconds() {
+    let test_file = "./tests/data/collapse-dtrace/off-cpu.txt";
+    let result_file = "./tests/data/collapse-dtrace/results/off-cpu-offcpu-ms.txt";
+
+    let mut options = Options::default();
+    options.aggregation = Some("offcpu".to_string());
+    options.duration_unit = Some(TimeUnit::Milliseconds);
+
+    test_collapse_dtrace(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_dtrace_off_cpu_in_microseconds() {
+    let test_file = "./tests/data/collapse-dtrace/off-cpu.txt";
+    let result_file = "./tests/data/collapse-dtrace/results/off-cpu-offcpu-us.txt";
+
+    let mut options = Options::default();
+    options.aggregation = Some("offcpu".to_string());
+    options.duration_unit = Some(TimeUnit::Microseconds);
+
+    test_collapse_dtrace(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_dtrace_on_cpu_aggregation() {
+    let test_file = "./tests/data/collapse-dtrace/off-cpu.txt";
+    let result_file = "./tests/data/collapse-dtrace/results/off-cpu-oncpu.txt";
+
+    let mut options = Options::default();
+    options.aggregation = Some("oncpu".to_string());
+
+    test_collapse_dtrace(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_dtrace_aggregation_with_sigil() {
+    let test_file = "./tests/data/collapse-dtrace/off-cpu.txt";
+    let result_file = "./tests/data/collapse-dtrace/results/off-cpu-oncpu.txt";
+
+    let mut options = Options::default();
+    options.aggregation = Some("@oncpu".to_string());
+
+    test_collapse_dtrace(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_dtrace_strict_should_return_error_for_missing_aggregation() {
+    let mut options = Options::default();
+    options.aggregation = Some("missing".to_string());
+    options.strict = true;
+    let error = common::test_collapse_error(
+        Folder::from(options),
+        "./tests/data/collapse-dtrace/off-cpu.txt",
+    );
+    assert!(matches!(
+        inferno::Error::from_io(error),
+        inferno::Error::EmptyInput
+    ));
+}
+
+#[test]
+fn collapse_dtrace_strict_should_return_error_for_weird_stack_lines() {
+    let mut options = Options::default();
+    options.strict = true;
+    let error = common::test_collapse_error(
+        Folder::from(options),
+        "./tests/data/collapse-dtrace/weird-stack-line.txt",
+    );
+    match inferno::Error::from_io(error) {
+        inferno::Error::Parse { line, text, .. } => {
+            assert_eq!(line, 6);
+            assert_eq!(text, "not a frame");
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn collapse_dtrace_should_warn_about_missing_aggregation() {
+    let mut options = Options::default();
+    options.aggregation = Some("missing".to_string());
+    test_collapse_dtrace_logs_with_options(
+        "./tests/data/collapse-dtrace/off-cpu.txt",
+        |captured_logs| {
+            let nwarnings = captured_logs
+                .iter()
+                .filter(|log| {
+                    log.body == "No aggregation named @missing found in input"
+                        && log.level == Level::Warn
+                })
+                .count();
+            assert_eq!(
+                nwarnings, 1,
+                "missing aggregation warning logged {} times, but should be logged exactly once",
+                nwarnings
+            );
+        },
+        options,
+    );
+}
+
 #[test]
 fn collapse_dtrace_cli() {
     let input_file = "./flamegraph/example-dtrace-stacks.txt";
@@ -150,3 +290,19 @@ fn collapse_dtrace_cli() {
     let expected = BufReader::new(File::open(expected_file).unwrap());
     common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
 }
+
+#[test]
+fn collapse_dtrace_cli_rejects_bare_aggregation_sigil() {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-collapse-dtrace"))
+        .args(["--aggregation", "@"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to execute process");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("`@` is not an aggregation name"),
+        "unexpected error: {}",
+        stderr
+    );
+}
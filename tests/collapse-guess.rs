@@ -4,7 +4,8 @@ use std::fs::File;
 use std::io::{self, BufReader, Cursor};
 use std::process::{Command, Stdio};
 
-use inferno::collapse::guess::Folder;
+use inferno::collapse::guess::{Folder, Options};
+use inferno::collapse::Collapse;
 use log::Level;
 use pretty_assertions::assert_eq;
 use testing_logger::CapturedLog;
@@ -98,6 +99,57 @@ fn collapse_guess_xctrace() {
     test_collapse_guess(test_file, result_file, false).unwrap()
 }
 
+#[test]
+fn collapse_guess_recursive() {
+    let test_file = "./tests/data/collapse-recursive/basic.txt";
+    let result_file = "./tests/data/collapse-recursive/results/basic-collapsed.txt";
+    test_collapse_guess(test_file, result_file, false).unwrap()
+}
+
+#[test]
+fn collapse_guess_should_rank_every_format() {
+    let input = std::fs::read_to_string("./tests/data/collapse-perf/go-stacks.txt").unwrap();
+    let candidates = Folder::default().rank(&input);
+    let mut formats: Vec<_> = candidates.iter().map(|c| c.format).collect();
+    assert_eq!(formats[0], "perf");
+    assert_eq!(candidates[0].confidence, 1.0);
+    assert!(candidates[1..].iter().all(|c| c.confidence == 0.0));
+    formats.sort_unstable();
+    assert_eq!(
+        formats,
+        [
+            "dtrace",
+            "ghcprof",
+            "perf",
+            "recursive",
+            "sample",
+            "vsprof",
+            "vtune",
+            "xctrace"
+        ]
+    );
+}
+
+#[test]
+fn collapse_guess_should_keep_candidates_of_last_input() {
+    let mut folder = Folder::default();
+    let input = File::open("./tests/data/collapse-sample/sample.txt").unwrap();
+    folder.collapse(BufReader::new(input), io::sink()).unwrap();
+    assert_eq!(folder.candidates()[0].format, "sample");
+    assert_eq!(folder.candidates().len(), 8);
+}
+
+#[test]
+fn collapse_guess_should_report_stats_of_chosen_collapser() {
+    let mut folder = Folder::default();
+    let input = File::open("./tests/data/collapse-perf/stats.txt").unwrap();
+    folder.collapse(BufReader::new(input), io::sink()).unwrap();
+    let stats = folder.stats().unwrap();
+    assert_eq!(stats.events, 4);
+    assert_eq!(stats.filtered_events, 1);
+    assert_eq!(stats.samples, 3);
+}
+
 #[test]
 fn collapse_guess_unknown_format_should_log_error() {
     test_collapse_guess_logs(
@@ -140,6 +192,34 @@ fn collapse_guess_invalid_perf_should_log_error() {
     );
 }
 
+#[test]
+fn collapse_guess_strict_should_return_error_for_unknown_format() {
+    let mut options = Options::default();
+    options.strict = true;
+    let error = common::test_collapse_error(
+        Folder::from(options),
+        "./tests/data/collapse-guess/invalid-perf-with-empty-line-after-event-line.txt",
+    );
+    assert!(matches!(
+        inferno::Error::from_io(error),
+        inferno::Error::UnknownFormat
+    ));
+}
+
+#[test]
+fn collapse_guess_cli_explain() {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-collapse-guess"))
+        .arg("--explain")
+        .arg("./tests/data/collapse-dtrace/java.txt")
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<_> = stderr.lines().collect();
+    assert_eq!(lines.len(), 8);
+    assert!(lines[0].starts_with("dtrace     1.00  accepted by its format check"));
+}
+
 #[test]
 fn collapse_guess_cli() {
     let input_file = "./tests/data/collapse-dtrace/java.txt";
@@ -5,7 +5,8 @@ use std::io::{self, BufReader, Cursor};
 use std::path::Path;
 use std::process::{Command, Stdio};
 
-use inferno::collapse::perf::{Folder, Options};
+use inferno::collapse::perf::{Folder, InlineFrames, Options, SrcLine};
+use inferno::collapse::{Collapse, CollapseStats, Demangle};
 use log::Level;
 use pretty_assertions::assert_eq;
 use testing_logger::CapturedLog;
@@ -58,6 +59,24 @@ fn options_from_vec(opt_vec: Vec<&str>) -> Options {
                 options.annotate_jit = true;
                 options.annotate_kernel = true;
             }
+            "srcline" => options.srcline = SrcLine::Annotate,
+            "srclineleaf" => options.srcline = SrcLine::Leaf,
+            "inline" => options.inline_frames = InlineFrames::Annotate,
+            "noinline" => options.inline_frames = InlineFrames::Drop,
+            "mergeinline" => options.inline_frames = InlineFrames::Merge,
+            "demangle" => options.demangle = Some(Demangle::default()),
+            "stripgenerics" => {
+                options
+                    .demangle
+                    .get_or_insert_with(Demangle::default)
+                    .strip_generics = true
+            }
+            "striphash" => {
+                options
+                    .demangle
+                    .get_or_insert_with(Demangle::default)
+                    .strip_rust_hash = true
+            }
             opt => panic!("invalid option: {}", opt),
         }
     }
@@ -233,7 +252,36 @@ collapse_perf_tests! {
     collapse_perf_go_stacks,
     collapse_perf_java_inline,
     collapse_perf_versioned_vmlinux__kernel,
-    collapse_perf_sourcepawn_jitdump__jit
+    collapse_perf_sourcepawn_jitdump__jit,
+    collapse_perf_srcline,
+    collapse_perf_srcline__srcline,
+    collapse_perf_srcline__srclineleaf,
+    collapse_perf_inline_frames,
+    collapse_perf_inline_frames__inline,
+    collapse_perf_inline_frames__noinline,
+    collapse_perf_inline_frames__mergeinline,
+    collapse_perf_inline_frames__inline_srcline,
+    collapse_perf_mangled,
+    collapse_perf_mangled__demangle,
+    collapse_perf_mangled__striphash,
+    collapse_perf_mangled__stripgenerics_striphash
+}
+
+#[test]
+fn collapse_perf_to_stacks() {
+    let test_file = "./tests/data/collapse-perf/java-inline.txt";
+    let expected_file = "./tests/data/collapse-perf/results/java-inline-collapsed.txt";
+    for &n in &[1, 2] {
+        let mut options = Options::default();
+        options.nthreads = n;
+        let reader = BufReader::new(File::open(test_file).unwrap());
+        let stacks = Folder::from(options).collapse_to_stacks(reader).unwrap();
+
+        let mut result = Vec::new();
+        stacks.write_to(&mut result).unwrap();
+        let expected = BufReader::new(File::open(expected_file).unwrap());
+        common::compare_results(Cursor::new(result), expected, expected_file, false);
+    }
 }
 
 #[test]
@@ -267,6 +315,41 @@ fn collapse_perf_should_warn_about_empty_input_lines() {
     );
 }
 
+#[test]
+fn collapse_perf_strict_should_return_error_for_weird_input_lines() {
+    let test_file = "./tests/data/collapse-perf/weird-stack-line.txt";
+    let mut options = Options::default();
+    options.strict = true;
+    let error = common::test_collapse_error(Folder::from(options), test_file);
+    match inferno::Error::from_io(error) {
+        inferno::Error::Parse {
+            path,
+            line,
+            text,
+            message,
+        } => {
+            assert_eq!(path.as_deref(), Some(Path::new(test_file)));
+            assert_eq!(line, 5);
+            assert_eq!(text, "\t          THIS_IS_A_WEIRD_LINE");
+            assert_eq!(message, "Weird stack line");
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn collapse_perf_strict_should_return_error_for_empty_input() {
+    let mut options = Options::default();
+    options.strict = true;
+    let error = Folder::from(options)
+        .collapse(&b""[..], io::sink())
+        .unwrap_err();
+    assert!(matches!(
+        inferno::Error::from_io(error),
+        inferno::Error::EmptyInput
+    ));
+}
+
 #[test]
 fn collapse_perf_should_warn_about_weird_input_lines() {
     test_collapse_perf_logs(
@@ -287,6 +370,114 @@ fn collapse_perf_should_warn_about_weird_input_lines() {
     );
 }
 
+#[test]
+fn collapse_perf_should_warn_about_moduleless_stack_lines() {
+    test_collapse_perf_logs(
+        "./tests/data/collapse-perf/moduleless-stack-line.txt",
+        |captured_logs| {
+            let nwarnings = captured_logs
+                .iter()
+                .filter(|log| {
+                    log.body.starts_with("Weird stack line: ") && log.level == Level::Warn
+                })
+                .count();
+            assert_eq!(
+                nwarnings, 2,
+                "only frames followed by an inlined source line may lack a module, \
+                 but {} warnings were logged",
+                nwarnings
+            );
+        },
+    );
+}
+
+#[test]
+fn collapse_perf_strict_should_return_error_for_moduleless_stack_lines() {
+    let test_file = "./tests/data/collapse-perf/moduleless-stack-line.txt";
+    let mut options = Options::default();
+    options.strict = true;
+    let error = common::test_collapse_error(Folder::from(options), test_file);
+    match inferno::Error::from_io(error) {
+        inferno::Error::Parse { line, text, .. } => {
+            assert_eq!(line, 2);
+            assert_eq!(text, "\t    55d0c6e5a13d square");
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn collapse_perf_merged_inline_frames_skip_after() {
+    let mut options = Options::default();
+    options.inline_frames = InlineFrames::Merge;
+    options.skip_after = vec!["compute".to_string()];
+    test_collapse_perf(
+        "./tests/data/collapse-perf/inline-frames.txt",
+        "./tests/data/collapse-perf/results/inline-frames-collapsed-mergeinline-skip-after.txt",
+        options,
+        false,
+    )
+    .unwrap();
+}
+
+#[test]
+fn collapse_perf_should_not_warn_about_srclines() {
+    test_collapse_perf_logs("./tests/data/collapse-perf/srcline.txt", |captured_logs| {
+        let nwarnings = captured_logs
+            .iter()
+            .filter(|log| log.level == Level::Warn)
+            .count();
+        assert_eq!(
+            nwarnings, 0,
+            "source lines should be parsed, but {} warnings were logged",
+            nwarnings
+        );
+    });
+}
+
+#[test]
+fn collapse_perf_should_count_stats() {
+    let test_file = "./tests/data/collapse-perf/stats.txt";
+    for &n in &[1, 2] {
+        let mut options = Options::default();
+        options.nthreads = n;
+        options.skip_after = vec!["main".to_string()];
+        let mut folder = Folder::from(options);
+        let reader = BufReader::new(File::open(test_file).unwrap());
+        folder.collapse(reader, io::sink()).unwrap();
+
+        let mut expected = CollapseStats::default();
+        expected.events = 4;
+        expected.filtered_events = 1;
+        expected.weird_lines = 1;
+        expected.truncated_stacks = 3;
+        expected.unique_stacks = 2;
+        expected.samples = 3;
+        assert_eq!(folder.stats(), Some(&expected), "with {} threads", n);
+    }
+}
+
+#[test]
+fn collapse_perf_cli_stats() {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-collapse-perf"))
+        .arg("--quiet")
+        .arg("--skip-after=main")
+        .arg("--stats=json")
+        .arg("./tests/data/collapse-perf/stats.txt")
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "main;bar 1\nmain;foo 2\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "{\"events\":4,\"filtered_events\":1,\"weird_lines\":1,\"truncated_stacks\":3,\
+         \"unique_stacks\":2,\"samples\":3}\n"
+    );
+}
+
 #[test]
 fn collapse_perf_cli() {
     let input_file = "./flamegraph/test/perf-vertx-stacks-01.txt";
@@ -5,6 +5,7 @@ use std::io::{self, BufReader, Cursor};
 use std::process::{Command, Stdio};
 
 use inferno::collapse::recursive::{Folder, Options};
+use inferno::collapse::Demangle;
 
 fn test_collapse_recursive(
     test_file: &str,
@@ -26,6 +27,61 @@ fn collapse_recursive_basic() {
     test_collapse_recursive(test_file, result_file, Options::default()).unwrap()
 }
 
+#[test]
+fn collapse_recursive_cycles() {
+    let test_file = "./tests/data/collapse-recursive/mutual.txt";
+    let result_file = "./tests/data/collapse-recursive/results/mutual-cycles-collapsed.txt";
+    let mut options = Options::default();
+    options.max_cycle_len = 2;
+    test_collapse_recursive(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_recursive_first_occurrence_only() {
+    let test_file = "./tests/data/collapse-recursive/mutual.txt";
+    let result_file =
+        "./tests/data/collapse-recursive/results/mutual-first-occurrence-collapsed.txt";
+    let mut options = Options::default();
+    options.first_occurrence_only = true;
+    test_collapse_recursive(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_recursive_demangles_before_folding() {
+    let test_file = "./tests/data/collapse-recursive/mangled.txt";
+    let result_file = "./tests/data/collapse-recursive/results/mangled-demangled.txt";
+    let mut options = Options::default();
+    options.demangle = Some(Demangle::default());
+    test_collapse_recursive(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_recursive_strict_should_return_error_for_missing_count() {
+    let mut options = Options::default();
+    options.strict = true;
+    let error = common::test_collapse_error(
+        Folder::from(options),
+        "./tests/data/collapse-recursive/missing-count.txt",
+    );
+    match inferno::Error::from_io(error) {
+        inferno::Error::Parse { line, text, .. } => {
+            assert_eq!(line, 2);
+            assert_eq!(text, "main;bar");
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn collapse_recursive_should_return_error_for_missing_count() {
+    let error = common::test_collapse_error(
+        Folder::default(),
+        "./tests/data/collapse-recursive/missing-count.txt",
+    );
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "Unable to parse folded line: main;bar");
+}
+
 #[test]
 fn collapse_recursive_cli() {
     let input_file = "./tests/data/collapse-recursive/basic.txt";
@@ -0,0 +1,69 @@
+#![cfg(feature = "regex")]
+
+mod common;
+
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::process::Command;
+
+use inferno::collapse::perf;
+use inferno::collapse::rewrite::{Folder, Rules};
+
+#[test]
+fn collapse_rewrite_perf_java() {
+    let rules = Rules::from_file("./tests/data/collapse-rewrite/java.rules").unwrap();
+    for &n in &[1, 2] {
+        let mut options = perf::Options::default();
+        options.nthreads = n;
+        common::test_collapse(
+            Folder::new(perf::Folder::from(options), rules.clone()),
+            "./tests/data/collapse-perf/java-inline.txt",
+            "./tests/data/collapse-rewrite/results/java-inline-rewritten.txt",
+            false,
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn collapse_rewrite_without_rules() {
+    common::test_collapse(
+        Folder::new(perf::Folder::default(), Rules::default()),
+        "./tests/data/collapse-perf/java-inline.txt",
+        "./tests/data/collapse-perf/results/java-inline-collapsed.txt",
+        false,
+    )
+    .unwrap();
+}
+
+#[test]
+fn collapse_rewrite_cli() {
+    let input_file = "./tests/data/collapse-perf/java-inline.txt";
+    let expected_file = "./tests/data/collapse-rewrite/results/java-inline-rewritten.txt";
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-collapse-perf"))
+        .arg("--rewrite-rules")
+        .arg("./tests/data/collapse-rewrite/java.rules")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}
+
+#[test]
+fn collapse_rewrite_cli_invalid_rules() {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-collapse-perf"))
+        .arg("--rewrite-rules")
+        .arg("./tests/data/collapse-perf/java-inline.txt")
+        .arg("./tests/data/collapse-perf/java-inline.txt")
+        .output()
+        .expect("failed to execute process");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Invalid rewrite rule (") && stderr.contains("line: 1"),
+        "unexpected error: {}",
+        stderr
+    );
+}
@@ -2,6 +2,7 @@ mod common;
 
 use std::fs::File;
 use std::io::{self, BufReader, Cursor};
+use std::path::Path;
 use std::process::{Command, Stdio};
 
 use inferno::collapse::sample::{Folder, Options};
@@ -96,7 +97,7 @@ fn collapse_sample_should_return_error_for_odd_number_of_indent_chars() {
     assert_eq!(error.kind(), io::ErrorKind::InvalidData);
     assert!(error
         .to_string()
-        .starts_with("Odd number of indentation characters for line"));
+        .starts_with("Odd number of indentation characters on line 22 of"));
 }
 
 #[test]
@@ -106,7 +107,29 @@ fn collapse_sample_should_return_error_for_skipped_indent_level() {
     assert_eq!(error.kind(), io::ErrorKind::InvalidData);
     assert!(error
         .to_string()
-        .starts_with("Skipped indentation level at line"));
+        .starts_with("Skipped indentation level on line 23 of"));
+    match inferno::Error::from_io(error) {
+        inferno::Error::Parse {
+            path, line, text, ..
+        } => {
+            assert_eq!(path.as_deref(), Some(Path::new(test_file)));
+            assert_eq!(line, 23);
+            assert_eq!(text, "    +     825 main  (in rg) + 41  [0x10384b549]");
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn collapse_sample_strict_should_return_error_for_ending_before_call_graph_start() {
+    let test_file = "./tests/data/collapse-sample/end-before-call-graph-start.txt";
+    let mut options = Options::default();
+    options.strict = true;
+    let error = test_collapse_sample_error(test_file, options);
+    assert!(matches!(
+        inferno::Error::from_io(error),
+        inferno::Error::EmptyInput
+    ));
 }
 
 #[test]
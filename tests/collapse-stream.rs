@@ -0,0 +1,80 @@
+mod common;
+
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::process::Command;
+
+use inferno::collapse::stream::{Folder, Options, OTHER_STACK};
+use inferno::collapse::{perf, Collapse};
+
+#[test]
+fn collapse_stream_to_stacks_matches_collapse() {
+    let test_file = "./tests/data/collapse-perf/go-stacks.txt";
+    let expected_file = "./tests/data/collapse-perf/results/go-stacks-collapsed.txt";
+
+    let mut folder = Folder::new(perf::Folder::default(), Options::default());
+    let reader = BufReader::new(File::open(test_file).unwrap());
+    let stacks = folder.collapse_to_stacks(reader).unwrap();
+
+    let mut result = Vec::new();
+    stacks.write_to(&mut result).unwrap();
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(result), expected, expected_file, false);
+}
+
+#[test]
+fn collapse_stream_evicts_into_other() {
+    let test_file = "./tests/data/collapse-perf/java-inline.txt";
+
+    let mut options = Options::default();
+    options.max_stacks = Some(5);
+    let mut folder = Folder::new(perf::Folder::default(), options);
+    let reader = BufReader::new(File::open(test_file).unwrap());
+    let capped = folder.collapse_to_stacks(reader).unwrap();
+
+    let reader = BufReader::new(File::open(test_file).unwrap());
+    let all = perf::Folder::default().collapse_to_stacks(reader).unwrap();
+
+    assert!(capped.len() <= 5);
+    assert!(capped.len() < all.len());
+    assert_eq!(capped.total_count(), all.total_count());
+    assert!(capped
+        .stacks()
+        .any(|(frames, _)| capped.join(frames) == OTHER_STACK));
+}
+
+#[test]
+fn collapse_stream_cli() {
+    let input_file = "./tests/data/collapse-perf/cpp-stacks-std-function.txt";
+    let expected_file =
+        "./tests/data/collapse-stream/results/cpp-stacks-std-function-flush-stacks-10.txt";
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-collapse-perf"))
+        .arg("--flush-stacks")
+        .arg("10")
+        .arg("--max-stacks")
+        .arg("4")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}
+
+#[test]
+fn collapse_stream_cli_cumulative() {
+    let input_file = "./tests/data/collapse-dtrace/rust-names.txt";
+    let expected_file = "./tests/data/collapse-stream/results/rust-names-cumulative.txt";
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-collapse-dtrace"))
+        .arg("--flush-stacks")
+        .arg("2")
+        .arg("--cumulative")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}
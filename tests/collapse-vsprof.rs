@@ -4,7 +4,8 @@ use std::fs::File;
 use std::io::{self, BufReader, Cursor};
 use std::process::{Command, Stdio};
 
-use inferno::collapse::vsprof::Folder;
+use inferno::collapse::vsprof::{Folder, Options};
+use inferno::collapse::Demangle;
 use log::Level;
 use pretty_assertions::assert_eq;
 use testing_logger::CapturedLog;
@@ -31,6 +32,15 @@ fn collapse_vsprof_default() {
     test_collapse_vsprof(test_file, result_file).unwrap()
 }
 
+#[test]
+fn collapse_vsprof_demangle() {
+    let test_file = "./tests/data/collapse-vsprof/mangled.csv";
+    let result_file = "./tests/data/collapse-vsprof/results/mangled-demangled.txt";
+    let mut options = Options::default();
+    options.demangle = Some(Demangle::default());
+    common::test_collapse(Folder::from(options), test_file, result_file, false).unwrap()
+}
+
 #[test]
 fn collapse_vsprof_should_log_warning_for_ending_before_call_graph_start() {
     test_collapse_vsprof_logs(
@@ -59,6 +69,10 @@ fn collapse_vsprof_should_return_error_for_incorrect_header() {
     assert!(error
         .to_string()
         .starts_with("Expected first line to be header line"));
+    assert!(matches!(
+        inferno::Error::from_io(error),
+        inferno::Error::Parse { line: 1, .. }
+    ));
 }
 
 #[test]
@@ -68,7 +82,7 @@ fn collapse_vsprof_should_return_error_for_missing_function_name() {
     assert_eq!(error.kind(), io::ErrorKind::InvalidData);
     assert!(error
         .to_string()
-        .starts_with("Missing function name in line:"));
+        .starts_with("Missing function name on line 2 of"));
 }
 
 #[test]
@@ -78,7 +92,7 @@ fn collapse_vsprof_should_return_error_for_invalid_function_name() {
     assert_eq!(error.kind(), io::ErrorKind::InvalidData);
     assert!(error
         .to_string()
-        .starts_with("Unable to parse function name from line:"));
+        .starts_with("Unable to parse function name on line"));
 }
 
 #[test]
@@ -101,6 +115,18 @@ fn collapse_vsprof_should_return_error_for_invalid_number_of_calls() {
         .starts_with("Unable to parse integer from"));
 }
 
+#[test]
+fn collapse_vsprof_strict_should_return_error_for_empty_file() {
+    let test_file = "./tests/data/collapse-vsprof/empty-file.csv";
+    let mut options = Options::default();
+    options.strict = true;
+    let error = common::test_collapse_error(Folder::from(options), test_file);
+    assert!(matches!(
+        inferno::Error::from_io(error),
+        inferno::Error::EmptyInput
+    ));
+}
+
 #[test]
 fn collapse_vsprof_cli() {
     let input_file = "./tests/data/collapse-vsprof/CallTreeSummary.csv";
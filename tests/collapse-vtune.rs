@@ -5,6 +5,7 @@ use std::io::{self, BufReader, Cursor};
 use std::process::{Command, Stdio};
 
 use inferno::collapse::vtune::{Folder, Options};
+use inferno::collapse::Demangle;
 use log::Level;
 use pretty_assertions::assert_eq;
 use testing_logger::CapturedLog;
@@ -49,6 +50,17 @@ fn collapse_vtune_no_modules() {
     test_collapse_vtune(test_file, result_file, options).unwrap()
 }
 
+#[test]
+fn collapse_vtune_demangle() {
+    let test_file = "./tests/data/collapse-vtune/mangled.csv";
+    let result_file = "./tests/data/collapse-vtune/results/mangled-demangled.txt";
+
+    let mut options = Options::default();
+    options.demangle = Some(Demangle::default());
+
+    test_collapse_vtune(test_file, result_file, options).unwrap()
+}
+
 #[test]
 fn collapse_vtune_should_log_warning_for_ending_before_header() {
     test_collapse_vtune_logs(
@@ -74,7 +86,19 @@ fn collapse_vtune_should_return_error_for_skipped_indent_level() {
     assert_eq!(error.kind(), io::ErrorKind::InvalidData);
     assert!(error
         .to_string()
-        .starts_with("Skipped indentation level at line"));
+        .starts_with("Skipped indentation level on line 7 of"));
+}
+
+#[test]
+fn collapse_vtune_strict_should_return_error_for_ending_before_header() {
+    let test_file = "./tests/data/collapse-vtune/end-before-header.csv";
+    let mut options = Options::default();
+    options.strict = true;
+    let error = test_collapse_vtune_error(test_file, options);
+    assert!(matches!(
+        inferno::Error::from_io(error),
+        inferno::Error::EmptyInput
+    ));
 }
 
 #[test]
@@ -4,7 +4,8 @@ use std::fs::File;
 use std::io::{self, BufReader, Cursor};
 use std::process::{Command, Stdio};
 
-use inferno::collapse::xctrace::Folder;
+use inferno::collapse::xctrace::{Folder, Options};
+use inferno::collapse::Demangle;
 
 fn test_collapse_xctrace(test_file: &str, expected_file: &str) -> io::Result<()> {
     common::test_collapse(Folder::default(), test_file, expected_file, false)?;
@@ -26,6 +27,50 @@ fn collapse_xctrace_simple_frame_without_binary_info() {
     test_collapse_xctrace(test_file, result_file).unwrap()
 }
 
+#[test]
+fn collapse_xctrace_demangle() {
+    let test_file = "./tests/data/collapse-xctrace/mangled.xml";
+    let result_file = "./tests/data/collapse-xctrace/results/mangled-demangled.folded";
+    let mut options = Options::default();
+    options.demangle = Some(Demangle::default());
+    common::test_collapse(Folder::from(options), test_file, result_file, false).unwrap()
+}
+
+#[test]
+fn collapse_xctrace_should_return_error_with_line_for_invalid_ref() {
+    let error = common::test_collapse_error(
+        Folder::default(),
+        "./tests/data/collapse-xctrace/invalid-frame-ref.xml",
+    );
+    match inferno::Error::from_io(error) {
+        inferno::Error::Parse {
+            line,
+            text,
+            message,
+            ..
+        } => {
+            assert_eq!(line, 5);
+            assert_eq!(text, r#"<frame ref="9"/>"#);
+            assert_eq!(message, "Invalid frame ref id: 9");
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn collapse_xctrace_strict_should_return_error_for_no_samples() {
+    let mut options = Options::default();
+    options.strict = true;
+    let error = common::test_collapse_error(
+        Folder::from(options),
+        "./tests/data/collapse-xctrace/no-samples.xml",
+    );
+    assert!(matches!(
+        inferno::Error::from_io(error),
+        inferno::Error::EmptyInput
+    ));
+}
+
 #[test]
 fn collapse_xctrace_cli() {
     let input_file = "./tests/data/collapse-xctrace/basic.xml";
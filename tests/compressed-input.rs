@@ -0,0 +1,102 @@
+mod common;
+
+#[cfg(any(feature = "gzip", feature = "zstd", feature = "xz"))]
+use std::fs::File;
+#[cfg(any(feature = "gzip", feature = "zstd", feature = "xz"))]
+use std::io::{BufReader, Cursor};
+#[cfg(feature = "gzip")]
+use std::path::PathBuf;
+
+use inferno::collapse::{perf, Collapse};
+#[cfg(feature = "gzip")]
+use inferno::{differential, flamegraph};
+
+#[cfg(any(feature = "gzip", feature = "zstd", feature = "xz"))]
+fn test_collapse_compressed(input_file: &str) {
+    let expected_file = "./tests/data/collapse-perf/results/java-inline-collapsed.txt";
+
+    let mut result = Cursor::new(Vec::new());
+    perf::Folder::default()
+        .collapse_file(Some(input_file), &mut result)
+        .unwrap();
+    result.set_position(0);
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(result, expected, expected_file, false);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn collapse_gzip_input() {
+    test_collapse_compressed("./tests/data/compressed/java-inline.txt.gz");
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn collapse_zstd_input() {
+    test_collapse_compressed("./tests/data/compressed/java-inline.txt.zst");
+}
+
+#[cfg(feature = "xz")]
+#[test]
+fn collapse_xz_input() {
+    test_collapse_compressed("./tests/data/compressed/java-inline.txt.xz");
+}
+
+#[cfg(not(feature = "zstd"))]
+#[test]
+fn collapse_zstd_input_without_feature() {
+    let err = perf::Folder::default()
+        .collapse_file(
+            Some("./tests/data/compressed/java-inline.txt.zst"),
+            std::io::sink(),
+        )
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("`zstd` feature"),
+        "unexpected error: {}",
+        err
+    );
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn flamegraph_gzip_input() {
+    let plain = "./tests/data/collapse-perf/results/java-inline-collapsed.txt";
+    let compressed = "./tests/data/compressed/java-inline-collapsed.txt.gz";
+
+    let render = |files: &[&str]| {
+        let files: Vec<_> = files.iter().map(PathBuf::from).collect();
+        let mut options = flamegraph::Options::default();
+        // Pick colors deterministically, so that the two graphs can be compared.
+        options.hash = true;
+        let mut svg = Vec::new();
+        flamegraph::from_files(&mut options, &files, &mut svg).unwrap();
+        String::from_utf8(svg).unwrap()
+    };
+
+    assert_eq!(render(&[compressed]), render(&[plain]));
+    assert_eq!(render(&[compressed, compressed]), render(&[plain, plain]));
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn differential_gzip_input() {
+    let plain = "./tests/data/collapse-perf/results/java-inline-collapsed.txt";
+    let compressed = "./tests/data/compressed/java-inline-collapsed.txt.gz";
+
+    let diff = |before: &str, after: &str| {
+        let mut out = Vec::new();
+        differential::from_files(differential::Options::default(), before, after, &mut out)
+            .unwrap();
+        // The stacks are written in no particular order.
+        let mut lines: Vec<_> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        lines.sort();
+        lines
+    };
+
+    assert_eq!(diff(compressed, plain), diff(plain, plain));
+}
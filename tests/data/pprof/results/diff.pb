@@ -0,0 +1,8 @@
+
+
+
+
+
+ 	
+""""""""****2 2nanoseconds2
+cpu_before2	cpu_after2eval2main2lex2parsep
\ No newline at end of file
@@ -0,0 +1,5 @@
+
+
+	
+
+""""""""****2 2count2samples2eval2main2lex2parsep
\ No newline at end of file
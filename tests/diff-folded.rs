@@ -2,6 +2,7 @@ mod common;
 
 use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader, Cursor};
+use std::path::Path;
 use std::process::Command;
 
 use inferno::differential::{self, Options};
@@ -67,10 +68,8 @@ where
     result_lines.sort_unstable();
     expected_lines.sort_unstable();
 
-    for (line_num, (result_line, expected_line)) in result_lines
-        .into_iter()
-        .zip(expected_lines.into_iter())
-        .enumerate()
+    for (line_num, (result_line, expected_line)) in
+        result_lines.into_iter().zip(expected_lines).enumerate()
     {
         assert_eq!(
             result_line, expected_line,
@@ -168,6 +167,29 @@ fn diff_folded_should_log_warning_on_bad_input_line() {
     );
 }
 
+#[test]
+fn diff_folded_strict_should_return_error_for_bad_input_line() {
+    let infile1 = "./tests/data/diff-folded/bad_before.txt";
+    let opt = Options {
+        strict: true,
+        ..Default::default()
+    };
+    let error = differential::from_files(
+        opt,
+        infile1,
+        "./tests/data/diff-folded/after.txt",
+        io::sink(),
+    )
+    .expect_err("Expected an error");
+    match inferno::Error::from_io(error) {
+        inferno::Error::Parse { path, line, .. } => {
+            assert_eq!(path.as_deref(), Some(Path::new(infile1)));
+            assert_eq!(line, 3);
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
 #[test]
 fn diff_folded_should_log_warning_about_fractional_samples() {
     test_diff_folded_logs(
@@ -6,6 +6,7 @@ use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str::FromStr;
 
+use inferno::collapse::FoldedStacks;
 use inferno::flamegraph::color::{BackgroundColor, PaletteMap};
 use inferno::flamegraph::{self, Direction, Options, Palette, TextTruncateDirection};
 use log::Level;
@@ -70,6 +71,23 @@ fn test_flamegraph_multiple_files(
     Ok(())
 }
 
+fn test_flamegraph_from_stacks(
+    input_file: &str,
+    expected_result_file: &str,
+    mut options: Options<'_>,
+) {
+    options.pretty_xml = true;
+    options.no_javascript = true;
+
+    let stacks =
+        FoldedStacks::from_reader(BufReader::new(File::open(input_file).unwrap())).unwrap();
+    let mut result = Cursor::new(Vec::new());
+    flamegraph::from_stacks(&mut options, &stacks, &mut result).unwrap();
+    result.set_position(0);
+    let expected = BufReader::new(File::open(expected_result_file).unwrap());
+    compare_results(result, expected, expected_result_file);
+}
+
 fn compare_results<R, E>(result: R, mut expected: E, expected_file: &str)
 where
     R: BufRead,
@@ -435,6 +453,41 @@ fn flamegraph_should_warn_about_bad_input_lines() {
     );
 }
 
+#[test]
+fn flamegraph_strict_should_return_error_for_bad_input_lines() {
+    let input_file = PathBuf::from("./tests/data/flamegraph/bad-lines/bad-lines.txt");
+    let mut options = Options::default();
+    options.strict = true;
+    let error = flamegraph::from_files(&mut options, std::slice::from_ref(&input_file), io::sink())
+        .expect_err("Expected an error");
+    match inferno::Error::from_io(error) {
+        inferno::Error::Parse {
+            path, line, text, ..
+        } => {
+            assert_eq!(path, Some(input_file));
+            assert_eq!(line, 3);
+            assert_eq!(text, "THIS IS A BAD LINE");
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn flamegraph_strict_should_return_error_for_empty_input() {
+    let mut options = Options::default();
+    options.strict = true;
+    let error = flamegraph::from_files(
+        &mut options,
+        &[PathBuf::from("./tests/data/flamegraph/empty/empty.txt")],
+        io::sink(),
+    )
+    .expect_err("Expected an error");
+    assert!(matches!(
+        inferno::Error::from_io(error),
+        inferno::Error::EmptyInput
+    ));
+}
+
 #[test]
 fn flamegraph_should_warn_about_empty_input() {
     test_flamegraph_logs("./tests/data/flamegraph/empty/empty.txt", |captured_logs| {
@@ -766,6 +819,33 @@ fn flamegraph_unsorted_input_file() {
     test_flamegraph(input_file, expected_result_file, options).unwrap();
 }
 
+#[test]
+fn flamegraph_from_stacks() {
+    let input_file =
+        "./tests/data/flamegraph/unsorted-input/perf-vertx-stacks-01-collapsed-all-unsorted.txt";
+    let expected_result_file =
+        "./tests/data/flamegraph/perf-vertx-stacks/perf-vertx-stacks-01-collapsed-all.svg";
+
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+
+    test_flamegraph_from_stacks(input_file, expected_result_file, options);
+}
+
+#[test]
+fn flamegraph_from_stacks_reversed_stack_ordering() {
+    let input_file =
+        "./tests/data/flamegraph/unsorted-input/perf-vertx-stacks-01-collapsed-all-unsorted.txt";
+    let expected_result_file =
+        "./tests/data/flamegraph/perf-vertx-stacks/perf-vertx-stacks-01-collapsed-all-reversed-stacks.svg";
+
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    options.reverse_stack_order = true;
+
+    test_flamegraph_from_stacks(input_file, expected_result_file, options);
+}
+
 #[test]
 fn flamegraph_no_sort_should_return_error_on_unsorted_input() {
     let input_file =
@@ -954,6 +1034,106 @@ fn flamegraph_base_symbol() {
     test_flamegraph(input_file, expected_result_file, opts).unwrap();
 }
 
+#[test]
+fn flamegraph_from_stacks_base_symbol() {
+    let input_file = "./tests/data/flamegraph/base/flames.txt";
+    let expected_result_file = "./tests/data/flamegraph/base/single-base.svg";
+
+    let mut opts = flamegraph::Options::default();
+    opts.title = flamegraph::defaults::CHART_TITLE.to_owned();
+    opts.base = vec!["Final".to_string()];
+
+    test_flamegraph_from_stacks(input_file, expected_result_file, opts);
+}
+
+#[test]
+fn flamegraph_sandwich() {
+    let input_file = "./tests/data/flamegraph/sandwich/memcpy.txt";
+    let expected_result_file = "./tests/data/flamegraph/sandwich/memcpy.svg";
+
+    let mut opts = flamegraph::Options::default();
+    opts.hash = true;
+    opts.sandwich = Some("memcpy".to_string());
+
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_from_stacks_sandwich() {
+    let input_file = "./tests/data/flamegraph/sandwich/memcpy.txt";
+    let expected_result_file = "./tests/data/flamegraph/sandwich/memcpy.svg";
+
+    let mut opts = flamegraph::Options::default();
+    opts.hash = true;
+    opts.sandwich = Some("memcpy".to_string());
+
+    test_flamegraph_from_stacks(input_file, expected_result_file, opts);
+}
+
+#[test]
+fn flamegraph_sandwich_differential_recursive() {
+    let input_file =
+        "./tests/data/flamegraph/differential/perf-cycles-instructions-01-collapsed-all-diff.txt";
+    let expected_result_file = "./tests/data/flamegraph/sandwich/diff-recursive.svg";
+
+    let mut opts = flamegraph::Options::default();
+    opts.hash = true;
+    opts.min_width = 1.0;
+    opts.sandwich = Some("cksum".to_string());
+    // The direction is ignored.
+    opts.direction = flamegraph::Direction::Inverted;
+
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_sandwich_is_svg_only() {
+    let mut opts = flamegraph::Options::default();
+    opts.sandwich = Some("memcpy".to_string());
+    opts.format = flamegraph::Format::Json;
+
+    let error = flamegraph::from_lines(&mut opts, ["main;memcpy 1"], io::sink()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn flamegraph_combined() {
+    let input_file = "./tests/data/flamegraph/sandwich/memcpy.txt";
+    let expected_result_file = "./tests/data/flamegraph/combined/memcpy.svg";
+
+    let mut opts = flamegraph::Options::default();
+    opts.hash = true;
+    opts.combined = true;
+
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_from_stacks_combined() {
+    let input_file = "./tests/data/flamegraph/sandwich/memcpy.txt";
+    let expected_result_file = "./tests/data/flamegraph/combined/memcpy.svg";
+
+    let mut opts = flamegraph::Options::default();
+    opts.hash = true;
+    opts.combined = true;
+
+    test_flamegraph_from_stacks(input_file, expected_result_file, opts);
+}
+
+#[test]
+fn flamegraph_combined_differential() {
+    let input_file =
+        "./tests/data/flamegraph/differential/perf-cycles-instructions-01-collapsed-all-diff.txt";
+    let expected_result_file = "./tests/data/flamegraph/combined/diff.svg";
+
+    let mut opts = flamegraph::Options::default();
+    opts.hash = true;
+    opts.min_width = 1.0;
+    opts.combined = true;
+
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
 #[test]
 fn flamegraph_multiple_base_symbol() {
     let input_file = "./tests/data/flamegraph/base/flames.txt";
@@ -974,6 +1154,189 @@ fn flamegraph_austin() {
     test_flamegraph(input_file, expected_result_file, opts).unwrap();
 }
 
+#[test]
+fn flamegraph_speedscope() {
+    let input_file = "./tests/data/flamegraph/grey-frames/grey-frames.txt";
+    let expected_result_file = "./tests/data/flamegraph/speedscope/grey-frames.json";
+    let mut opts = flamegraph::Options::default();
+    opts.format = flamegraph::Format::Speedscope;
+    opts.count_name = "ms".to_string();
+    opts.factor = 0.5;
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_speedscope_flamechart() {
+    let input_file = "./tests/data/flamegraph/speedscope/chart.txt";
+    let expected_result_file = "./tests/data/flamegraph/speedscope/chart.json";
+    let mut opts = flamegraph::Options::default();
+    opts.format = flamegraph::Format::Speedscope;
+    opts.title = flamegraph::defaults::CHART_TITLE.to_owned();
+    opts.flame_chart = true;
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_ansi() {
+    let input_file = "./tests/data/flamegraph/narrow-blocks/narrow-blocks.txt";
+    let expected_result_file = "./tests/data/flamegraph/ansi/narrow-blocks.txt";
+    let mut opts = flamegraph::Options::default();
+    opts.format = flamegraph::Format::Ansi;
+    opts.terminal_width = Some(60);
+    opts.hash = true;
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_ansi_inverted_differential() {
+    let input_file =
+        "./tests/data/flamegraph/differential/perf-cycles-instructions-01-collapsed-all-diff.txt";
+    let expected_result_file = "./tests/data/flamegraph/ansi/diff-inverted.txt";
+    let mut opts = flamegraph::Options::default();
+    opts.format = flamegraph::Format::Ansi;
+    opts.terminal_width = Some(100);
+    opts.direction = Direction::Inverted;
+    opts.min_width = 5.0;
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_html() {
+    let input_file = "./tests/data/flamegraph/narrow-blocks/narrow-blocks.txt";
+    let mut opts = flamegraph::Options::default();
+    opts.format = flamegraph::Format::Html;
+    let mut html = Vec::new();
+    flamegraph::from_files(&mut opts, &[PathBuf::from(input_file)], &mut html).unwrap();
+    let html = String::from_utf8(html).unwrap();
+
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(
+        !html.contains("<?xml"),
+        "the SVG is inlined without its XML declaration"
+    );
+    assert!(html.contains("<svg version=\"1.1\""));
+    assert!(html.contains("function search(term)"));
+    assert!(
+        !html.contains(" src=") && !html.contains("<link"),
+        "the page must not load anything"
+    );
+    assert!(html.contains(r#"{"name":"noploop","self":0,"total":276000,"callers":[],"#));
+}
+
+#[test]
+fn flamegraph_json_differential() {
+    let input_file =
+        "./tests/data/flamegraph/differential/perf-cycles-instructions-01-collapsed-all-diff.txt";
+    let expected_result_file = "./tests/data/flamegraph/json/diff.json";
+    let mut opts = flamegraph::Options::default();
+    opts.format = flamegraph::Format::Json;
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_json_reversed_min_width() {
+    let input_file = "./tests/data/flamegraph/narrow-blocks/narrow-blocks.txt";
+    let expected_result_file = "./tests/data/flamegraph/json/narrow-blocks-reversed.json";
+    let mut opts = flamegraph::Options::default();
+    opts.format = flamegraph::Format::Json;
+    opts.reverse_stack_order = true;
+    opts.min_width = 1.0;
+    opts.factor = 0.001;
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+#[cfg(feature = "png")]
+fn flamegraph_png() {
+    let input_file = "./tests/data/flamegraph/colors/async-profiler-collapsed-part.txt";
+    let mut svg = Vec::new();
+    let mut opts = flamegraph::Options::default();
+    flamegraph::from_files(&mut opts, &[PathBuf::from(input_file)], &mut svg).unwrap();
+    let svg = String::from_utf8(svg).unwrap();
+    let height: u32 = svg
+        .split("height=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let mut png = Vec::new();
+    let mut opts = flamegraph::Options::default();
+    opts.format = flamegraph::Format::Png;
+    opts.png_scale = 2.0;
+    flamegraph::from_files(&mut opts, &[PathBuf::from(input_file)], &mut png).unwrap();
+    let decoder = png::Decoder::new(png.as_slice());
+    let reader = decoder.read_info().unwrap();
+    let info = reader.info();
+    assert_eq!(info.width, 2 * 1200);
+    assert_eq!(info.height, 2 * height);
+}
+
+#[test]
+#[cfg(feature = "png")]
+fn flamegraph_png_should_reject_invalid_scale() {
+    for scale in [0.0, -1.0, f64::NAN, f64::INFINITY, 100000.0] {
+        let mut opts = flamegraph::Options::default();
+        opts.format = flamegraph::Format::Png;
+        opts.png_scale = scale;
+        let error = flamegraph::from_lines(&mut opts, ["main;foo 1"], io::sink()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+#[cfg(feature = "png")]
+fn flamegraph_png_should_reject_huge_images() {
+    let mut opts = flamegraph::Options::default();
+    opts.format = flamegraph::Format::Png;
+    opts.image_width = Some(1 << 20);
+    opts.png_scale = flamegraph::MAX_PNG_SCALE;
+    let error = flamegraph::from_lines(&mut opts, ["main;foo 1"], io::sink()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn flamegraph_cli_should_reject_invalid_png_scale() {
+    for scale in ["0", "-2", "NaN", "inf", "100000"] {
+        let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-flamegraph"))
+            .arg(format!("--png-scale={}", scale))
+            .arg("./tests/data/flamegraph/multiple-inputs/perf-vertx-stacks-01-collapsed-all-unsorted-1.txt")
+            .output()
+            .expect("failed to execute process");
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Expected a positive number"), "{}", stderr);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "png"))]
+fn flamegraph_png_requires_feature() {
+    let mut opts = flamegraph::Options::default();
+    opts.format = flamegraph::Format::Png;
+    let error = flamegraph::from_lines(&mut opts, ["main;foo 1"], io::sink()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn flamegraph_sandwich_should_skip_lines_without_counts() {
+    // The last character of the invalid line is multibyte, and used to be sliced in half.
+    let lines = ["main;caf\u{e9}", "main;foo 3"];
+    let mut opts = flamegraph::Options::default();
+    opts.sandwich = Some("main".to_string());
+    flamegraph::from_lines(&mut opts, lines, io::sink()).unwrap();
+}
+
+#[test]
+fn flamegraph_combined_should_skip_lines_without_counts() {
+    // The last character of the invalid line is multibyte, and used to be sliced in half.
+    let lines = ["main;caf\u{e9}", "main;foo 3"];
+    let mut opts = flamegraph::Options::default();
+    opts.combined = true;
+    flamegraph::from_lines(&mut opts, lines, io::sink()).unwrap();
+}
+
 // Regression test for https://github.com/jonhoo/inferno/issues/170
 // Sample counts large enough that `100 * samples` overflowed the integer
 // type used to compute the percentage caused a panic.
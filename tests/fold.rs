@@ -0,0 +1,215 @@
+#![cfg(feature = "regex")]
+
+use std::fs;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use inferno::fold::merge::{self, Input};
+use inferno::fold::{self, Options};
+use pretty_assertions::assert_eq;
+use regex::Regex;
+
+#[test]
+fn fold_should_merge_files() {
+    let mut options = Options::default();
+    options.drop_frames = Some(Regex::new(r"^\[unknown\]$").unwrap());
+    options.reroot = Some(Regex::new("^main$").unwrap());
+    options.max_depth = Some(2);
+
+    let mut result = Vec::new();
+    fold::from_files(
+        &options,
+        &[
+            "./tests/data/fold/stacks.txt",
+            "./tests/data/fold/more-stacks.txt",
+        ],
+        &mut result,
+    )
+    .unwrap();
+    let expected = fs::read_to_string("./tests/data/fold/results/merged.txt").unwrap();
+    assert_eq!(String::from_utf8(result).unwrap(), expected);
+}
+
+#[test]
+fn fold_strict_should_return_error_with_path() {
+    let mut options = Options::default();
+    options.strict = true;
+    let error = fold::from_files(
+        &options,
+        &["./tests/data/flamegraph/bad-lines/bad-lines.txt"],
+        std::io::sink(),
+    )
+    .unwrap_err();
+    match inferno::Error::from_io(error) {
+        inferno::Error::Parse { path, .. } => assert_eq!(
+            path.as_deref(),
+            Some(std::path::Path::new(
+                "./tests/data/flamegraph/bad-lines/bad-lines.txt"
+            ))
+        ),
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn fold_cli() {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-fold"))
+        .args(["--keep", "eval", "--top", "2", "--scale", "2"])
+        .arg("./tests/data/fold/stacks.txt")
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    let expected = fs::read_to_string("./tests/data/fold/results/top.txt").unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn fold_cli_should_read_stdin_for_dash_among_files() {
+    let mut child = Command::new(assert_cmd::cargo::cargo_bin!("inferno-fold"))
+        .args(["--drop-frames", r"^\[unknown\]$", "--reroot", "^main$"])
+        .args(["--max-depth", "2", "./tests/data/fold/stacks.txt", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn child process");
+    let input = fs::read("./tests/data/fold/more-stacks.txt").unwrap();
+    child.stdin.take().unwrap().write_all(&input).unwrap();
+    let output = child.wait_with_output().expect("failed to read stdout");
+    assert!(output.status.success());
+    let expected = fs::read_to_string("./tests/data/fold/results/merged.txt").unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn fold_should_reject_invalid_scale() {
+    for scale in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+        let mut options = Options::default();
+        options.scale = Some(scale);
+        let error = fold::from_reader(&options, "main;a 1\n".as_bytes(), io::sink()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "scale {}", scale);
+    }
+}
+
+#[test]
+fn fold_cli_should_reject_invalid_scale() {
+    for scale in ["0", "-1", "NaN", "inf"] {
+        let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-fold"))
+            .args(["--scale", scale])
+            .arg("./tests/data/fold/stacks.txt")
+            .output()
+            .expect("failed to execute process");
+        assert!(!output.status.success(), "--scale {} was accepted", scale);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("Expected a positive number"),
+            "unexpected error for --scale {}: {}",
+            scale,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn merge_should_weight_and_tag_profiles() {
+    let mut a = Input::default();
+    a.weight = 2.0;
+    a.tag = Some("a".to_string());
+    let mut b = Input::default();
+    b.weight = 0.5;
+    b.tag = Some("b".to_string());
+
+    let mut result = Vec::new();
+    merge::from_files(
+        &merge::Options::default(),
+        &[
+            ("./tests/data/fold/stacks.txt", a),
+            ("./tests/data/fold/more-stacks.txt", b),
+        ],
+        &mut result,
+    )
+    .unwrap();
+    let expected = fs::read_to_string("./tests/data/fold/results/weighted.txt").unwrap();
+    assert_eq!(String::from_utf8(result).unwrap(), expected);
+}
+
+#[test]
+fn merge_folded_cli() {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-merge-folded"))
+        .args(["--normalize", "--tag-files"])
+        .arg("./tests/data/fold/stacks.txt")
+        .arg("./tests/data/fold/more-stacks.txt")
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    let expected = fs::read_to_string("./tests/data/fold/results/normalized.txt").unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn merge_folded_cli_should_tag_stdin_as_stdin() {
+    let mut child = Command::new(assert_cmd::cargo::cargo_bin!("inferno-merge-folded"))
+        .arg("--tag-files")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn child process");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"main;foo 3\n")
+        .unwrap();
+    let output = child.wait_with_output().expect("failed to read stdout");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "stdin;main;foo 3\n"
+    );
+}
+
+#[test]
+fn merge_folded_cli_should_reject_mismatched_weights() {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-merge-folded"))
+        .args(["--weight", "2"])
+        .arg("./tests/data/fold/stacks.txt")
+        .arg("./tests/data/fold/more-stacks.txt")
+        .output()
+        .expect("failed to execute process");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn merge_folded_cli_should_reject_invalid_weights() {
+    for weight in ["0", "-1", "NaN", "inf"] {
+        let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-merge-folded"))
+            .args(["--weight", weight])
+            .arg("./tests/data/fold/stacks.txt")
+            .output()
+            .expect("failed to execute process");
+        assert!(!output.status.success(), "--weight {} was accepted", weight);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("--weight must be a positive number"),
+            "unexpected error for --weight {}: {}",
+            weight,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn merge_folded_cli_should_reject_tags_with_semicolons() {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-merge-folded"))
+        .args(["--tag", "host;a"])
+        .arg("./tests/data/fold/stacks.txt")
+        .output()
+        .expect("failed to execute process");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Tags must not contain `;`"),
+        "unexpected error: {}",
+        stderr
+    );
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "gzip")]
+
+use std::fs;
+use std::io::Read;
+use std::process::Command;
+
+use flate2::read::GzDecoder;
+use inferno::pprof::{self, Options};
+use pretty_assertions::assert_eq;
+
+fn gunzip(bytes: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut decoded).unwrap();
+    decoded
+}
+
+#[test]
+fn pprof_should_write_profile() {
+    let mut result = Vec::new();
+    pprof::from_files(
+        &Options::default(),
+        &["./tests/data/pprof/stacks.txt"],
+        &mut result,
+    )
+    .unwrap();
+    let expected = fs::read("./tests/data/pprof/results/stacks.pb").unwrap();
+    assert_eq!(gunzip(&result), expected);
+}
+
+#[test]
+fn pprof_should_write_same_profile_from_stacks() {
+    let stacks = inferno::collapse::FoldedStacks::from_reader(
+        fs::read("./tests/data/pprof/stacks.txt")
+            .unwrap()
+            .as_slice(),
+    )
+    .unwrap();
+    let mut result = Vec::new();
+    pprof::from_stacks(&Options::default(), &stacks, &mut result).unwrap();
+    let expected = fs::read("./tests/data/pprof/results/stacks.pb").unwrap();
+    assert_eq!(gunzip(&result), expected);
+}
+
+#[test]
+fn pprof_cli_differential() {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-pprof"))
+        .args(["--sample-type", "cpu", "--unit", "nanoseconds"])
+        .arg("./tests/data/pprof/diff.txt")
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    let expected = fs::read("./tests/data/pprof/results/diff.pb").unwrap();
+    assert_eq!(gunzip(&output.stdout), expected);
+}
+
+#[test]
+fn pprof_strict_should_reject_empty_input() {
+    let mut options = Options::default();
+    options.strict = true;
+    let mut result = Vec::new();
+    let error = pprof::from_reader(&options, "# nothing\n\n".as_bytes(), &mut result).unwrap_err();
+    assert!(matches!(
+        inferno::Error::from_io(error),
+        inferno::Error::EmptyInput
+    ));
+    assert!(result.is_empty());
+
+    pprof::from_reader(&Options::default(), "".as_bytes(), &mut result).unwrap();
+    assert!(!result.is_empty());
+}
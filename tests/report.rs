@@ -0,0 +1,82 @@
+use std::fs;
+use std::io::BufReader;
+use std::process::Command;
+
+use inferno::collapse::FoldedStacks;
+use inferno::report::{self, Format, Options, SortBy};
+use pretty_assertions::assert_eq;
+
+fn expected(name: &str) -> String {
+    fs::read_to_string(format!("./tests/data/report/results/{}", name)).unwrap()
+}
+
+#[test]
+fn report_table() {
+    let mut result = Vec::new();
+    report::from_files(
+        &Options::default(),
+        &["./tests/data/report/stacks.txt"],
+        &mut result,
+    )
+    .unwrap();
+    assert_eq!(String::from_utf8(result).unwrap(), expected("stacks.txt"));
+}
+
+#[test]
+fn report_csv_by_total_from_stacks() {
+    let mut opt = Options::default();
+    opt.format = Format::Csv;
+    opt.sort = SortBy::Total;
+    let file = fs::File::open("./tests/data/report/stacks.txt").unwrap();
+    let stacks = FoldedStacks::from_reader(BufReader::new(file)).unwrap();
+    let mut result = Vec::new();
+    report::from_stacks(&opt, &stacks, &mut result).unwrap();
+    assert_eq!(
+        String::from_utf8(result).unwrap(),
+        expected("stacks-by-total.csv")
+    );
+}
+
+#[test]
+fn report_json_against_baseline() {
+    let mut opt = Options::default();
+    opt.format = Format::Json;
+    opt.top = Some(4);
+    let mut result = Vec::new();
+    report::diff_files(
+        &opt,
+        &["./tests/data/report/baseline.txt"],
+        &["./tests/data/report/stacks.txt"],
+        &mut result,
+    )
+    .unwrap();
+    assert_eq!(String::from_utf8(result).unwrap(), expected("diff.json"));
+}
+
+#[test]
+fn report_strict() {
+    let mut opt = Options::default();
+    opt.strict = true;
+    let error =
+        report::from_reader(&opt, "main;f 1\nnot a line\n".as_bytes(), Vec::new()).unwrap_err();
+    let error = inferno::Error::from_io(error);
+    assert!(
+        matches!(error, inferno::Error::Parse { line: 2, .. }),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn report_cli_differential() {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-report"))
+        .args(["--sort", "total", "--top", "5"])
+        .arg("./tests/data/flamegraph/differential/perf-cycles-instructions-01-collapsed-all-diff.txt")
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        expected("diff-by-total.txt")
+    );
+}
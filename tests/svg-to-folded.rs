@@ -0,0 +1,98 @@
+mod common;
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::process::{Command, Stdio};
+
+use inferno::collapse::svg::{Folder, Options};
+use inferno::collapse::Collapse;
+use inferno::Error;
+
+fn test_svg_to_folded(test_file: &str, expected_file: &str, options: Options) -> io::Result<()> {
+    common::test_collapse(Folder::from(options), test_file, expected_file, false)
+}
+
+#[test]
+fn svg_to_folded_narrow_blocks() {
+    let test_file = "./tests/data/flamegraph/narrow-blocks/narrow-blocks.svg";
+    let result_file = "./tests/data/svg-to-folded/results/narrow-blocks.txt";
+    test_svg_to_folded(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn svg_to_folded_differential() {
+    let test_file = "./tests/data/flamegraph/differential/diff.svg";
+    let result_file = "./tests/data/svg-to-folded/results/diff.txt";
+    test_svg_to_folded(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn svg_to_folded_flame_chart() {
+    let test_file = "./tests/data/flamegraph/flamechart/flame.svg";
+    let result_file = "./tests/data/svg-to-folded/results/flame.txt";
+    test_svg_to_folded(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn svg_to_folded_inverted() {
+    let test_file = "./tests/data/flamegraph/inverted/inverted.svg";
+    let result_file = "./tests/data/svg-to-folded/results/inverted.txt";
+    test_svg_to_folded(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn svg_to_folded_custom_titles() {
+    let test_file = "./tests/data/flamegraph/nameattr/nameattr.svg";
+    let result_file = "./tests/data/svg-to-folded/results/nameattr.txt";
+    test_svg_to_folded(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn svg_to_folded_strict() {
+    let mut options = Options::default();
+    options.strict = true;
+
+    let mut folder = Folder::from(options.clone());
+    let path = Some("./tests/data/flamegraph/nameattr/nameattr.svg");
+    let error = folder.collapse_file(path, io::sink()).unwrap_err();
+    match Error::from_io(error) {
+        Error::Parse { line, text, .. } => {
+            // The line of the `<a>` that the frame with the custom title starts with.
+            assert_eq!(line, 51);
+            assert_eq!(text, "foo");
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+
+    let mut folder = Folder::from(options);
+    let path = Some("./tests/data/flamegraph/empty/empty.svg");
+    let error = folder.collapse_file(path, io::sink()).unwrap_err();
+    assert!(matches!(Error::from_io(error), Error::EmptyInput));
+}
+
+#[test]
+fn svg_to_folded_cli() {
+    let input_file = "./tests/data/flamegraph/differential/diff.svg";
+    let expected_file = "./tests/data/svg-to-folded/results/diff.txt";
+
+    // Test with file passed in
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("inferno-svg-to-folded"))
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with STDIN
+    let mut child = Command::new(assert_cmd::cargo::cargo_bin!("inferno-svg-to-folded"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    let mut input = BufReader::new(File::open(input_file).unwrap());
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    io::copy(&mut input, stdin).unwrap();
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}